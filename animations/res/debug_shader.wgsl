@@ -0,0 +1,42 @@
+struct Camera {
+    up: vec3<f32>,
+    right: vec3<f32>,
+    position: vec3<f32>,
+    direction: vec3<f32>,
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexInput{
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput{
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    // squash the depth right in front of the near plane so the gizmos are drawn over the model,
+    // but still sorted against each other
+    out.clip_position.z *= 0.001;
+    out.world_position = model.position;
+    out.color = model.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
+    // flat shaded from the screen space derivatives, the gizmos have no normals
+    let normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    let light_dir = normalize(vec3<f32>(1.0));
+    let light = 0.5 + 0.5 * abs(dot(normal, light_dir));
+    return vec4<f32>(in.color * light, 1.0);
+}
@@ -13,15 +13,78 @@ struct Joints {
     j: array<mat4x4<f32>>
 };
 
+// joint j of instance i is at i * stride + offset + j, crowds keep the palettes of all instances in one buffer
+struct JointInfo {
+    offset: u32,
+    stride: u32,
+    // 1 draws the weights of highlighted_joint instead of the materials
+    heat_map: u32,
+    highlighted_joint: u32,
+};
+
+// a KHR_lights_punctual light at its node, shared by every skin since all bind groups are taken
+struct Light {
+    position: vec3<f32>,
+    // 0 directional, 1 point, 2 spot
+    kind: u32,
+    direction: vec3<f32>,
+    // 0 when infinite
+    range: f32,
+    // multiplied with the intensity
+    color: vec3<f32>,
+    inner_cone_cos: f32,
+    outer_cone_cos: f32,
+};
+
 @group(1) @binding(0)
 var<storage, read_write> joints: Joints;
+@group(1) @binding(1)
+var<uniform> joint_info: JointInfo;
+@group(1) @binding(2)
+var<storage, read_write> lights: array<Light>;
+
+struct Material {
+    base_color_factor: vec4<f32>,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    alpha_cutoff: f32,
+    // 0 opaque, 1 mask, 2 blend
+    alpha_mode: u32,
+};
+
+@group(2) @binding(0)
+var<uniform> material: Material;
+@group(2) @binding(1)
+var base_color_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var base_color_sampler: sampler;
+@group(2) @binding(3)
+var normal_texture: texture_2d<f32>;
+@group(2) @binding(4)
+var normal_sampler: sampler;
+
+struct MorphInfo {
+    target_count: u32,
+    vertex_count: u32,
+};
+
+@group(3) @binding(0)
+var<uniform> morph_info: MorphInfo;
+// per target all position deltas followed by all normal deltas
+@group(3) @binding(1)
+var<storage, read_write> morph_deltas: array<vec4<f32>>;
+@group(3) @binding(2)
+var<storage, read_write> morph_weights: array<f32>;
 
 struct VertexInput{
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
-    @location(3) weights: u32,
-    @location(4) joints: u32,
+    @location(3) weights: vec4<f32>,
+    @location(4) joints_01: u32,
+    @location(9) joints_23: u32,
+    @location(10) tangent: vec4<f32>,
 };
 
 struct InstanceInput{
@@ -36,16 +99,18 @@ struct VertexOutput{
     @location(0) uv: vec2<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) clip_position_raw: vec4<f32>,
-    @location(4) @interpolate(flat) random_value: u32,
-    @location(5) weight: f32,
-    @location(6) joint: u32
+    @location(3) world_position: vec3<f32>,
+    @location(4) tangent: vec4<f32>,
+    @location(5) highlight_weight: f32,
+    @location(6) @interpolate(flat) heat_map: u32
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
-    @builtin(vertex_index) index: u32
+    @builtin(vertex_index) index: u32,
+    @builtin(instance_index) instance_index: u32
 ) -> VertexOutput{
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
@@ -54,14 +119,34 @@ fn vs_main(
         instance.model_matrix_3,
     );
 
+    // morph targets are applied in bind space, before skinning
+    var morphed_position = model.position;
+    var morphed_normal = model.normal;
+    for (var t = 0u; t < morph_info.target_count; t += 1u){
+        let weight = morph_weights[t];
+        if weight == 0.0f {
+            continue;
+        }
+        let offset = t * morph_info.vertex_count * 2u;
+        morphed_position += weight * morph_deltas[offset + index].xyz;
+        morphed_normal += weight * morph_deltas[offset + morph_info.vertex_count + index].xyz;
+    }
+
     var position = vec4<f32>(0.0f);
     var normal = vec4<f32>(0.0f);
+    var tangent = vec4<f32>(0.0f);
+    if dot(model.weights, vec4<f32>(1.0f)) == 0.0f {
+        // unskinned mesh, placed by its node
+        position = model_matrix * vec4<f32>(morphed_position, 1.0f);
+        normal = model_matrix * vec4<f32>(morphed_normal, 0.0f);
+        tangent = model_matrix * vec4<f32>(model.tangent.xyz, 0.0f);
+    }
     for (var i = 0u; i < 4u; i += 1u){
-        let joint = (model.joints >> (i * 8u)) & 0xffu;
-        let weight_u32 = (model.weights >> (i * 8u)) & 0xffu;
-        let weight = f32(weight_u32) / 255.0f;
-        position = position + weight * (joints.j[joint] * vec4<f32>(model.position, 1.0f));
-        normal = normal + weight * (joints.j[joint] * vec4<f32>(model.normal, 0.0f));
+        let joint = instance_index * joint_info.stride + joint_info.offset + unpack_joint(model, i);
+        let weight = model.weights[i];
+        position = position + weight * (joints.j[joint] * vec4<f32>(morphed_position, 1.0f));
+        normal = normal + weight * (joints.j[joint] * vec4<f32>(morphed_normal, 0.0f));
+        tangent = tangent + weight * (joints.j[joint] * vec4<f32>(model.tangent.xyz, 0.0f));
     }
 
     let world_position = vec4<f32>(position.xyz, 1.0);
@@ -71,86 +156,121 @@ fn vs_main(
     out.clip_position = out.clip_position_raw;
     out.uv = model.uv;
     out.normal = normalize(normal).xyz;
-    out.random_value = pcg_hash(index);
-    out.weight = f32(model.weights & 0xffu);
-    out.joint = model.joints & 0xffu;
+    out.world_position = world_position.xyz;
+    // w is 0 when the mesh has no tangents, the fragment shader derives them from the uvs then
+    out.tangent = vec4<f32>(tangent.xyz, model.tangent.w);
+    out.highlight_weight = 0.0;
+    for (var i = 0u; i < 4u; i += 1u){
+        if unpack_joint(model, i) == joint_info.highlighted_joint {
+            out.highlight_weight += model.weights[i];
+        }
+    }
+    out.heat_map = joint_info.heat_map;
     return out;
 }
 
+fn unpack_joint(model: VertexInput, i: u32) -> u32 {
+    let packed = select(model.joints_23, model.joints_01, i < 2u);
+    return (packed >> ((i % 2u) * 16u)) & 0xffffu;
+}
+
 fn distance_squared(a: vec3<f32>, b: vec3<f32>) -> f32{
     let d = a - b;
     return a.x * d.x + d.y * d.y + d.z + d.z;
 }
 
-const COLORS: array<vec3<f32>, 10> = array<vec3<f32>, 10>(
-    vec3<f32>(0.984, 0.592, 0.794),
-    vec3<f32>(0.727, 0.941, 0.557),
-    vec3<f32>(0.692, 0.778, 0.945),
-    vec3<f32>(0.957, 0.771, 0.502),
-    vec3<f32>(0.973, 0.616, 0.775),
-    vec3<f32>(0.790, 0.941, 0.588),
-    vec3<f32>(0.782, 0.696, 0.957),
-    vec3<f32>(0.996, 0.837, 0.498),
-    vec3<f32>(0.653, 0.751, 0.941),
-    vec3<f32>(0.786, 0.696, 0.649) 
-);
-
-fn pcg_hash(s: u32) -> u32
-{
-    var state = s * 747796405u + 2891336453u;
-    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
-    state = (word >> 22u) ^ word; 
-    return state;
+// tangent frame from screen space derivatives, for meshes without authored tangents
+fn cotangent_frame(normal: vec3<f32>, position: vec3<f32>, uv: vec2<f32>) -> mat3x3<f32> {
+    let dp1 = dpdx(position);
+    let dp2 = dpdy(position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+
+    let dp2perp = cross(dp2, normal);
+    let dp1perp = cross(normal, dp1);
+    let t = dp2perp * duv1.x + dp1perp * duv2.x;
+    let b = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    let invmax = inverseSqrt(max(dot(t, t), dot(b, b)) + 1e-12);
+    return mat3x3<f32>(t * invmax, b * invmax, normal);
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>{
-    let light_dir = vec3<f32>(-1.0);
+    let base_color = textureSample(base_color_texture, base_color_sampler, in.uv) * material.base_color_factor;
+    if material.alpha_mode == 1u && base_color.a < material.alpha_cutoff {
+        discard;
+    }
 
-    var warm = vec3<f32>(1.0);
-    switch(in.random_value % 10u){
-        case 0u: {
-            warm = COLORS[0];
-        }
-        case 1u: {
-            warm = COLORS[1];
-        }
-        case 2u: {
-            warm = COLORS[2];
-        }
-        case 3u: {
-            warm = COLORS[3];
-        }
-        case 4u: {
-            warm = COLORS[4];
-        }
-        case 5u: {
-            warm = COLORS[5];
-        }
-        case 6u: {
-            warm = COLORS[6];
-        }
-        case 7u: {
-            warm = COLORS[7];
-        }
-        case 8u: {
-            warm = COLORS[8];
-        }
-        case 9u: {
-            warm = COLORS[9];
+    let geometric_normal = normalize(in.normal);
+    var tbn = cotangent_frame(geometric_normal, in.world_position, in.uv);
+    if in.tangent.w != 0.0 {
+        let t = normalize(in.tangent.xyz - geometric_normal * dot(geometric_normal, in.tangent.xyz));
+        tbn = mat3x3<f32>(t, cross(geometric_normal, t) * in.tangent.w, geometric_normal);
+    }
+    var tangent_normal = textureSample(normal_texture, normal_sampler, in.uv).xyz * 2.0 - 1.0;
+    tangent_normal = vec3<f32>(tangent_normal.xy * material.normal_scale, tangent_normal.z);
+    let normal = normalize(tbn * tangent_normal);
+
+    let view_dir = normalize(camera.position - in.world_position);
+    // rough surfaces spread the highlight until it's as wide as the diffuse term
+    let shininess = mix(256.0, 4.0, material.roughness_factor);
+    let specular_color = mix(vec3<f32>(0.04), base_color.rgb, material.metallic_factor);
+    let diffuse_color = base_color.rgb * (1.0 - material.metallic_factor);
+
+    let ambient = 0.2;
+    var color = base_color.rgb * ambient;
+    // how lit the surface is in total, for the heat map
+    var diffuse_sum = 0.0;
+    for (var i = 0u; i < arrayLength(&lights); i += 1u){
+        let light = lights[i];
+        var light_dir = -light.direction;
+        var radiance = light.color;
+        if light.kind != 0u {
+            let to_light = light.position - in.world_position;
+            light_dir = normalize(to_light);
+            radiance *= attenuation(length(to_light), light.range);
         }
-        default: {
-            warm = vec3<f32>(1.0);
+        if light.kind == 2u {
+            let cone = smoothstep(light.outer_cone_cos, light.inner_cone_cos, dot(light.direction, -light_dir));
+            radiance *= cone * cone;
         }
+
+        let half_dir = normalize(light_dir + view_dir);
+        let diffuse = max(dot(normal, light_dir), 0.0);
+        let specular = mix(pow(max(dot(normal, half_dir), 0.0), shininess), diffuse, material.roughness_factor);
+        color += (diffuse_color * diffuse + specular_color * specular) * radiance;
+        diffuse_sum += diffuse * dot(radiance, vec3<f32>(1.0 / 3.0));
     }
 
-    warm = vec3<f32>(1.0, 0.8, 0.5);
-    let cool = vec3<f32>(0.2, 0.5, 1.0);
+    var alpha = 1.0;
+    if material.alpha_mode == 2u {
+        alpha = base_color.a;
+    }
 
-    let d = dot(light_dir, in.normal) * 0.5 + 0.5;
-    let color = warm * (1.0 - d) + cool * d; 
+    if in.heat_map == 1u {
+        return vec4<f32>(heat_color(in.highlight_weight) * (ambient + diffuse_sum), 1.0);
+    }
 
-    //return vec4<f32>(in.weight / 255.0f, 0.0, 0.0, 1.0);
- //   return vec4<f32>(f32(in.joint) / 65.0f, 0.0, 0.0, 1.0);
-    return vec4<f32>(color, 1.0);
+    return vec4<f32>(color, alpha);
+}
+
+// inverse square falloff that reaches 0 at `range`, as KHR_lights_punctual recommends
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let falloff = 1.0 / max(distance * distance, 0.0001);
+    if range <= 0.0 {
+        return falloff;
+    }
+    let window = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+    return window * window * falloff;
+}
+
+// blue for no influence through green to red for full influence
+fn heat_color(weight: f32) -> vec3<f32> {
+    let w = clamp(weight, 0.0, 1.0);
+    return vec3<f32>(
+        clamp(w * 2.0 - 1.0, 0.0, 1.0),
+        1.0 - abs(w * 2.0 - 1.0),
+        clamp(1.0 - w * 2.0, 0.0, 1.0),
+    );
 }
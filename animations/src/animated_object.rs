@@ -1,396 +1,486 @@
-use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use jandering_engine::{
     bind_group::{
         BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutDescriptorEntry,
         BindGroupLayoutEntry,
     },
-    object::Renderable,
     renderer::{BindGroupHandle, BufferHandle, Janderer, Renderer},
     shader::{BufferLayout, BufferLayoutEntry, BufferLayoutEntryDataType, BufferLayoutStepMode},
-    types::{Mat4, Qua, Vec2, Vec3},
-    utils::load_binary,
+    types::Mat4,
+};
+
+use crate::{
+    animation::Animation,
+    animator::Animator,
+    bake::{self, BakedAnimation, BakedImage, BakedMesh, BakedModel},
+    bounds::{Aabb, PoseBounds},
+    error::{BakeError, GltfLoadError},
+    events::TriggeredEvent,
+    ik::{self, IkConstraint},
+    layer::AnimationLayer,
+    material::{
+        load_textures, read_images, AlphaMode, FallbackTextures, Image, Material,
+        MaterialProperties, TextureInfo,
+    },
+    mesh::{Mesh, Primitive},
+    retarget::{retarget, BoneMap},
+    scene::Scene,
+    skeleton::{NodeType, Pose, Skeleton},
+    socket::Socket,
 };
 
 #[derive(Debug)]
 pub struct AnimatedObject {
     pub meshes: Vec<Mesh>,
-    pub nodes: Vec<Node>,
-
-    pub animations: Vec<Animation>,
-    pub current_animation: usize,
-    animation_start_time: std::time::Instant,
-
-    joints: Vec<usize>,
-    joint_buffer: BufferHandle,
-    inverse_bind_matrices: Vec<Mat4>,
-    pub joint_data_bind_group: BindGroupHandle,
-
-    parentless_nodes: HashSet<usize>,
-}
-
-#[derive(Debug)]
-pub enum Keyframes {
-    Rotations(Vec<Qua>),
-    Translations(Vec<Vec3>),
-    Other,
+    pub materials: Vec<Material>,
+    // what the textures of `materials` were made from, kept on the cpu for `to_baked`
+    pub images: Vec<Image>,
+    pub textures: Vec<TextureInfo>,
+    pub skeleton: Skeleton,
+    pub pose: Pose,
+
+    // clips, playback, blending, layers and root motion
+    pub animator: Animator,
+    // solved in order after sampling, before the joint matrices are written
+    pub ik_constraints: Vec<IkConstraint>,
+
+    // around the current pose, updated by `animate`
+    pub bounds: Aabb,
+    // around every pose of each animation, measured at load
+    pub clip_bounds: Vec<Aabb>,
+    pose_bounds: PoseBounds,
+
+    // authored cameras and lights, on nodes of `skeleton`
+    pub scene: Scene,
+    // `scene.light_data()` of the current pose, bound next to the joints of every skin
+    pub light_buffer: BufferHandle,
+
+    // one per skin, or a single identity one if there are no skins so unskinned meshes have something to bind
+    skins: Vec<SkinRenderData>,
 }
 
-#[derive(Debug)]
-pub struct Track {
-    keyframes: Keyframes,
-    target: usize,
-    timestamps: Vec<f32>,
+// where the joint matrices of a skin start in the joint buffer bound with it, the shader reads
+// joint `j` of instance `i` at `i * stride + offset + j`
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+pub struct JointInfo {
+    pub offset: u32,
+    // joints per instance, 0 when only one instance is drawn
+    pub stride: u32,
+    // 1 draws the weights of `highlighted_joint` as a heat map instead of the materials
+    pub heat_map: u32,
+    pub highlighted_joint: u32,
 }
 
-#[derive(Debug)]
-pub struct Animation {
-    #[allow(dead_code)]
-    pub name: String,
-    pub length: f32,
-    pub tracks: Vec<Track>,
+impl JointInfo {
+    pub fn new(offset: usize, stride: usize) -> Self {
+        Self {
+            offset: offset as u32,
+            stride: stride as u32,
+            heat_map: 0,
+            highlighted_joint: u32::MAX,
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct AnimatedObjectRenderData {
-    pub vertex_buffer: BufferHandle,
-    pub index_buffer: BufferHandle,
-    pub instance_buffer: BufferHandle,
+struct SkinRenderData {
+    joint_buffer: BufferHandle,
+    joint_info_buffer: BufferHandle,
+    joint_data_bind_group: BindGroupHandle,
 }
 
+// bind groups to bind before rendering `primitive`
 #[derive(Debug)]
-pub struct Mesh {
-    #[allow(dead_code)]
-    pub vertices: Vec<AnimatedVertex>,
-    pub indices: Vec<u32>,
-    pub render_data: AnimatedObjectRenderData,
+pub struct Draw<'a> {
+    // group 1
+    pub joint_data_bind_group: BindGroupHandle,
+    // group 2
+    pub material_bind_group: BindGroupHandle,
+    // group 3
+    pub morph_bind_group: BindGroupHandle,
+    pub primitive: &'a Primitive,
 }
 
-#[derive(Debug)]
-pub enum NodeType {
-    Mesh { mesh: usize },
-    Generic,
-}
+impl AnimatedObject {
+    // `path` is a .gltf or .glb file, see `resolve_path`
+    pub fn from_gltf(renderer: &mut Renderer, path: &str) -> Result<Self, GltfLoadError> {
+        let path = resolve_path(path);
+        let (gltf, buffers) = load_gltf(&path)?;
+
+        let skeleton = Skeleton::from_gltf(&gltf, &buffers)?;
+
+        let images = read_images(&gltf, &buffers, uri_base(&path))?;
+        let texture_infos = TextureInfo::from_gltf(&gltf);
+        let textures = load_textures(renderer, &texture_infos, &images);
+        let fallback_textures = FallbackTextures::new(renderer);
+        let mut materials = gltf
+            .materials()
+            .map(|material| Material::new(renderer, material.into(), &textures, fallback_textures))
+            .collect::<Vec<_>>();
+        // primitives without a material use the gltf default material, appended after the others
+        let default_material = materials.len();
+        materials.push(Material::new(
+            renderer,
+            MaterialProperties::default(),
+            &textures,
+            fallback_textures,
+        ));
+
+        let mut meshes = Vec::new();
+        for mesh in gltf.meshes() {
+            // a mesh can be instanced by multiple nodes, its joint indices have to be valid for all of their skins
+            let joint_count = gltf
+                .nodes()
+                .filter(|node| node.mesh().is_some_and(|m| m.index() == mesh.index()))
+                .filter_map(|node| node.skin())
+                .map(|skin| skin.joints().len())
+                .min();
+
+            meshes.push(Mesh::from_gltf(
+                renderer,
+                mesh,
+                &buffers,
+                joint_count,
+                default_material,
+            )?);
+        }
 
-#[derive(Debug)]
-pub struct Node {
-    #[allow(dead_code)]
-    name: String,
-    node_type: NodeType,
-    transform: Mat4,
-    world_transform: Mat4,
-    children: Vec<usize>,
-}
+        let animations = gltf
+            .animations()
+            .map(|animation| Animation::from_gltf(animation, &buffers))
+            .collect::<Result<Vec<_>, _>>()?;
 
-impl AnimatedObject {
-    pub async fn from_gltf(renderer: &mut Renderer, path: &str) -> Self {
-        let gltf = gltf::Gltf::from_slice(
-            &load_binary(jandering_engine::utils::FilePath::FileName(unsafe{std::mem::transmute::<&str, &'static str>(path)}))
-                .await
-                .unwrap(),
+        Ok(Self::new(
+            renderer,
+            skeleton,
+            meshes,
+            materials,
+            animations,
+            None,
+            Scene::from_gltf(&gltf),
         )
-        .unwrap();
-
-        let mut buffers = Vec::new();
-        for buffer in gltf.buffers() {
-            match buffer.source() {
-                gltf::buffer::Source::Uri(filename) => {
-                    buffers.push(
-                        load_binary(jandering_engine::utils::FilePath::FileName(unsafe {
-                            std::mem::transmute::<&str, &'static str>(filename)
-                        }))
-                        .await
-                        .unwrap(),
-                    );
-                }
-                gltf::buffer::Source::Bin => {
-                    buffers.push(gltf.blob.as_deref().unwrap().into());
-                }
-            }
-        }
+        .with_textures(images, texture_infos))
+    }
 
-        let meshes = gltf
-            .meshes()
-            .map(|mesh| {
-                let primitives = mesh.primitives();
-
-                let mut vertices = Vec::new();
-                let mut indices = Vec::new();
-
-                primitives.for_each(|primitive| {
-                    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-
-                    if let Some(positions) = reader.read_positions() {
-                        for position in positions {
-                            vertices.push(AnimatedVertex {
-                                position: Vec3::from_array(position),
-                                ..Default::default()
-                            })
-                        }
-                    }
-
-                    if let Some(normals) = reader.read_normals() {
-                        for (i, normal) in normals.into_iter().enumerate() {
-                            vertices[i].normal = Vec3::from_array(normal);
-                        }
-                    }
-
-                    if let Some(uvs) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
-                        for (i, uv) in uvs.into_iter().enumerate() {
-                            vertices[i].uv = uv.into();
-                        }
-                    }
-
-                    if let Some(weights) = reader.read_weights(0) {
-                        for (i, weight) in weights.into_u8().into_iter().enumerate() {
-                            vertices[i].weights = unsafe {
-                                std::mem::transmute::<[u8; 4], u32>([
-                                    weight[0], weight[1], weight[2], weight[3],
-                                ])
-                            }
-                        }
-                    }
-
-                    if let Some(joints) = reader.read_joints(0) {
-                        for (i, joint) in joints.into_u16().into_iter().enumerate() {
-                            vertices[i].joints = unsafe {
-                                std::mem::transmute::<[u8; 4], u32>([
-                                    joint[0] as u8,
-                                    joint[1] as u8,
-                                    joint[2] as u8,
-                                    joint[3] as u8,
-                                ])
-                            };
-                        }
-                    }
-
-                    if let Some(indices_raw) = reader.read_indices() {
-                        indices.append(&mut indices_raw.into_u32().collect::<Vec<u32>>());
-                    }
-                });
+    // a model written by `bake::bake`, loads without parsing the gltf again
+    pub fn from_bake(renderer: &mut Renderer, path: &str) -> Result<Self, BakeError> {
+        let bytes = load_file(&resolve_path(path))?;
+        bake::with_aligned(&bytes, |bytes| {
+            Ok(Self::from_baked(renderer, bake::parse(bytes)?))
+        })
+    }
 
-                let render_data = AnimatedObjectRenderData {
-                    vertex_buffer: renderer.create_vertex_buffer(bytemuck::cast_slice(&vertices)),
-                    instance_buffer: renderer
-                        .create_vertex_buffer(bytemuck::cast_slice(&[Mat4::IDENTITY])),
-                    index_buffer: renderer.create_index_buffer(bytemuck::cast_slice(&indices)),
-                };
-                Mesh {
-                    vertices,
-                    indices,
-                    render_data,
-                }
-            })
+    // `parse` doesn't copy the arrays of `baked`, but the object keeps its own copy of every one of
+    // them for sampling, bounds and baking again. They're copied once, straight from the parsed bytes
+    pub fn from_baked(renderer: &mut Renderer, baked: BakedModel) -> Self {
+        let images = baked
+            .images
+            .iter()
+            .map(|image| image.to_image())
             .collect::<Vec<_>>();
+        let textures = load_textures(renderer, &baked.textures, &images);
+        let fallback_textures = FallbackTextures::new(renderer);
+        let materials = baked
+            .materials
+            .into_iter()
+            .map(|properties| Material::new(renderer, properties, &textures, fallback_textures))
+            .collect();
 
-        let animations = gltf
-            .animations()
-            .map(|animation| {
-                let mut length = 0.0f32;
-                let tracks = animation
-                    .channels()
-                    .map(|channel| {
-                        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
-                        let timestamps = if let Some(gltf::accessor::Iter::Standard(times)) =
-                            reader.read_inputs()
-                        {
-                            times.collect::<Vec<_>>()
-                        } else {
-                            panic!("unsupported {:?}", reader.read_inputs());
-                        };
-
-                        length = length.max(
-                            *timestamps
-                                .iter()
-                                .max_by(|a, b| a.partial_cmp(b).unwrap())
-                                .unwrap_or(&0.0),
-                        );
-
-                        let keyframes = match reader.read_outputs().unwrap() {
-                            gltf::animation::util::ReadOutputs::Rotations(rotations) => {
-                                Keyframes::Rotations(
-                                    rotations
-                                        .into_f32()
-                                        .map(|rotation| {
-                                            Qua::from_xyzw(
-                                                rotation[0],
-                                                rotation[1],
-                                                rotation[2],
-                                                rotation[3],
-                                            )
-                                        })
-                                        .collect::<Vec<_>>(),
-                                )
-                            }
-                            gltf::animation::util::ReadOutputs::Translations(translations) => {
-                                Keyframes::Translations(
-                                    translations.map(|translation| translation.into()).collect(),
-                                )
-                            }
-                            _ => Keyframes::Other,
-                        };
-
-                        Track {
-                            keyframes,
-                            timestamps,
-                            target: channel.target().node().index(),
-                        }
-                    })
-                    .collect();
-
-                Animation {
-                    name: animation.name().unwrap_or("Unnamed").to_string(),
-                    length,
-                    tracks,
+        let meshes = baked
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let mut result = Mesh::new(
+                    renderer,
+                    mesh.name.to_string(),
+                    mesh.morph_target_count,
+                    &[],
+                );
+                for primitive in mesh.primitives.iter() {
+                    result.add_primitive(
+                        renderer,
+                        primitive.vertices.to_vec(),
+                        primitive.indices.to_vec(),
+                        primitive.to_morph_targets(),
+                        primitive.material,
+                    );
                 }
+                result
             })
             .collect();
 
-        let mut parentless_nodes = HashSet::new();
-        let nodes = gltf
-            .nodes()
-            .enumerate()
-            .map(|(i, node)| {
-                parentless_nodes.insert(i);
-
-                Node {
-                    name: node.name().unwrap_or("Unnamed").to_string(),
-                    node_type: if let Some(mesh) = node.mesh() {
-                        NodeType::Mesh { mesh: mesh.index() }
-                    } else {
-                        NodeType::Generic
-                    },
-                    transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
-                    world_transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
-                    children: node.children().map(|n| n.index()).collect(),
-                }
-            })
-            .collect::<Vec<_>>();
+        let animations = baked
+            .animations
+            .iter()
+            .map(|animation| animation.to_animation())
+            .collect();
 
-        for node in nodes.iter() {
-            for child in node.children.iter() {
-                parentless_nodes.remove(child);
-            }
+        Self::new(
+            renderer,
+            baked.skeleton,
+            meshes,
+            materials,
+            animations,
+            Some(baked.clip_bounds),
+            baked.scene,
+        )
+        .with_textures(images, baked.textures)
+    }
+
+    // borrows the arrays of the object to write them with `bake::bake`
+    pub fn to_baked(&self) -> BakedModel<'_> {
+        BakedModel {
+            skeleton: self.skeleton.clone(),
+            images: self.images.iter().map(BakedImage::from_image).collect(),
+            textures: self.textures.clone(),
+            materials: self
+                .materials
+                .iter()
+                .map(|material| material.properties.clone())
+                .collect(),
+            meshes: self.meshes.iter().map(BakedMesh::from_mesh).collect(),
+            animations: self
+                .animator
+                .animations
+                .iter()
+                .map(BakedAnimation::from_animation)
+                .collect(),
+            clip_bounds: self.clip_bounds.clone(),
+            scene: self.scene.clone(),
         }
+    }
 
-        let mut joints = Vec::new();
-        let mut inverse_bind_matrices = Vec::new();
-        for skin in gltf.skins() {
-            joints.append(&mut skin.joints().map(|n| n.index()).collect::<Vec<_>>());
+    // keep `images` and `textures` the materials were made with, so they're baked as well
+    pub fn with_textures(mut self, images: Vec<Image>, textures: Vec<TextureInfo>) -> Self {
+        self.images = images;
+        self.textures = textures;
+        self
+    }
 
-            let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
-            if let Some(inverse_matrices) = reader.read_inverse_bind_matrices() {
-                inverse_bind_matrices = inverse_matrices
-                    .map(|mat| Mat4::from_cols_array_2d(&mat))
-                    .collect();
-            }
+    // everything that's loaded is put together here. `clip_bounds` has one box per animation, they're
+    // measured by sampling the animations if they aren't known yet
+    pub fn new(
+        renderer: &mut Renderer,
+        skeleton: Skeleton,
+        meshes: Vec<Mesh>,
+        materials: Vec<Material>,
+        animations: Vec<Animation>,
+        clip_bounds: Option<Vec<Aabb>>,
+        scene: Scene,
+    ) -> Self {
+        let pose = skeleton.rest_pose();
+        let pose_bounds = PoseBounds::new(&skeleton, &meshes);
+        let bounds = pose_bounds.bounds(&pose);
+        let clip_bounds = clip_bounds.unwrap_or_else(|| {
+            animations
+                .iter()
+                .map(|animation| pose_bounds.clip_bounds(&skeleton, animation))
+                .collect()
+        });
 
-            break; // only one for now :D
-        }
+        let light_buffer =
+            renderer.create_storage_buffer(bytemuck::cast_slice(&scene.light_data(&pose)));
 
-        let joint_matrices = joints
-            .iter()
-            .map(|joint| nodes[*joint].transform)
+        let mut joint_matrices = (0..skeleton.skins.len())
+            .map(|skin| skeleton.joint_matrices(skin, &pose))
             .collect::<Vec<_>>();
-        let joint_buffer = renderer.create_storage_buffer(bytemuck::cast_slice(&joint_matrices));
-        let joint_data_bind_group = renderer.create_bind_group(BindGroupLayout {
-            entries: vec![BindGroupLayoutEntry::Data(joint_buffer)],
-        });
+        if joint_matrices.is_empty() {
+            joint_matrices.push(vec![Mat4::IDENTITY]);
+        }
+        let skins = joint_matrices
+            .iter()
+            .map(|joint_matrices| {
+                let joint_buffer =
+                    renderer.create_storage_buffer(bytemuck::cast_slice(joint_matrices));
+                let joint_info_buffer =
+                    renderer.create_uniform_buffer(bytemuck::cast_slice(&[JointInfo::new(0, 0)]));
+                let joint_data_bind_group = renderer.create_bind_group(BindGroupLayout {
+                    entries: vec![
+                        BindGroupLayoutEntry::Data(joint_buffer),
+                        BindGroupLayoutEntry::Data(joint_info_buffer),
+                        BindGroupLayoutEntry::Data(light_buffer),
+                    ],
+                });
+                SkinRenderData {
+                    joint_buffer,
+                    joint_info_buffer,
+                    joint_data_bind_group,
+                }
+            })
+            .collect();
 
         Self {
-            nodes,
             meshes,
-            animations,
-            joints,
-            joint_buffer,
-            joint_data_bind_group,
-            inverse_bind_matrices,
-            parentless_nodes,
-            animation_start_time: std::time::Instant::now(),
-            current_animation: 0,
+            materials,
+            images: Vec::new(),
+            textures: Vec::new(),
+            skeleton,
+            pose,
+            animator: Animator::new(animations),
+            skins,
+            ik_constraints: Vec::new(),
+            bounds,
+            clip_bounds,
+            pose_bounds,
+            scene,
+            light_buffer,
         }
     }
 
-    pub fn update(&mut self, renderer: &mut Renderer) {
-        // update current animation, by finding the current keyframe and lerping between it and the next one
-        if let Some(animation) = self.animations.get(self.current_animation) {
-            let mut time = self.animation_start_time.elapsed().as_secs_f32();
-            if time > animation.length {
-                time = 0.0;
-                self.animation_start_time = std::time::Instant::now();
-            }
+    // advance playback by `dt` seconds and upload the new pose, returns the events crossed on the way
+    pub fn update(&mut self, renderer: &mut Renderer, dt: f32) -> Vec<TriggeredEvent> {
+        let events = self.animate(dt);
+        self.upload(renderer);
+        events
+    }
 
-            for track in animation.tracks.iter() {
-                let Some((keyframe, timestamp)) = track
-                    .timestamps
-                    .iter()
-                    .enumerate()
-                    .rfind(|(_, e)| **e <= time)
-                else {
-                    continue;
-                };
-
-                let next_timestamp = *track.timestamps.get(keyframe + 1).unwrap_or(&timestamp);
-                let t = 1.0 - ((next_timestamp - time) / (next_timestamp - timestamp));
-
-                let node = &mut self.nodes[track.target];
-                let (scale, mut rotation, mut translation) =
-                    node.transform.to_scale_rotation_translation();
-
-                match &track.keyframes {
-                    Keyframes::Rotations(rotations) => {
-                        rotation = rotations[keyframe];
-                        let next_rotation = *rotations.get(keyframe + 1).unwrap_or(&rotation);
-
-                        rotation = rotation.slerp(next_rotation, t);
-
-                        let (scale, _, translation) =
-                            node.transform.to_scale_rotation_translation();
-                        node.transform =
-                            Mat4::from_scale_rotation_translation(scale, rotation, translation)
-                    }
-                    Keyframes::Translations(translations) => {
-                        translation = translations[keyframe];
-                        let next_translation =
-                            *translations.get(keyframe + 1).unwrap_or(&translation);
-
-                        translation = translation.lerp(next_translation, t);
-                    }
-
-                    _ => {}
-                }
+    // advance playback by `dt` seconds and update the pose, doesn't touch the gpu.
+    // Returns the events crossed by every clip that's playing, including ones being faded out
+    pub fn animate(&mut self, dt: f32) -> Vec<TriggeredEvent> {
+        let (pose, events) = self.animator.animate(&self.skeleton, dt);
 
-                node.transform = Mat4::from_scale_rotation_translation(scale, rotation, translation)
-            }
-        }
+        self.pose = pose;
+        self.skeleton.update_world_matrices(&mut self.pose);
+        ik::solve(&self.skeleton, &mut self.pose, &self.ik_constraints);
+        self.bounds = self.pose_bounds.bounds(&self.pose);
 
-        // calculate world matrices for all nodes from the root nodes down
-        for node in self.parentless_nodes.clone().iter() {
-            self.update_world_matrices(*node, Mat4::IDENTITY);
-        }
+        events
+    }
 
+    // extract the horizontal movement and yaw of `node` from the clips instead of playing it, see
+    // `Animator::set_root_motion_node`
+    pub fn set_root_motion_node(&mut self, node: Option<usize>) {
+        self.animator.set_root_motion_node(&self.skeleton, node);
+    }
+
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.skeleton
+            .nodes
+            .iter()
+            .position(|node| node.name == name)
+    }
+
+    // world transform of `node` in the last animated pose, after IK
+    pub fn node_world_transform(&self, node: usize) -> Mat4 {
+        self.pose.world_transforms[node]
+    }
+
+    // a socket on the node called `name`, see `Socket::world_transform`
+    pub fn socket(&self, name: &str, offset: Mat4) -> Option<Socket> {
+        self.find_node(name)
+            .map(|node| Socket::new(node).with_offset(offset))
+    }
+
+    // play a clip on top of the current animation, see `Animator::add_layer`
+    pub fn add_layer(&mut self, layer: AnimationLayer) -> Option<usize> {
+        self.animator.add_layer(&self.skeleton, layer)
+    }
+
+    // copy `animation` of `source` over to this object, see `retarget`. Returns the index of the new animation
+    pub fn add_retargeted(
+        &mut self,
+        source: &AnimatedObject,
+        animation: usize,
+        bone_map: &BoneMap,
+    ) -> usize {
+        let animation = retarget(
+            &source.animator.animations[animation],
+            &source.skeleton,
+            &self.skeleton,
+            bone_map,
+        );
+        self.clip_bounds
+            .push(self.pose_bounds.clip_bounds(&self.skeleton, &animation));
+        self.animator.animations.push(animation);
+        self.animator.animations.len() - 1
+    }
+
+    pub fn upload(&self, renderer: &mut Renderer) {
         // write world matrices as instance data for mesh nodes
-        for node in self.nodes.iter() {
+        for (i, node) in self.skeleton.nodes.iter().enumerate() {
             if let NodeType::Mesh { mesh } = node.node_type {
                 renderer.write_buffer(
-                    self.meshes[mesh].render_data.instance_buffer,
-                    bytemuck::cast_slice(&[node.world_transform]),
+                    self.meshes[mesh].instance_buffer,
+                    bytemuck::cast_slice(&[self.pose.world_transforms[i]]),
                 );
+                self.meshes[mesh].write_morph_weights(renderer, &self.pose.weights[i]);
+            }
+        }
+
+        for (i, skin) in self
+            .skins
+            .iter()
+            .enumerate()
+            .take(self.skeleton.skins.len())
+        {
+            let joint_matrices = self.skeleton.joint_matrices(i, &self.pose);
+            renderer.write_buffer(skin.joint_buffer, bytemuck::cast_slice(&joint_matrices));
+        }
+
+        renderer.write_buffer(
+            self.light_buffer,
+            bytemuck::cast_slice(&self.scene.light_data(&self.pose)),
+        );
+    }
+
+    // draw how strongly every vertex is influenced by the joint `node` instead of the materials, for
+    // checking skin weights. Vertices of skins the node isn't a joint of are drawn as not influenced
+    pub fn set_highlighted_node(&self, renderer: &mut Renderer, node: Option<usize>) {
+        for (i, skin) in self.skins.iter().enumerate() {
+            let mut joint_info = JointInfo::new(0, 0);
+            if let Some(node) = node {
+                joint_info.heat_map = 1;
+                if let Some(joint) = self
+                    .skeleton
+                    .skins
+                    .get(i)
+                    .and_then(|skin| skin.joints.iter().position(|joint| *joint == node))
+                {
+                    joint_info.highlighted_joint = joint as u32;
+                }
             }
+            renderer.write_buffer(skin.joint_info_buffer, bytemuck::cast_slice(&[joint_info]));
         }
+    }
 
-        // get final joint matrices and write them to gpu
-        let joint_matrices = self
-            .joints
-            .iter().enumerate()
-            .map(|(i, joint)| self.nodes[*joint].world_transform * self.inverse_bind_matrices[i])
+    // every primitive of every mesh node, blended primitives come last so they're drawn over opaque ones
+    pub fn draws(&self) -> Vec<Draw<'_>> {
+        let mut draws = self
+            .skeleton
+            .nodes
+            .iter()
+            .filter_map(|node| match node.node_type {
+                NodeType::Mesh { mesh } => Some((&self.skins[node.skin.unwrap_or(0)], mesh)),
+                NodeType::Generic => None,
+            })
+            .flat_map(|(skin, mesh)| {
+                self.meshes[mesh].primitives.iter().map(|primitive| Draw {
+                    joint_data_bind_group: skin.joint_data_bind_group,
+                    material_bind_group: self.materials[primitive.material].bind_group,
+                    morph_bind_group: primitive.render_data.morph_bind_group,
+                    primitive,
+                })
+            })
             .collect::<Vec<_>>();
-        renderer.write_buffer(self.joint_buffer, bytemuck::cast_slice(&joint_matrices));
+        draws.sort_by_key(|draw| {
+            self.materials[draw.primitive.material]
+                .properties
+                .alpha_mode
+                == AlphaMode::Blend
+        });
+        draws
     }
 
     pub fn joint_data_bind_group_layout_descriptor() -> BindGroupLayoutDescriptor {
         BindGroupLayoutDescriptor {
-            entries: vec![BindGroupLayoutDescriptorEntry::Data { is_uniform: false }],
+            entries: vec![
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: false },
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: true },
+                // lights
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: false },
+            ],
         }
     }
 
@@ -422,77 +512,255 @@ impl AnimatedObject {
             ],
         }
     }
+}
 
-    fn update_world_matrices(&mut self, node: usize, parent_matrix: Mat4) {
-        let node = &mut self.nodes[node];
-        node.world_transform = parent_matrix * node.transform;
-        let world = node.world_transform;
-        for child in node.children.clone().iter() {
-            self.update_world_matrices(*child, world);
-        }
+// paths are used as they are, the ones that don't exist are looked up in `res/` where the demo
+// keeps its models
+pub fn resolve_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.exists() {
+        path.to_path_buf()
+    } else {
+        Path::new("res").join(path)
     }
 }
 
-impl Renderable for Mesh {
-    fn num_instances(&self) -> u32 {
-        1
+// the directory uris in the gltf at `path` are relative to
+pub fn uri_base(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new(""))
+}
+
+// the parsed .gltf or .glb file and the contents of all of its buffers
+pub fn load_gltf(path: &Path) -> Result<(gltf::Gltf, Vec<Vec<u8>>), GltfLoadError> {
+    let gltf = gltf::Gltf::from_slice(&load_file(path)?)?;
+    let buffers = load_buffers(&gltf, uri_base(path))?;
+    Ok((gltf, buffers))
+}
+
+pub fn load_buffers(gltf: &gltf::Gltf, base: &Path) -> Result<Vec<Vec<u8>>, GltfLoadError> {
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => {
+                buffers.push(load_uri(uri, base)?);
+            }
+            gltf::buffer::Source::Bin => {
+                buffers.push(gltf.blob.clone().ok_or(GltfLoadError::MissingBlob)?);
+            }
+        }
     }
 
-    fn num_indices(&self) -> u32 {
-        self.indices.len() as u32
+    Ok(buffers)
+}
+
+// read directly instead of with `load_binary`, its `FilePath` only takes static strings. Loading
+// blocks, models are loaded before the first frame
+pub fn load_file(path: &Path) -> Result<Vec<u8>, GltfLoadError> {
+    std::fs::read(path).map_err(|error| GltfLoadError::Io {
+        path: path.display().to_string(),
+        error: error.to_string(),
+    })
+}
+
+// a buffer or image, embedded as a base64 data uri or a file relative to `base`
+pub fn load_uri(uri: &str, base: &Path) -> Result<Vec<u8>, GltfLoadError> {
+    let Some(data) = uri.strip_prefix("data:") else {
+        return load_file(&base.join(percent_decode(uri)));
+    };
+
+    let Some((header, payload)) = data.split_once(',') else {
+        return Err(GltfLoadError::InvalidDataUri {
+            error: "missing ','",
+        });
+    };
+    if !header.ends_with(";base64") {
+        return Err(GltfLoadError::InvalidDataUri {
+            error: "only base64 data is supported",
+        });
     }
+    decode_base64(payload).ok_or(GltfLoadError::InvalidDataUri {
+        error: "invalid base64",
+    })
+}
 
-    fn get_buffers(&self) -> (BufferHandle, BufferHandle, Option<BufferHandle>) {
-        (
-            self.render_data.vertex_buffer,
-            self.render_data.index_buffer,
-            Some(self.render_data.instance_buffer),
-        )
+// uris escape characters like spaces as %20
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| uri.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
     }
+    String::from_utf8_lossy(&decoded).into_owned()
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Default)]
-pub struct AnimatedVertex {
-    pub position: Vec3,
-    pub normal: Vec3,
-    pub uv: Vec2,
-    pub weights: u32,
-    pub joints: u32,
+// standard or url safe alphabet, padding is optional
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
 }
 
-impl AnimatedVertex {
-    pub fn desc() -> BufferLayout {
-        BufferLayout {
-            step_mode: BufferLayoutStepMode::Vertex,
-            stride: std::mem::size_of::<AnimatedVertex>(),
-            entries: &[
-                BufferLayoutEntry {
-                    offset: std::mem::offset_of!(AnimatedVertex, position) as u64,
-                    location: 0,
-                    data_type: BufferLayoutEntryDataType::Float32x3,
-                },
-                BufferLayoutEntry {
-                    offset: std::mem::offset_of!(AnimatedVertex, normal) as u64,
-                    location: 1,
-                    data_type: BufferLayoutEntryDataType::Float32x3,
-                },
-                BufferLayoutEntry {
-                    offset: std::mem::offset_of!(AnimatedVertex, uv) as u64,
-                    location: 2,
-                    data_type: BufferLayoutEntryDataType::Float32x2,
-                },
-                BufferLayoutEntry {
-                    offset: std::mem::offset_of!(AnimatedVertex, weights) as u64,
-                    location: 3,
-                    data_type: BufferLayoutEntryDataType::U32,
-                },
-                BufferLayoutEntry {
-                    offset: std::mem::offset_of!(AnimatedVertex, joints) as u64,
-                    location: 4,
-                    data_type: BufferLayoutEntryDataType::U32,
-                },
-            ],
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own for every test, they run in parallel
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("animations_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // a .glb container, both chunks are padded to 4 bytes
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((28 + json.len() + bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+        glb
+    }
+
+    #[test]
+    fn missing_files_name_the_path() {
+        assert!(matches!(
+            load_gltf(Path::new("does_not_exist.gltf")),
+            Err(GltfLoadError::Io { path, .. }) if path == "does_not_exist.gltf"
+        ));
+    }
+
+    #[test]
+    fn binary_buffers_need_a_glb_chunk() {
+        // a buffer without an uri refers to the binary chunk, which a .gltf doesn't have
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 4 }]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+
+        assert!(matches!(
+            load_buffers(&gltf, Path::new("")),
+            Err(GltfLoadError::MissingBlob)
+        ));
+    }
+
+    #[test]
+    fn missing_buffer_files_name_the_uri() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 4, "uri": "missing.bin" }]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+
+        assert!(matches!(
+            load_buffers(&gltf, Path::new("models")),
+            Err(GltfLoadError::Io { path, .. }) if path == "models/missing.bin"
+        ));
+    }
+
+    #[test]
+    fn glb_buffers_come_from_the_binary_chunk() {
+        let directory = test_directory("glb");
+        let path = directory.join("model.glb");
+        let json = r#"{ "asset": { "version": "2.0" }, "buffers": [{ "byteLength": 4 }] }"#;
+        std::fs::write(&path, glb(json, &[1, 2, 3, 4])).unwrap();
+
+        let (_, buffers) = load_gltf(&path).unwrap();
+        assert_eq!(buffers, vec![vec![1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn uris_are_relative_to_the_gltf() {
+        let directory = test_directory("uris");
+        std::fs::create_dir_all(directory.join("data")).unwrap();
+        std::fs::write(directory.join("data/my buffer.bin"), [5, 6, 7, 8]).unwrap();
+        let path = directory.join("model.gltf");
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [
+                { "byteLength": 4, "uri": "data/my%20buffer.bin" },
+                { "byteLength": 4, "uri": "data:application/octet-stream;base64,AQIDBA==" }
+            ]
+        }"#;
+        std::fs::write(&path, json).unwrap();
+
+        let path = resolve_path(path.to_str().unwrap());
+        let (_, buffers) = load_gltf(&path).unwrap();
+        assert_eq!(buffers, vec![vec![5, 6, 7, 8], vec![1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn models_that_dont_exist_are_looked_up_in_res() {
+        assert_eq!(
+            resolve_path("character.gltf"),
+            Path::new("res/character.gltf")
+        );
+        assert_eq!(
+            resolve_path("res/character.gltf"),
+            Path::new("res/character.gltf")
+        );
+    }
+
+    #[test]
+    fn data_uris_must_be_base64() {
+        for uri in [
+            "data:application/octet-stream;base64,AQ*D",
+            "data:application/octet-stream,%01%02",
+            "data:application/octet-stream;base64",
+        ] {
+            assert!(matches!(
+                load_uri(uri, Path::new("")),
+                Err(GltfLoadError::InvalidDataUri { .. })
+            ));
         }
     }
+
+    #[test]
+    fn base64_decodes_with_and_without_padding() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ").unwrap(), b"M");
+        assert_eq!(decode_base64("-_8").unwrap(), [0xfb, 0xff]);
+        assert_eq!(decode_base64("").unwrap(), b"");
+    }
 }
@@ -0,0 +1,447 @@
+use std::ops::{Add, Mul};
+
+use jandering_engine::types::{Qua, Vec3};
+
+use crate::{
+    compression::{quantize_rotations, reduce_keyframes, QuantizedQua, ReductionTolerance},
+    error::GltfLoadError,
+    events::{events_from_gltf, AnimationEvent},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // every keyframe is stored as [in tangent, value, out tangent]
+    CubicSpline,
+}
+
+impl From<gltf::animation::Interpolation> for Interpolation {
+    fn from(value: gltf::animation::Interpolation) -> Self {
+        match value {
+            gltf::animation::Interpolation::Step => Self::Step,
+            gltf::animation::Interpolation::Linear => Self::Linear,
+            gltf::animation::Interpolation::CubicSpline => Self::CubicSpline,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Keyframes {
+    Rotations(Vec<Qua>),
+    Translations(Vec<Vec3>),
+    Scales(Vec<Vec3>),
+    // all morph target weights for one keyframe are stored next to each other
+    MorphTargetWeights(Vec<f32>),
+    // rotations after `Animation::quantize_rotations`
+    QuantizedRotations(Vec<QuantizedQua>),
+}
+
+#[derive(Debug)]
+pub struct Track {
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+    pub target: usize,
+    pub timestamps: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub struct Animation {
+    pub name: String,
+    pub length: f32,
+    pub tracks: Vec<Track>,
+    // sorted by time
+    pub events: Vec<AnimationEvent>,
+}
+
+impl Animation {
+    pub fn from_gltf(
+        animation: gltf::Animation,
+        buffers: &[Vec<u8>],
+    ) -> Result<Self, GltfLoadError> {
+        let name = animation.name().unwrap_or("Unnamed").to_string();
+
+        let mut length = 0.0f32;
+        let mut tracks = Vec::new();
+        for (i, channel) in animation.channels().enumerate() {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let Some(gltf::accessor::Iter::Standard(times)) = reader.read_inputs() else {
+                return Err(GltfLoadError::UnsupportedInput {
+                    animation: name,
+                    channel: i,
+                });
+            };
+            let timestamps = times.collect::<Vec<_>>();
+
+            length = timestamps.iter().copied().fold(length, f32::max);
+
+            let Some(outputs) = reader.read_outputs() else {
+                return Err(GltfLoadError::MissingOutput {
+                    animation: name,
+                    channel: i,
+                });
+            };
+            let keyframes = match outputs {
+                gltf::animation::util::ReadOutputs::Rotations(rotations) => Keyframes::Rotations(
+                    rotations
+                        .into_f32()
+                        .map(|rotation| {
+                            Qua::from_xyzw(rotation[0], rotation[1], rotation[2], rotation[3])
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                gltf::animation::util::ReadOutputs::Translations(translations) => {
+                    Keyframes::Translations(
+                        translations.map(|translation| translation.into()).collect(),
+                    )
+                }
+                gltf::animation::util::ReadOutputs::Scales(scales) => {
+                    Keyframes::Scales(scales.map(|scale| scale.into()).collect())
+                }
+                gltf::animation::util::ReadOutputs::MorphTargetWeights(weights) => {
+                    Keyframes::MorphTargetWeights(weights.into_f32().collect())
+                }
+            };
+
+            let track = Track {
+                keyframes,
+                interpolation: channel.sampler().interpolation().into(),
+                timestamps,
+                target: channel.target().node().index(),
+            };
+
+            // sampling indexes values by keyframe, so the counts have to line up
+            let values = track.keyframes.len();
+            let expected = track.timestamps.len() * track.values_per_keyframe();
+            let valid = match track.keyframes {
+                Keyframes::MorphTargetWeights(_) if expected > 0 => values.is_multiple_of(expected),
+                _ => values == expected,
+            };
+            if !valid {
+                return Err(GltfLoadError::KeyframeCountMismatch {
+                    animation: name,
+                    channel: i,
+                    timestamps: track.timestamps.len(),
+                    values,
+                });
+            }
+
+            tracks.push(track);
+        }
+
+        let mut events = events_from_gltf(&animation)?;
+        events.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Ok(Self {
+            name,
+            length,
+            tracks,
+            events,
+        })
+    }
+
+    // register an event in code, in addition to the ones from the gltf
+    pub fn add_event(&mut self, name: &str, time: f32) {
+        let index = self.events.partition_point(|event| event.time <= time);
+        self.events.insert(
+            index,
+            AnimationEvent {
+                name: name.to_string(),
+                time,
+            },
+        );
+    }
+}
+
+// compression passes, run at load time or before saving clips
+impl Animation {
+    // drop keyframes the remaining ones reproduce within `tolerance`, see `compression::reduce_keyframes`
+    pub fn reduce_keyframes(&mut self, tolerance: ReductionTolerance) {
+        for track in self.tracks.iter_mut() {
+            reduce_keyframes(track, tolerance);
+        }
+    }
+
+    // halve the memory rotations take, call after `reduce_keyframes`
+    pub fn quantize_rotations(&mut self) {
+        for track in self.tracks.iter_mut() {
+            quantize_rotations(track);
+        }
+    }
+
+    pub fn keyframe_count(&self) -> usize {
+        self.tracks.iter().map(|track| track.timestamps.len()).sum()
+    }
+}
+
+impl Keyframes {
+    pub fn len(&self) -> usize {
+        match self {
+            Keyframes::Rotations(rotations) => rotations.len(),
+            Keyframes::Translations(translations) => translations.len(),
+            Keyframes::Scales(scales) => scales.len(),
+            Keyframes::MorphTargetWeights(weights) => weights.len(),
+            Keyframes::QuantizedRotations(rotations) => rotations.len(),
+        }
+    }
+}
+
+pub trait Keyframe: Copy + Add<Output = Self> + Mul<f32, Output = Self> {
+    fn interpolate(self, other: Self, t: f32) -> Self;
+
+    // called on values produced by the cubic spline, which doesn't preserve unit quaternions
+    fn finish(self) -> Self {
+        self
+    }
+}
+
+impl Keyframe for f32 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Keyframe for Vec3 {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+impl Keyframe for Qua {
+    fn interpolate(self, other: Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+
+    fn finish(self) -> Self {
+        self.normalize()
+    }
+}
+
+impl Track {
+    // number of stored values per keyframe, 3 for cubic splines because of the tangents
+    pub fn values_per_keyframe(&self) -> usize {
+        match self.interpolation {
+            Interpolation::CubicSpline => 3,
+            _ => 1,
+        }
+    }
+
+    pub fn morph_target_count(&self) -> usize {
+        match &self.keyframes {
+            Keyframes::MorphTargetWeights(weights) if !self.timestamps.is_empty() => {
+                weights.len() / (self.timestamps.len() * self.values_per_keyframe())
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn sample<T: Keyframe>(&self, time: f32, value: impl Fn(usize) -> T) -> Option<T> {
+        sample(self.interpolation, &self.timestamps, time, value)
+    }
+}
+
+// the last keyframe at or before `time`, or the first one if `time` is before all of them.
+// Timestamps are strictly increasing per the spec so this can be a binary search
+pub fn keyframe_index(timestamps: &[f32], time: f32) -> usize {
+    timestamps
+        .partition_point(|timestamp| *timestamp <= time)
+        .saturating_sub(1)
+}
+
+// `value` returns the i-th element of the raw output array, so for cubic splines that includes the tangents
+pub fn sample<T: Keyframe>(
+    interpolation: Interpolation,
+    timestamps: &[f32],
+    time: f32,
+    value: impl Fn(usize) -> T,
+) -> Option<T> {
+    let keyframe_value = |keyframe: usize| match interpolation {
+        Interpolation::CubicSpline => value(keyframe * 3 + 1),
+        _ => value(keyframe),
+    };
+
+    let keyframe = keyframe_index(timestamps, time);
+    let timestamp = *timestamps.get(keyframe)?;
+    let Some(next_timestamp) = timestamps.get(keyframe + 1).copied() else {
+        return Some(keyframe_value(keyframe));
+    };
+
+    let delta = next_timestamp - timestamp;
+    let t = if delta > 0.0 {
+        ((time - timestamp) / delta).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    Some(match interpolation {
+        Interpolation::Step => keyframe_value(keyframe),
+        Interpolation::Linear => {
+            keyframe_value(keyframe).interpolate(keyframe_value(keyframe + 1), t)
+        }
+        Interpolation::CubicSpline => {
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            let start = value(keyframe * 3 + 1);
+            let start_out_tangent = value(keyframe * 3 + 2);
+            let end_in_tangent = value((keyframe + 1) * 3);
+            let end = value((keyframe + 1) * 3 + 1);
+
+            (start * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + start_out_tangent * (delta * (t3 - 2.0 * t2 + t))
+                + end * (-2.0 * t3 + 3.0 * t2)
+                + end_in_tangent * (delta * (t3 - t2)))
+                .finish()
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn step_holds_the_previous_keyframe() {
+        let timestamps = [0.0, 1.0, 2.0];
+        let values = [1.0, 5.0, 3.0];
+        let sample_at =
+            |time| sample(Interpolation::Step, &timestamps, time, |i| values[i]).unwrap();
+
+        assert_close(sample_at(-1.0), 1.0);
+        assert_close(sample_at(0.5), 1.0);
+        assert_close(sample_at(1.0), 5.0);
+        assert_close(sample_at(1.99), 5.0);
+        assert_close(sample_at(3.0), 3.0);
+    }
+
+    #[test]
+    fn linear_interpolates_between_keyframes() {
+        let timestamps = [0.0, 1.0, 2.0];
+        let values = [1.0, 5.0, 3.0];
+        let sample_at =
+            |time| sample(Interpolation::Linear, &timestamps, time, |i| values[i]).unwrap();
+
+        assert_close(sample_at(0.25), 2.0);
+        assert_close(sample_at(1.5), 4.0);
+        assert_close(sample_at(2.5), 3.0);
+
+        let translations = [Vec3::ZERO, Vec3::new(2.0, -4.0, 8.0)];
+        let translation = sample(Interpolation::Linear, &timestamps[..2], 0.75, |i| {
+            translations[i]
+        })
+        .unwrap();
+        assert!(translation.abs_diff_eq(Vec3::new(1.5, -3.0, 6.0), 1e-5));
+    }
+
+    #[test]
+    fn linear_rotations_slerp() {
+        let rotations = [Qua::IDENTITY, Qua::from_rotation_y(90.0f32.to_radians())];
+        let rotation = sample(Interpolation::Linear, &[0.0, 1.0], 0.5, |i| rotations[i]).unwrap();
+        assert!(rotation.abs_diff_eq(Qua::from_rotation_y(45.0f32.to_radians()), 1e-5));
+    }
+
+    #[test]
+    fn cubic_spline_uses_the_tangents() {
+        // [in tangent, value, out tangent] per keyframe, the tangents are scaled by the 2 seconds between them
+        let timestamps = [0.0, 2.0];
+        let values = [0.0, 0.0, 1.0, 3.0, 4.0, 0.0];
+        let sample_at =
+            |time| sample(Interpolation::CubicSpline, &timestamps, time, |i| values[i]).unwrap();
+
+        assert_close(sample_at(0.0), 0.0);
+        // t = 0.25: 1 * 2 * 0.140625 + 4 * 0.15625 + 3 * 2 * -0.046875
+        assert_close(sample_at(0.5), 0.625);
+        // t = 0.5: 1 * 2 * 0.125 + 4 * 0.5 + 3 * 2 * -0.125
+        assert_close(sample_at(1.0), 1.5);
+        assert_close(sample_at(2.0), 4.0);
+        assert_close(sample_at(5.0), 4.0);
+    }
+
+    #[test]
+    fn cubic_spline_rotations_stay_normalized() {
+        let tangent = Qua::from_xyzw(0.0, 1.0, 0.0, 0.0);
+        let values = [
+            tangent,
+            Qua::IDENTITY,
+            tangent,
+            tangent,
+            Qua::from_rotation_y(90.0f32.to_radians()),
+            tangent,
+        ];
+        let rotation = sample(Interpolation::CubicSpline, &[0.0, 1.0], 0.3, |i| values[i]).unwrap();
+        assert_close(rotation.length(), 1.0);
+    }
+
+    #[test]
+    fn keyframe_index_finds_the_last_keyframe_at_or_before_time() {
+        let timestamps = [0.0, 0.5, 1.0, 2.0];
+        assert_eq!(keyframe_index(&timestamps, -1.0), 0);
+        assert_eq!(keyframe_index(&timestamps, 0.0), 0);
+        assert_eq!(keyframe_index(&timestamps, 0.75), 1);
+        assert_eq!(keyframe_index(&timestamps, 1.0), 2);
+        assert_eq!(keyframe_index(&timestamps, 10.0), 3);
+        assert_eq!(keyframe_index(&[], 1.0), 0);
+    }
+
+    #[test]
+    fn empty_tracks_have_no_value() {
+        assert_eq!(sample(Interpolation::Linear, &[], 1.0, |_| 1.0f32), None);
+    }
+
+    // a translation channel on node 0 with 2 timestamps, outputs are read from `output_count` vec3s
+    fn translation_channel(output_count: usize, buffer_length: usize) -> gltf::Gltf {
+        let json = format!(
+            r#"{{
+            "asset": {{ "version": "2.0" }},
+            "nodes": [{{}}],
+            "animations": [{{
+                "name": "walk",
+                "channels": [{{ "sampler": 0, "target": {{ "node": 0, "path": "translation" }} }}],
+                "samplers": [{{ "input": 0, "output": 1 }}]
+            }}],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1] }},
+                {{ "bufferView": 1, "componentType": 5126, "count": {output_count}, "type": "VEC3" }}
+            ],
+            "bufferViews": [
+                {{ "buffer": 0, "byteLength": 8 }},
+                {{ "buffer": 0, "byteOffset": 8, "byteLength": {} }}
+            ],
+            "buffers": [{{ "byteLength": {buffer_length} }}]
+        }}"#,
+            output_count * 12,
+        );
+        gltf::Gltf::from_slice(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn channels_need_a_value_per_timestamp() {
+        let gltf = translation_channel(3, 44);
+        let buffer = vec![0; 44];
+
+        assert!(matches!(
+            Animation::from_gltf(gltf.animations().next().unwrap(), &[buffer]),
+            Err(GltfLoadError::KeyframeCountMismatch { animation, channel: 0, timestamps: 2, values: 3 })
+                if animation == "walk"
+        ));
+    }
+
+    #[test]
+    fn outputs_past_the_end_of_the_buffer_are_rejected() {
+        // the file claims 32 bytes but only the timestamps were written
+        let gltf = translation_channel(2, 32);
+        let buffer = vec![0; 8];
+
+        assert!(matches!(
+            Animation::from_gltf(gltf.animations().next().unwrap(), &[buffer]),
+            Err(GltfLoadError::MissingOutput { animation, channel: 0 }) if animation == "walk"
+        ));
+    }
+}
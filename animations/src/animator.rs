@@ -0,0 +1,450 @@
+use crate::{
+    animation::Animation,
+    events::{crossed_events, TriggeredEvent},
+    layer::AnimationLayer,
+    playback::{Playback, WrapMode},
+    root_motion::{RootMotion, RootMotionExtractor},
+    skeleton::{Pose, Skeleton},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlendClip {
+    pub animation: usize,
+    pub weight: f32,
+}
+
+#[derive(Debug)]
+struct Crossfade {
+    from: Vec<BlendClip>,
+    // the clips being faded out keep playing
+    from_playback: Playback,
+    elapsed: f32,
+    duration: f32,
+}
+
+// which clips of a skeleton play and how they're blended, faded, layered and moved by root motion.
+// Plain cpu side data like `Skeleton`, so poses can be animated and tested headlessly
+#[derive(Debug)]
+pub struct Animator {
+    pub animations: Vec<Animation>,
+    pub current_animation: usize,
+    pub playback: Playback,
+    // when not empty these get blended together instead of playing `current_animation`
+    blend: Vec<BlendClip>,
+    crossfade: Option<Crossfade>,
+    // applied in order on top of the clips above, added with `add_layer` so they're prepared
+    layers: Vec<AnimationLayer>,
+    root_motion: Option<RootMotionExtractor>,
+    root_motion_delta: RootMotion,
+}
+
+impl Animator {
+    pub fn new(animations: Vec<Animation>) -> Self {
+        Self {
+            animations,
+            current_animation: 0,
+            playback: Playback::default(),
+            blend: Vec::new(),
+            crossfade: None,
+            layers: Vec::new(),
+            root_motion: None,
+            root_motion_delta: RootMotion::default(),
+        }
+    }
+
+    // advance playback by `dt` seconds and sample the local transforms of `skeleton`, world matrices
+    // aren't updated. Returns the events crossed by every clip that's playing, including ones being faded out
+    pub fn animate(&mut self, skeleton: &Skeleton, dt: f32) -> (Pose, Vec<TriggeredEvent>) {
+        let active_clips = self.active_clips();
+        let (_, length) = self.weighted_clips(&active_clips);
+        self.playback.advance(dt, length);
+        let (mut pose, mut root_motion) =
+            self.sample_group(skeleton, &active_clips, &self.playback);
+        let mut events = self.crossed_events(&active_clips, &self.playback, 1.0);
+
+        if let Some(mut crossfade) = self.crossfade.take() {
+            crossfade.elapsed += dt;
+            let t = crossfade.elapsed / crossfade.duration;
+            if t < 1.0 {
+                let (_, from_length) = self.weighted_clips(&crossfade.from);
+                crossfade.from_playback.advance(dt, from_length);
+                let (mut from_pose, from_root_motion) =
+                    self.sample_group(skeleton, &crossfade.from, &crossfade.from_playback);
+                for event in events.iter_mut() {
+                    event.weight *= t;
+                }
+                events.extend(self.crossed_events(
+                    &crossfade.from,
+                    &crossfade.from_playback,
+                    1.0 - t,
+                ));
+
+                from_pose.blend(&pose, t);
+                pose = from_pose;
+                root_motion = from_root_motion * (1.0 - t) + root_motion * t;
+                self.crossfade = Some(crossfade);
+            }
+        }
+
+        for layer in self.layers.iter_mut() {
+            if let Some(animation) = self.animations.get(layer.animation) {
+                layer.playback.advance(dt, animation.length);
+            }
+        }
+        for layer in self.layers.iter() {
+            layer.apply(skeleton, &self.animations, &mut pose);
+            let clip = BlendClip {
+                animation: layer.animation,
+                weight: 1.0,
+            };
+            events.extend(self.crossed_events(&[clip], &layer.playback, layer.weight));
+        }
+
+        if let Some(extractor) = &self.root_motion {
+            extractor.strip(&mut pose.transforms[extractor.node]);
+            self.root_motion_delta = root_motion;
+        }
+
+        (pose, events)
+    }
+
+    // extract the horizontal movement and yaw of `node` from the clips instead of playing it, so the
+    // rendered root stays in place. The motion of every update can be read with `root_motion`
+    pub fn set_root_motion_node(&mut self, skeleton: &Skeleton, node: Option<usize>) {
+        self.root_motion = node.map(|node| RootMotionExtractor::new(skeleton, node));
+        self.root_motion_delta = RootMotion::default();
+    }
+
+    // root motion of the last `animate`, zero when root motion isn't enabled
+    pub fn root_motion(&self) -> RootMotion {
+        self.root_motion_delta
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations
+            .iter()
+            .position(|animation| animation.name == name)
+    }
+
+    // switch to `animation` instantly, speed and wrap mode of the current playback are kept
+    pub fn play(&mut self, animation: usize) {
+        self.crossfade = None;
+        self.blend.clear();
+        self.current_animation = animation;
+        self.restart();
+    }
+
+    // switch to `animation`, fading out whatever was playing before over `duration` seconds
+    pub fn crossfade_to(&mut self, animation: usize, duration: f32) {
+        self.crossfade = Some(Crossfade {
+            from: self.active_clips(),
+            from_playback: self.playback,
+            elapsed: 0.0,
+            duration,
+        });
+
+        self.blend.clear();
+        self.current_animation = animation;
+        self.restart();
+    }
+
+    // play a clip on top of the current animation, e.g. an upper body wave over a walk or an additive
+    // breathing clip. Returns the index into `layers`, or None if the layer's mask root doesn't exist
+    pub fn add_layer(&mut self, skeleton: &Skeleton, mut layer: AnimationLayer) -> Option<usize> {
+        if !layer.prepare(skeleton, &self.animations) {
+            return None;
+        }
+
+        self.layers.push(layer);
+        Some(self.layers.len() - 1)
+    }
+
+    // fade a layer in or out, `layer` is an index returned by `add_layer`
+    pub fn set_layer_weight(&mut self, layer: usize, weight: f32) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.weight = weight;
+        }
+    }
+
+    // jump to `time` in the current animation, without root motion
+    pub fn seek(&mut self, time: f32) {
+        self.playback.seek(time);
+    }
+
+    // whether an animation played with `WrapMode::Once` reached its end
+    pub fn is_finished(&self) -> bool {
+        let (_, length) = self.weighted_clips(&self.active_clips());
+        self.playback.is_finished(length)
+    }
+
+    fn restart(&mut self) {
+        let (_, length) = self.weighted_clips(&self.active_clips());
+        self.playback.restart(length);
+    }
+
+    // blend several animations by weight, clips are time-synced by their normalized time so
+    // e.g. a walk and a run with different lengths stay in step. Weights can be changed every frame.
+    pub fn set_blend(&mut self, clips: Vec<BlendClip>) {
+        self.blend = clips;
+    }
+
+    fn active_clips(&self) -> Vec<BlendClip> {
+        if self.blend.is_empty() {
+            vec![BlendClip {
+                animation: self.current_animation,
+                weight: 1.0,
+            }]
+        } else {
+            self.blend.clone()
+        }
+    }
+
+    // clips with a weight paired with their animation, plus the length the group is synced to. The
+    // length is 0 when no clip has a weight, e.g. when the model has no animations
+    fn weighted_clips(&self, clips: &[BlendClip]) -> (Vec<(&Animation, f32)>, f32) {
+        let clips = clips
+            .iter()
+            .filter(|clip| clip.weight > 0.0)
+            .filter_map(|clip| Some((self.animations.get(clip.animation)?, clip.weight)))
+            .collect::<Vec<_>>();
+
+        let total_weight = clips.iter().map(|(_, weight)| weight).sum::<f32>();
+        if total_weight <= 0.0 {
+            return (clips, 0.0);
+        }
+        let length = clips
+            .iter()
+            .map(|(animation, weight)| animation.length * weight)
+            .sum::<f32>()
+            / total_weight;
+        (clips, length)
+    }
+
+    // events every clip of a group crossed during the last `Playback::advance`, `weight` scales the clips' weights
+    fn crossed_events(
+        &self,
+        clips: &[BlendClip],
+        playback: &Playback,
+        weight: f32,
+    ) -> Vec<TriggeredEvent> {
+        let (weighted_clips, length) = self.weighted_clips(clips);
+        if length <= 0.0 {
+            return Vec::new();
+        }
+        let total_weight = weighted_clips.iter().map(|(_, weight)| weight).sum::<f32>();
+
+        clips
+            .iter()
+            .filter(|clip| clip.weight > 0.0 && clip.animation < self.animations.len())
+            .flat_map(|clip| {
+                let animation = &self.animations[clip.animation];
+                // same phase syncing as the pose, scaled to the clip's own length
+                let scale = animation.length / length;
+                crossed_events(
+                    &animation.events,
+                    playback.previous_time * scale,
+                    playback.time * scale,
+                    animation.length,
+                    playback.wrap_mode,
+                )
+                .into_iter()
+                .map(move |event| TriggeredEvent {
+                    animation: clip.animation,
+                    name: event.name.clone(),
+                    weight: clip.weight / total_weight * weight,
+                })
+            })
+            .collect()
+    }
+
+    // pose and root motion of a group of clips at its playhead
+    fn sample_group(
+        &self,
+        skeleton: &Skeleton,
+        clips: &[BlendClip],
+        playback: &Playback,
+    ) -> (Pose, RootMotion) {
+        let (clips, length) = self.weighted_clips(clips);
+        let total_weight = clips.iter().map(|(_, weight)| weight).sum::<f32>();
+
+        // clips are synced by phase, each one is sampled at the same fraction of its own length
+        let phase = if length > 0.0 {
+            playback.clip_time(length) / length
+        } else {
+            0.0
+        };
+
+        let mut pose = skeleton.rest_pose();
+        let mut root_motion = RootMotion::default();
+        let mut accumulated_weight = 0.0;
+        for (animation, weight) in clips {
+            let mut clip_pose = skeleton.rest_pose();
+            clip_pose.apply(animation, phase * animation.length);
+
+            accumulated_weight += weight;
+            pose.blend(&clip_pose, weight / accumulated_weight);
+
+            if let Some(extractor) = &self.root_motion {
+                let scale = animation.length / length;
+                let motion = if playback.wrap_mode == WrapMode::Loop {
+                    // unwrapped so the motion of every loop is accumulated
+                    extractor.extract(
+                        skeleton,
+                        animation,
+                        playback.previous_time * scale,
+                        playback.time * scale,
+                    )
+                } else {
+                    extractor.segment(
+                        skeleton,
+                        animation,
+                        playback.clip_time_at(playback.previous_time, length) * scale,
+                        playback.clip_time(length) * scale,
+                    )
+                };
+                root_motion = root_motion + motion * (weight / total_weight);
+            }
+        }
+
+        (pose, root_motion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use jandering_engine::types::{Qua, Vec3};
+
+    use super::*;
+    use crate::{
+        animation::{Interpolation, Keyframes, Track},
+        skeleton::{Node, NodeType, Transform},
+    };
+
+    fn one_node() -> Skeleton {
+        Skeleton {
+            nodes: vec![Node {
+                name: "root".to_string(),
+                node_type: NodeType::Generic,
+                transform: Transform {
+                    translation: Vec3::ZERO,
+                    rotation: Qua::IDENTITY,
+                    scale: Vec3::ONE,
+                },
+                children: Vec::new(),
+                parent: None,
+                weights: Vec::new(),
+                skin: None,
+            }],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    // holds the root at `translation` and `rotation` for `length` seconds
+    fn hold(name: &str, length: f32, translation: Vec3, rotation: Qua) -> Animation {
+        Animation {
+            name: name.to_string(),
+            length,
+            tracks: vec![
+                Track {
+                    keyframes: Keyframes::Translations(vec![translation; 2]),
+                    interpolation: Interpolation::Linear,
+                    target: 0,
+                    timestamps: vec![0.0, length],
+                },
+                Track {
+                    keyframes: Keyframes::Rotations(vec![rotation; 2]),
+                    interpolation: Interpolation::Linear,
+                    target: 0,
+                    timestamps: vec![0.0, length],
+                },
+            ],
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn models_without_clips_keep_a_finite_playhead() {
+        let skeleton = one_node();
+        let mut animator = Animator::new(Vec::new());
+        for _ in 0..3 {
+            let (pose, events) = animator.animate(&skeleton, 0.1);
+            assert_eq!(pose.transforms[0].translation, Vec3::ZERO);
+            assert!(events.is_empty());
+        }
+        assert_eq!(animator.playback.time, 0.0);
+        assert!(!animator.is_finished());
+
+        // a blend without any weight doesn't have a length either
+        let mut animator = Animator::new(vec![hold("a", 1.0, Vec3::X, Qua::IDENTITY)]);
+        animator.set_blend(vec![BlendClip {
+            animation: 0,
+            weight: 0.0,
+        }]);
+        animator.animate(&skeleton, 0.1);
+        animator.seek(0.5);
+        animator.animate(&skeleton, 0.1);
+        assert!(animator.playback.time.is_finite());
+    }
+
+    #[test]
+    fn crossfades_are_halfway_after_half_their_duration() {
+        let skeleton = one_node();
+        let mut animator = Animator::new(vec![
+            hold("a", 1.0, Vec3::ZERO, Qua::IDENTITY),
+            hold(
+                "b",
+                1.0,
+                Vec3::new(2.0, 0.0, 0.0),
+                Qua::from_rotation_y(FRAC_PI_2),
+            ),
+        ]);
+        animator.animate(&skeleton, 0.3);
+
+        animator.crossfade_to(1, 1.0);
+        let (pose, _) = animator.animate(&skeleton, 0.5);
+        assert!(pose.transforms[0]
+            .translation
+            .abs_diff_eq(Vec3::new(1.0, 0.0, 0.0), 1e-5));
+        assert!(pose.transforms[0]
+            .rotation
+            .abs_diff_eq(Qua::from_rotation_y(FRAC_PI_2 * 0.5), 1e-5));
+
+        // done fading
+        let (pose, _) = animator.animate(&skeleton, 0.6);
+        assert!(pose.transforms[0]
+            .translation
+            .abs_diff_eq(Vec3::new(2.0, 0.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn blends_mix_by_weight_and_sync_their_lengths() {
+        let skeleton = one_node();
+        let mut animator = Animator::new(vec![
+            hold("walk", 1.0, Vec3::ZERO, Qua::IDENTITY),
+            hold("run", 2.0, Vec3::new(4.0, 0.0, 0.0), Qua::IDENTITY),
+        ]);
+        animator.set_blend(vec![
+            BlendClip {
+                animation: 0,
+                weight: 1.0,
+            },
+            BlendClip {
+                animation: 1,
+                weight: 3.0,
+            },
+        ]);
+
+        let (pose, _) = animator.animate(&skeleton, 0.25);
+        assert!(pose.transforms[0]
+            .translation
+            .abs_diff_eq(Vec3::new(3.0, 0.0, 0.0), 1e-5));
+
+        // the group loops after (1 * 1 + 2 * 3) / 4 seconds
+        animator.animate(&skeleton, 1.5);
+        assert_eq!(animator.playback.loops, 1);
+        assert!(animator.playback.time.abs() < 1e-5);
+    }
+}
@@ -0,0 +1,1483 @@
+use jandering_engine::types::{Mat4, Qua, UVec2, Vec3};
+
+use crate::{
+    animation::{Animation, Interpolation, Keyframes, Track},
+    bounds::Aabb,
+    compression::QuantizedQua,
+    error::BakeError,
+    events::AnimationEvent,
+    material::{AlphaMode, Image, MaterialProperties, TextureInfo},
+    mesh::{AnimatedVertex, Mesh, MorphTarget, Primitive},
+    scene::{LightKind, Projection, Scene, SceneCamera, SceneLight},
+    skeleton::{Node, NodeType, Skeleton, Skin, Transform},
+};
+
+pub const BAKE_EXTENSION: &str = ".bake";
+// bumped whenever the layout of the payload changes, older files have to be baked again
+pub const BAKE_VERSION: u32 = 2;
+const MAGIC: [u8; 8] = *b"JANIMBAK";
+// arrays start at multiples of this from the start of the file so they can be used in place
+const ALIGNMENT: usize = 16;
+// in place of a missing index
+const NONE: u32 = u32::MAX;
+
+// a bake is this header followed by the payload, a stream of records, strings and arrays. Strings and
+// arrays are prefixed with their length, arrays are also padded to `ALIGNMENT`
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct Header {
+    magic: [u8; 8],
+    version: u32,
+    padding: u32,
+    payload_length: u64,
+    // FNV-1a of the payload
+    checksum: u64,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct NodeRecord {
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    mesh: u32,
+    parent: u32,
+    skin: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct TextureRecord {
+    image: u32,
+    srgb: u32,
+    nearest: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct MaterialRecord {
+    base_color_factor: [f32; 4],
+    base_color_texture: u32,
+    normal_texture: u32,
+    normal_scale: f32,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    alpha_cutoff: f32,
+    alpha_mode: u32,
+    double_sided: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct TrackRecord {
+    target: u32,
+    interpolation: u32,
+    // which `Keyframes` the values are
+    kind: u32,
+}
+
+// missing optional values are NaN
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct CameraRecord {
+    node: u32,
+    orthographic: u32,
+    // yfov and aspect ratio or xmag and ymag
+    size: [f32; 2],
+    znear: f32,
+    zfar: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct LightRecord {
+    node: u32,
+    // 0 directional, 1 point, 2 spot
+    kind: u32,
+    color: [f32; 3],
+    intensity: f32,
+    range: f32,
+    inner_cone_angle: f32,
+    outer_cone_angle: f32,
+}
+
+// a model to bake or a parsed bake, the pixels, vertices, indices and keyframes point into the
+// object it was made from or the bytes it was parsed from
+#[derive(Debug)]
+pub struct BakedModel<'a> {
+    pub skeleton: Skeleton,
+    // decoded, so loading a bake doesn't decode pngs or jpegs again
+    pub images: Vec<BakedImage<'a>>,
+    pub textures: Vec<TextureInfo>,
+    pub materials: Vec<MaterialProperties>,
+    pub meshes: Vec<BakedMesh<'a>>,
+    pub animations: Vec<BakedAnimation<'a>>,
+    // one per animation
+    pub clip_bounds: Vec<Aabb>,
+    pub scene: Scene,
+}
+
+#[derive(Debug)]
+pub struct BakedImage<'a> {
+    pub name: &'a str,
+    pub size: UVec2,
+    pub rgba: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct BakedMesh<'a> {
+    pub name: &'a str,
+    pub morph_target_count: usize,
+    pub primitives: Vec<BakedPrimitive<'a>>,
+}
+
+#[derive(Debug)]
+pub struct BakedPrimitive<'a> {
+    pub vertices: &'a [AnimatedVertex],
+    pub indices: &'a [u32],
+    // (positions, normals) offsets of each target
+    pub morph_targets: Vec<(&'a [Vec3], &'a [Vec3])>,
+    pub material: usize,
+}
+
+#[derive(Debug)]
+pub struct BakedAnimation<'a> {
+    pub name: &'a str,
+    pub length: f32,
+    pub tracks: Vec<BakedTrack<'a>>,
+    pub events: Vec<AnimationEvent>,
+}
+
+#[derive(Debug)]
+pub struct BakedTrack<'a> {
+    pub target: usize,
+    pub interpolation: Interpolation,
+    pub timestamps: &'a [f32],
+    pub keyframes: BakedKeyframes<'a>,
+}
+
+#[derive(Debug)]
+pub enum BakedKeyframes<'a> {
+    Rotations(&'a [Qua]),
+    Translations(&'a [Vec3]),
+    Scales(&'a [Vec3]),
+    MorphTargetWeights(&'a [f32]),
+    QuantizedRotations(&'a [QuantizedQua]),
+}
+
+// `model` with its meshes, textures, materials, skeleton, animations, clip bounds and scene, see
+// `AnimatedObject::to_baked`
+pub fn bake(model: &BakedModel) -> Vec<u8> {
+    let mut payload = Writer::default();
+
+    let skeleton = &model.skeleton;
+    payload.count(skeleton.nodes.len());
+    for node in skeleton.nodes.iter() {
+        payload.str(&node.name);
+        payload.record(NodeRecord {
+            translation: node.transform.translation.to_array(),
+            rotation: node.transform.rotation.to_array(),
+            scale: node.transform.scale.to_array(),
+            mesh: match node.node_type {
+                NodeType::Mesh { mesh } => mesh as u32,
+                NodeType::Generic => NONE,
+            },
+            parent: node.parent.map_or(NONE, |parent| parent as u32),
+            skin: node.skin.map_or(NONE, |skin| skin as u32),
+        });
+        payload.indices(&node.children);
+        payload.array(&node.weights);
+    }
+    payload.indices(&skeleton.roots);
+    payload.count(skeleton.skins.len());
+    for skin in skeleton.skins.iter() {
+        payload.indices(&skin.joints);
+        payload.array(&skin.inverse_bind_matrices);
+    }
+
+    payload.count(model.images.len());
+    for image in model.images.iter() {
+        payload.str(image.name);
+        payload.record(image.size.to_array());
+        payload.array(image.rgba);
+    }
+    payload.count(model.textures.len());
+    for texture in model.textures.iter() {
+        payload.record(TextureRecord {
+            image: texture.image as u32,
+            srgb: texture.srgb as u32,
+            nearest: texture.nearest as u32,
+        });
+    }
+
+    payload.count(model.materials.len());
+    for properties in model.materials.iter() {
+        payload.str(&properties.name);
+        payload.record(MaterialRecord {
+            base_color_factor: properties.base_color_factor,
+            base_color_texture: properties
+                .base_color_texture
+                .map_or(NONE, |texture| texture as u32),
+            normal_texture: properties
+                .normal_texture
+                .map_or(NONE, |texture| texture as u32),
+            normal_scale: properties.normal_scale,
+            metallic_factor: properties.metallic_factor,
+            roughness_factor: properties.roughness_factor,
+            alpha_cutoff: properties.alpha_cutoff,
+            alpha_mode: properties.alpha_mode as u32,
+            double_sided: properties.double_sided as u32,
+        });
+    }
+
+    payload.count(model.meshes.len());
+    for mesh in model.meshes.iter() {
+        payload.str(mesh.name);
+        payload.count(mesh.morph_target_count);
+        payload.count(mesh.primitives.len());
+        for primitive in mesh.primitives.iter() {
+            payload.count(primitive.material);
+            payload.array(primitive.vertices);
+            payload.array(primitive.indices);
+            payload.count(primitive.morph_targets.len());
+            for (positions, normals) in primitive.morph_targets.iter() {
+                payload.array(positions);
+                payload.array(normals);
+            }
+        }
+    }
+
+    payload.count(model.animations.len());
+    for (i, animation) in model.animations.iter().enumerate() {
+        payload.str(animation.name);
+        payload.record(animation.length);
+        payload.record(model.clip_bounds.get(i).copied().unwrap_or(Aabb::EMPTY));
+        payload.count(animation.tracks.len());
+        for track in animation.tracks.iter() {
+            let kind = match track.keyframes {
+                BakedKeyframes::Rotations(_) => 0,
+                BakedKeyframes::Translations(_) => 1,
+                BakedKeyframes::Scales(_) => 2,
+                BakedKeyframes::MorphTargetWeights(_) => 3,
+                BakedKeyframes::QuantizedRotations(_) => 4,
+            };
+            payload.record(TrackRecord {
+                target: track.target as u32,
+                interpolation: match track.interpolation {
+                    Interpolation::Step => 0,
+                    Interpolation::Linear => 1,
+                    Interpolation::CubicSpline => 2,
+                },
+                kind,
+            });
+            payload.array(track.timestamps);
+            match track.keyframes {
+                BakedKeyframes::Rotations(values) => payload.array(values),
+                BakedKeyframes::Translations(values) => payload.array(values),
+                BakedKeyframes::Scales(values) => payload.array(values),
+                BakedKeyframes::MorphTargetWeights(values) => payload.array(values),
+                BakedKeyframes::QuantizedRotations(values) => payload.array(values),
+            }
+        }
+        payload.count(animation.events.len());
+        for event in animation.events.iter() {
+            payload.str(&event.name);
+            payload.record(event.time);
+        }
+    }
+
+    payload.count(model.scene.cameras.len());
+    for camera in model.scene.cameras.iter() {
+        payload.str(&camera.name);
+        payload.record(match camera.projection {
+            Projection::Perspective {
+                yfov,
+                aspect_ratio,
+                znear,
+                zfar,
+            } => CameraRecord {
+                node: camera.node as u32,
+                orthographic: 0,
+                size: [yfov, aspect_ratio.unwrap_or(f32::NAN)],
+                znear,
+                zfar: zfar.unwrap_or(f32::NAN),
+            },
+            Projection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => CameraRecord {
+                node: camera.node as u32,
+                orthographic: 1,
+                size: [xmag, ymag],
+                znear,
+                zfar,
+            },
+        });
+    }
+    payload.count(model.scene.lights.len());
+    for light in model.scene.lights.iter() {
+        payload.str(&light.name);
+        let (kind, inner_cone_angle, outer_cone_angle) = match light.kind {
+            LightKind::Directional => (0, 0.0, 0.0),
+            LightKind::Point => (1, 0.0, 0.0),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (2, inner_cone_angle, outer_cone_angle),
+        };
+        payload.record(LightRecord {
+            node: light.node as u32,
+            kind,
+            color: light.color.to_array(),
+            intensity: light.intensity,
+            range: light.range.unwrap_or(f32::NAN),
+            inner_cone_angle,
+            outer_cone_angle,
+        });
+    }
+
+    let header = Header {
+        magic: MAGIC,
+        version: BAKE_VERSION,
+        padding: 0,
+        payload_length: payload.bytes.len() as u64,
+        checksum: checksum(&payload.bytes),
+    };
+    let mut bytes = bytemuck::bytes_of(&header).to_vec();
+    bytes.extend_from_slice(&payload.bytes);
+    bytes
+}
+
+// check the header and checksum of `bytes` and read the model, its arrays borrow from `bytes`. `bytes` has
+// to start at a multiple of 16 bytes in memory, `with_aligned` copies them there when they don't
+pub fn parse(bytes: &[u8]) -> Result<BakedModel<'_>, BakeError> {
+    if !(bytes.as_ptr() as usize).is_multiple_of(ALIGNMENT) {
+        return Err(BakeError::Misaligned);
+    }
+    let header_bytes = bytes
+        .get(..size_of::<Header>())
+        .ok_or(BakeError::NotABake)?;
+    let header = bytemuck::pod_read_unaligned::<Header>(header_bytes);
+    if header.magic != MAGIC {
+        return Err(BakeError::NotABake);
+    }
+    if header.version != BAKE_VERSION {
+        return Err(BakeError::UnsupportedVersion {
+            version: header.version,
+        });
+    }
+    let payload = usize::try_from(header.payload_length)
+        .ok()
+        .and_then(|length| bytes.get(size_of::<Header>()..size_of::<Header>() + length))
+        .ok_or(BakeError::Truncated)?;
+    if checksum(payload) != header.checksum {
+        return Err(BakeError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader {
+        bytes: payload,
+        offset: 0,
+    };
+
+    // the counts come from the file, nothing is reserved for them so a corrupted one can't allocate more
+    // than the arrays it's followed by
+    let node_count = reader.count()?;
+    let mut nodes = Vec::new();
+    for _ in 0..node_count {
+        let name = reader.str()?.to_string();
+        let record = reader.record::<NodeRecord>()?;
+        let children = reader.indices(node_count, "child")?;
+        let weights = reader.array::<f32>()?.to_vec();
+        nodes.push(Node {
+            name,
+            node_type: match record.mesh {
+                NONE => NodeType::Generic,
+                mesh => NodeType::Mesh {
+                    mesh: mesh as usize,
+                },
+            },
+            transform: Transform {
+                translation: Vec3::from_array(record.translation),
+                rotation: Qua::from_array(record.rotation),
+                scale: Vec3::from_array(record.scale),
+            },
+            children,
+            parent: optional_index(record.parent, node_count, "parent")?,
+            weights,
+            skin: (record.skin != NONE).then_some(record.skin as usize),
+        });
+    }
+    let roots = reader.indices(node_count, "root")?;
+    check_hierarchy(&nodes, &roots)?;
+    let skin_count = reader.count()?;
+    let mut skins = Vec::new();
+    for _ in 0..skin_count {
+        let joints = reader.indices(node_count, "joint")?;
+        let inverse_bind_matrices = reader.array::<Mat4>()?.to_vec();
+        check(inverse_bind_matrices.len() == joints.len(), || {
+            "a skin has a different number of joints and inverse bind matrices".to_string()
+        })?;
+        skins.push(Skin {
+            joints,
+            inverse_bind_matrices,
+        });
+    }
+
+    let image_count = reader.count()?;
+    let mut images = Vec::new();
+    for _ in 0..image_count {
+        let name = reader.str()?;
+        let size = UVec2::from_array(reader.record::<[u32; 2]>()?);
+        let rgba = reader.array::<u8>()?;
+        check(
+            (size.x as usize)
+                .checked_mul(size.y as usize)
+                .and_then(|pixels| pixels.checked_mul(4))
+                == Some(rgba.len()),
+            || format!("image '{name}' doesn't have {}x{} pixels", size.x, size.y),
+        )?;
+        images.push(BakedImage { name, size, rgba });
+    }
+    let texture_count = reader.count()?;
+    let mut textures = Vec::new();
+    for _ in 0..texture_count {
+        let record = reader.record::<TextureRecord>()?;
+        check((record.image as usize) < image_count, || {
+            format!("a texture uses image {}", record.image)
+        })?;
+        textures.push(TextureInfo {
+            image: record.image as usize,
+            srgb: record.srgb != 0,
+            nearest: record.nearest != 0,
+        });
+    }
+
+    let material_count = reader.count()?;
+    let mut materials = Vec::new();
+    for _ in 0..material_count {
+        let name = reader.str()?.to_string();
+        let record = reader.record::<MaterialRecord>()?;
+        let alpha_mode = match record.alpha_mode {
+            0 => AlphaMode::Opaque,
+            1 => AlphaMode::Mask,
+            2 => AlphaMode::Blend,
+            mode => {
+                return Err(BakeError::InvalidData(format!(
+                    "material '{name}' has unknown alpha mode {mode}"
+                )))
+            }
+        };
+        materials.push(MaterialProperties {
+            base_color_factor: record.base_color_factor,
+            base_color_texture: optional_index(
+                record.base_color_texture,
+                texture_count,
+                "base color texture",
+            )?,
+            normal_texture: optional_index(record.normal_texture, texture_count, "normal texture")?,
+            name,
+            normal_scale: record.normal_scale,
+            metallic_factor: record.metallic_factor,
+            roughness_factor: record.roughness_factor,
+            alpha_mode,
+            alpha_cutoff: record.alpha_cutoff,
+            double_sided: record.double_sided != 0,
+        });
+    }
+
+    let mesh_count = reader.count()?;
+    let mut meshes = Vec::new();
+    for _ in 0..mesh_count {
+        let name = reader.str()?;
+        let morph_target_count = reader.count()?;
+        let primitive_count = reader.count()?;
+        let mut primitives = Vec::new();
+        for _ in 0..primitive_count {
+            let material = reader.count()?;
+            check(material < material_count, || {
+                format!("mesh '{name}' uses material {material}")
+            })?;
+            let vertices = reader.array::<AnimatedVertex>()?;
+            let indices = reader.array::<u32>()?;
+            check(
+                indices
+                    .iter()
+                    .all(|index| (*index as usize) < vertices.len()),
+                || format!("mesh '{name}' has indices past its vertices"),
+            )?;
+            let target_count = reader.count()?;
+            let mut morph_targets = Vec::new();
+            for _ in 0..target_count {
+                let positions = reader.array::<Vec3>()?;
+                let normals = reader.array::<Vec3>()?;
+                check(
+                    positions.len() == vertices.len() && normals.len() == vertices.len(),
+                    || format!("a morph target of mesh '{name}' doesn't cover every vertex"),
+                )?;
+                morph_targets.push((positions, normals));
+            }
+            primitives.push(BakedPrimitive {
+                vertices,
+                indices,
+                morph_targets,
+                material,
+            });
+        }
+        meshes.push(BakedMesh {
+            name,
+            morph_target_count,
+            primitives,
+        });
+    }
+    for node in nodes.iter() {
+        if let NodeType::Mesh { mesh } = node.node_type {
+            check(mesh < mesh_count, || {
+                format!("node '{}' uses mesh {mesh}", node.name)
+            })?;
+        }
+        if let Some(skin) = node.skin {
+            check(skin < skin_count, || {
+                format!("node '{}' uses skin {skin}", node.name)
+            })?;
+        }
+        // like `read_primitive` only joints with a weight have to be in the skin
+        if let (NodeType::Mesh { mesh }, Some(skin)) = (&node.node_type, node.skin) {
+            let joint_count = skins[skin].joints.len();
+            check(
+                meshes[*mesh]
+                    .primitives
+                    .iter()
+                    .flat_map(|primitive| primitive.vertices.iter())
+                    .flat_map(|vertex| vertex.joints.iter().zip(vertex.weights))
+                    .all(|(joint, weight)| weight <= 0.0 || (*joint as usize) < joint_count),
+                || {
+                    format!(
+                        "mesh '{}' of node '{}' uses joints past the {joint_count} of its skin",
+                        meshes[*mesh].name, node.name
+                    )
+                },
+            )?;
+        }
+    }
+
+    let animation_count = reader.count()?;
+    let mut animations = Vec::new();
+    let mut clip_bounds = Vec::new();
+    for _ in 0..animation_count {
+        let name = reader.str()?;
+        let length = reader.record::<f32>()?;
+        clip_bounds.push(reader.record::<Aabb>()?);
+        let track_count = reader.count()?;
+        let mut tracks = Vec::new();
+        for _ in 0..track_count {
+            let record = reader.record::<TrackRecord>()?;
+            check((record.target as usize) < node_count, || {
+                format!(
+                    "a track of animation '{name}' targets node {}",
+                    record.target
+                )
+            })?;
+            let interpolation = match record.interpolation {
+                0 => Interpolation::Step,
+                1 => Interpolation::Linear,
+                2 => Interpolation::CubicSpline,
+                interpolation => {
+                    return Err(BakeError::InvalidData(format!(
+                        "a track of animation '{name}' has unknown interpolation {interpolation}"
+                    )))
+                }
+            };
+            let timestamps = reader.array::<f32>()?;
+            let (keyframes, values) = match record.kind {
+                0 => {
+                    let values = reader.array()?;
+                    (BakedKeyframes::Rotations(values), values.len())
+                }
+                1 => {
+                    let values = reader.array()?;
+                    (BakedKeyframes::Translations(values), values.len())
+                }
+                2 => {
+                    let values = reader.array()?;
+                    (BakedKeyframes::Scales(values), values.len())
+                }
+                3 => {
+                    let values = reader.array()?;
+                    (BakedKeyframes::MorphTargetWeights(values), values.len())
+                }
+                4 => {
+                    let values = reader.array()?;
+                    (BakedKeyframes::QuantizedRotations(values), values.len())
+                }
+                kind => {
+                    return Err(BakeError::InvalidData(format!(
+                        "a track of animation '{name}' has unknown keyframes {kind}"
+                    )))
+                }
+            };
+            // sampling indexes values by keyframe, like `Animation::from_gltf` checks
+            let per_keyframe = timestamps.len()
+                * match interpolation {
+                    Interpolation::CubicSpline => 3,
+                    _ => 1,
+                };
+            let valid = match keyframes {
+                BakedKeyframes::MorphTargetWeights(_) if per_keyframe > 0 => {
+                    values.is_multiple_of(per_keyframe)
+                }
+                _ => values == per_keyframe,
+            };
+            check(valid, || {
+                format!(
+                    "a track of animation '{name}' has {values} values for {} timestamps",
+                    timestamps.len()
+                )
+            })?;
+            tracks.push(BakedTrack {
+                target: record.target as usize,
+                interpolation,
+                timestamps,
+                keyframes,
+            });
+        }
+        let event_count = reader.count()?;
+        let mut events = Vec::new();
+        for _ in 0..event_count {
+            events.push(AnimationEvent {
+                name: reader.str()?.to_string(),
+                time: reader.record()?,
+            });
+        }
+        animations.push(BakedAnimation {
+            name,
+            length,
+            tracks,
+            events,
+        });
+    }
+
+    let camera_count = reader.count()?;
+    let mut cameras = Vec::new();
+    for _ in 0..camera_count {
+        let name = reader.str()?.to_string();
+        let record = reader.record::<CameraRecord>()?;
+        check((record.node as usize) < node_count, || {
+            format!("camera '{name}' is on node {}", record.node)
+        })?;
+        let projection = if record.orthographic != 0 {
+            Projection::Orthographic {
+                xmag: record.size[0],
+                ymag: record.size[1],
+                znear: record.znear,
+                zfar: record.zfar,
+            }
+        } else {
+            Projection::Perspective {
+                yfov: record.size[0],
+                aspect_ratio: Some(record.size[1]).filter(|value| !value.is_nan()),
+                znear: record.znear,
+                zfar: Some(record.zfar).filter(|value| !value.is_nan()),
+            }
+        };
+        cameras.push(SceneCamera {
+            name,
+            node: record.node as usize,
+            projection,
+        });
+    }
+    let light_count = reader.count()?;
+    let mut lights = Vec::new();
+    for _ in 0..light_count {
+        let name = reader.str()?.to_string();
+        let record = reader.record::<LightRecord>()?;
+        check((record.node as usize) < node_count, || {
+            format!("light '{name}' is on node {}", record.node)
+        })?;
+        let kind = match record.kind {
+            0 => LightKind::Directional,
+            1 => LightKind::Point,
+            2 => LightKind::Spot {
+                inner_cone_angle: record.inner_cone_angle,
+                outer_cone_angle: record.outer_cone_angle,
+            },
+            kind => {
+                return Err(BakeError::InvalidData(format!(
+                    "light '{name}' has unknown kind {kind}"
+                )))
+            }
+        };
+        lights.push(SceneLight {
+            name,
+            node: record.node as usize,
+            kind,
+            color: Vec3::from_array(record.color),
+            intensity: record.intensity,
+            range: Some(record.range).filter(|value| !value.is_nan()),
+        });
+    }
+
+    Ok(BakedModel {
+        skeleton: Skeleton {
+            nodes,
+            roots,
+            skins,
+        },
+        images,
+        textures,
+        materials,
+        meshes,
+        animations,
+        clip_bounds,
+        scene: Scene { cameras, lights },
+    })
+}
+
+// calls `f` with `bytes` at an address `parse` accepts, they're only copied if they aren't there already
+pub fn with_aligned<R>(bytes: &[u8], f: impl FnOnce(&[u8]) -> R) -> R {
+    if (bytes.as_ptr() as usize).is_multiple_of(ALIGNMENT) {
+        return f(bytes);
+    }
+
+    let mut blocks = vec![Block([0; ALIGNMENT]); bytes.len().div_ceil(ALIGNMENT)];
+    bytemuck::cast_slice_mut::<_, u8>(&mut blocks)[..bytes.len()].copy_from_slice(bytes);
+    f(&bytemuck::cast_slice(&blocks)[..bytes.len()])
+}
+
+impl BakedImage<'_> {
+    pub fn from_image(image: &Image) -> BakedImage<'_> {
+        BakedImage {
+            name: &image.name,
+            size: image.size,
+            rgba: &image.rgba,
+        }
+    }
+
+    pub fn to_image(&self) -> Image {
+        Image {
+            name: self.name.to_string(),
+            size: self.size,
+            rgba: self.rgba.to_vec(),
+        }
+    }
+}
+
+impl BakedMesh<'_> {
+    pub fn from_mesh(mesh: &Mesh) -> BakedMesh<'_> {
+        BakedMesh {
+            name: &mesh.name,
+            morph_target_count: mesh.morph_target_count,
+            primitives: mesh
+                .primitives
+                .iter()
+                .map(BakedPrimitive::from_primitive)
+                .collect(),
+        }
+    }
+}
+
+impl BakedAnimation<'_> {
+    pub fn from_animation(animation: &Animation) -> BakedAnimation<'_> {
+        BakedAnimation {
+            name: &animation.name,
+            length: animation.length,
+            tracks: animation
+                .tracks
+                .iter()
+                .map(|track| BakedTrack {
+                    target: track.target,
+                    interpolation: track.interpolation,
+                    timestamps: &track.timestamps,
+                    keyframes: match &track.keyframes {
+                        Keyframes::Rotations(values) => BakedKeyframes::Rotations(values),
+                        Keyframes::Translations(values) => BakedKeyframes::Translations(values),
+                        Keyframes::Scales(values) => BakedKeyframes::Scales(values),
+                        Keyframes::MorphTargetWeights(values) => {
+                            BakedKeyframes::MorphTargetWeights(values)
+                        }
+                        Keyframes::QuantizedRotations(values) => {
+                            BakedKeyframes::QuantizedRotations(values)
+                        }
+                    },
+                })
+                .collect(),
+            events: animation.events.clone(),
+        }
+    }
+
+    pub fn to_animation(&self) -> Animation {
+        Animation {
+            name: self.name.to_string(),
+            length: self.length,
+            tracks: self
+                .tracks
+                .iter()
+                .map(|track| Track {
+                    keyframes: match track.keyframes {
+                        BakedKeyframes::Rotations(values) => Keyframes::Rotations(values.to_vec()),
+                        BakedKeyframes::Translations(values) => {
+                            Keyframes::Translations(values.to_vec())
+                        }
+                        BakedKeyframes::Scales(values) => Keyframes::Scales(values.to_vec()),
+                        BakedKeyframes::MorphTargetWeights(values) => {
+                            Keyframes::MorphTargetWeights(values.to_vec())
+                        }
+                        BakedKeyframes::QuantizedRotations(values) => {
+                            Keyframes::QuantizedRotations(values.to_vec())
+                        }
+                    },
+                    interpolation: track.interpolation,
+                    target: track.target,
+                    timestamps: track.timestamps.to_vec(),
+                })
+                .collect(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl BakedPrimitive<'_> {
+    pub fn from_primitive(primitive: &Primitive) -> BakedPrimitive<'_> {
+        BakedPrimitive {
+            vertices: &primitive.vertices,
+            indices: &primitive.indices,
+            morph_targets: primitive
+                .morph_targets
+                .iter()
+                .map(|target| (target.positions.as_slice(), target.normals.as_slice()))
+                .collect(),
+            material: primitive.material,
+        }
+    }
+
+    pub fn to_morph_targets(&self) -> Vec<MorphTarget> {
+        self.morph_targets
+            .iter()
+            .map(|(positions, normals)| MorphTarget {
+                positions: positions.to_vec(),
+                normals: normals.to_vec(),
+            })
+            .collect()
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Block([u8; ALIGNMENT]);
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn record<T: bytemuck::Pod>(&mut self, value: T) {
+        self.bytes.extend_from_slice(bytemuck::bytes_of(&value));
+    }
+
+    fn count(&mut self, count: usize) {
+        self.record(count as u32);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.count(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    // the payload starts right after the header, which is a multiple of `ALIGNMENT` long
+    fn array<T: bytemuck::Pod>(&mut self, values: &[T]) {
+        self.count(values.len());
+        self.bytes
+            .resize(self.bytes.len().next_multiple_of(ALIGNMENT), 0);
+        self.bytes.extend_from_slice(bytemuck::cast_slice(values));
+    }
+
+    fn indices(&mut self, indices: &[usize]) {
+        let indices = indices
+            .iter()
+            .map(|index| *index as u32)
+            .collect::<Vec<_>>();
+        self.array(&indices);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], BakeError> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(BakeError::Truncated)?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn record<T: bytemuck::Pod>(&mut self) -> Result<T, BakeError> {
+        Ok(bytemuck::pod_read_unaligned(self.take(size_of::<T>())?))
+    }
+
+    fn count(&mut self) -> Result<usize, BakeError> {
+        Ok(self.record::<u32>()? as usize)
+    }
+
+    fn str(&mut self) -> Result<&'a str, BakeError> {
+        let length = self.count()?;
+        std::str::from_utf8(self.take(length)?)
+            .map_err(|error| BakeError::InvalidData(error.to_string()))
+    }
+
+    fn array<T: bytemuck::Pod>(&mut self) -> Result<&'a [T], BakeError> {
+        let count = self.count()?;
+        self.offset = self.offset.next_multiple_of(ALIGNMENT);
+        let length = count
+            .checked_mul(size_of::<T>())
+            .ok_or(BakeError::Truncated)?;
+        bytemuck::try_cast_slice(self.take(length)?).map_err(|_| BakeError::Misaligned)
+    }
+
+    // node indices below `count`
+    fn indices(&mut self, count: usize, what: &str) -> Result<Vec<usize>, BakeError> {
+        self.array::<u32>()?
+            .iter()
+            .map(|index| {
+                optional_index(*index, count, what)?
+                    .ok_or_else(|| BakeError::InvalidData(format!("missing {what}")))
+            })
+            .collect()
+    }
+}
+
+// every child lists its parent and the other way around, the roots are the nodes without one and there
+// are no cycles, so walking down from the roots reaches every node exactly once
+fn check_hierarchy(nodes: &[Node], roots: &[usize]) -> Result<(), BakeError> {
+    for (i, node) in nodes.iter().enumerate() {
+        for child in node.children.iter() {
+            check(nodes[*child].parent == Some(i), || {
+                format!(
+                    "node '{}' is a child of '{}' but has another parent",
+                    nodes[*child].name, node.name
+                )
+            })?;
+        }
+        if let Some(parent) = node.parent {
+            check(nodes[parent].children.contains(&i), || {
+                format!(
+                    "node '{}' isn't a child of its parent '{}'",
+                    node.name, nodes[parent].name
+                )
+            })?;
+        }
+    }
+    check(
+        nodes.iter().map(|node| node.children.len()).sum::<usize>()
+            == nodes.iter().filter(|node| node.parent.is_some()).count(),
+        || "a node is listed as a child more than once".to_string(),
+    )?;
+    check(
+        roots
+            .iter()
+            .copied()
+            .eq((0..nodes.len()).filter(|i| nodes[*i].parent.is_none())),
+        || "the roots aren't the nodes without a parent".to_string(),
+    )?;
+
+    // with the links agreeing, nodes whose parents go around in a cycle are the ones that can't be reached
+    let mut reached = 0;
+    let mut stack = roots.to_vec();
+    while let Some(node) = stack.pop() {
+        reached += 1;
+        stack.extend(nodes[node].children.iter().copied());
+    }
+    check(reached == nodes.len(), || {
+        "the parents of some nodes form a cycle".to_string()
+    })
+}
+
+fn optional_index(index: u32, count: usize, what: &str) -> Result<Option<usize>, BakeError> {
+    if index == NONE {
+        return Ok(None);
+    }
+    check((index as usize) < count, || {
+        format!("{what} {index} is out of range")
+    })?;
+    Ok(Some(index as usize))
+}
+
+fn check(valid: bool, error: impl FnOnce() -> String) -> Result<(), BakeError> {
+    if valid {
+        Ok(())
+    } else {
+        Err(BakeError::InvalidData(error()))
+    }
+}
+
+// 64 bit FNV-1a
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        animated_object::load_gltf,
+        mesh::{read_morph_targets, read_primitive},
+    };
+
+    fn bytes<T: bytemuck::Pod>(values: &[T]) -> &[u8] {
+        bytemuck::cast_slice(values)
+    }
+
+    fn keyframe_bytes<'a>(keyframes: &BakedKeyframes<'a>) -> (u32, &'a [u8]) {
+        match *keyframes {
+            BakedKeyframes::Rotations(values) => (0, bytes(values)),
+            BakedKeyframes::Translations(values) => (1, bytes(values)),
+            BakedKeyframes::Scales(values) => (2, bytes(values)),
+            BakedKeyframes::MorphTargetWeights(values) => (3, bytes(values)),
+            BakedKeyframes::QuantizedRotations(values) => (4, bytes(values)),
+        }
+    }
+
+    fn assert_same_skeleton(a: &Skeleton, b: &Skeleton) {
+        assert_eq!(a.nodes.len(), b.nodes.len());
+        for (a, b) in a.nodes.iter().zip(b.nodes.iter()) {
+            assert_eq!(a.name, b.name);
+            match (&a.node_type, &b.node_type) {
+                (NodeType::Mesh { mesh: a }, NodeType::Mesh { mesh: b }) => assert_eq!(a, b),
+                (NodeType::Generic, NodeType::Generic) => {}
+                (a, b) => panic!("{a:?} != {b:?}"),
+            }
+            assert_eq!(a.transform.translation, b.transform.translation);
+            assert_eq!(a.transform.rotation, b.transform.rotation);
+            assert_eq!(a.transform.scale, b.transform.scale);
+            assert_eq!(a.children, b.children);
+            assert_eq!(a.parent, b.parent);
+            assert_eq!(a.weights, b.weights);
+            assert_eq!(a.skin, b.skin);
+        }
+        assert_eq!(a.roots, b.roots);
+        assert_eq!(a.skins.len(), b.skins.len());
+        for (a, b) in a.skins.iter().zip(b.skins.iter()) {
+            assert_eq!(a.joints, b.joints);
+            assert_eq!(
+                bytes(&a.inverse_bind_matrices),
+                bytes(&b.inverse_bind_matrices)
+            );
+        }
+    }
+
+    fn assert_same_animation(a: &BakedAnimation, b: &BakedAnimation) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.length, b.length);
+        assert_eq!(a.tracks.len(), b.tracks.len());
+        for (a, b) in a.tracks.iter().zip(b.tracks.iter()) {
+            assert_eq!(a.target, b.target);
+            assert_eq!(a.interpolation, b.interpolation);
+            assert_eq!(a.timestamps, b.timestamps);
+            assert_eq!(keyframe_bytes(&a.keyframes), keyframe_bytes(&b.keyframes));
+        }
+        assert_eq!(a.events.len(), b.events.len());
+        for (a, b) in a.events.iter().zip(b.events.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.time, b.time);
+        }
+    }
+
+    fn assert_same(a: &BakedModel, b: &BakedModel) {
+        assert_same_skeleton(&a.skeleton, &b.skeleton);
+
+        assert_eq!(a.images.len(), b.images.len());
+        for (a, b) in a.images.iter().zip(b.images.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.size, b.size);
+            assert_eq!(a.rgba, b.rgba);
+        }
+        assert_eq!(a.textures, b.textures);
+
+        assert_eq!(a.materials.len(), b.materials.len());
+        for (a, b) in a.materials.iter().zip(b.materials.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.base_color_factor, b.base_color_factor);
+            assert_eq!(a.base_color_texture, b.base_color_texture);
+            assert_eq!(a.normal_texture, b.normal_texture);
+            assert_eq!(a.normal_scale, b.normal_scale);
+            assert_eq!(a.metallic_factor, b.metallic_factor);
+            assert_eq!(a.roughness_factor, b.roughness_factor);
+            assert_eq!(a.alpha_mode, b.alpha_mode);
+            assert_eq!(a.alpha_cutoff, b.alpha_cutoff);
+            assert_eq!(a.double_sided, b.double_sided);
+        }
+
+        assert_eq!(a.meshes.len(), b.meshes.len());
+        for (a, b) in a.meshes.iter().zip(b.meshes.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.morph_target_count, b.morph_target_count);
+            assert_eq!(a.primitives.len(), b.primitives.len());
+            for (a, b) in a.primitives.iter().zip(b.primitives.iter()) {
+                assert_eq!(bytes(a.vertices), bytes(b.vertices));
+                assert_eq!(a.indices, b.indices);
+                assert_eq!(a.morph_targets, b.morph_targets);
+                assert_eq!(a.material, b.material);
+            }
+        }
+
+        assert_eq!(a.animations.len(), b.animations.len());
+        for (a, b) in a.animations.iter().zip(b.animations.iter()) {
+            assert_same_animation(a, b);
+        }
+        assert_eq!(a.clip_bounds, b.clip_bounds);
+
+        assert_eq!(a.scene.cameras.len(), b.scene.cameras.len());
+        for (a, b) in a.scene.cameras.iter().zip(b.scene.cameras.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.node, b.node);
+            assert_eq!(format!("{:?}", a.projection), format!("{:?}", b.projection));
+        }
+        assert_eq!(a.scene.lights.len(), b.scene.lights.len());
+        for (a, b) in a.scene.lights.iter().zip(b.scene.lights.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.node, b.node);
+            assert_eq!(a.kind, b.kind);
+            assert_eq!(a.color, b.color);
+            assert_eq!(a.intensity, b.intensity);
+            assert_eq!(a.range, b.range);
+        }
+    }
+
+    // the header's checksum after editing the payload
+    fn fix_checksum(bytes: &mut [u8]) {
+        let payload_checksum = checksum(&bytes[size_of::<Header>()..]);
+        let mut header = bytemuck::pod_read_unaligned::<Header>(&bytes[..size_of::<Header>()]);
+        header.checksum = payload_checksum;
+        bytes[..size_of::<Header>()].copy_from_slice(bytemuck::bytes_of(&header));
+    }
+
+    #[test]
+    fn baked_models_load_back_identically() {
+        let (gltf, buffers) = load_gltf(Path::new("res/character.gltf")).unwrap();
+        let skeleton = Skeleton::from_gltf(&gltf, &buffers).unwrap();
+        let mut animations = gltf
+            .animations()
+            .map(|animation| Animation::from_gltf(animation, &buffers))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        animations[0].quantize_rotations();
+        let meshes = gltf
+            .meshes()
+            .map(|mesh| {
+                let joint_count = gltf
+                    .nodes()
+                    .filter(|node| node.mesh().is_some_and(|m| m.index() == mesh.index()))
+                    .filter_map(|node| node.skin())
+                    .map(|skin| skin.joints().len())
+                    .min();
+                let primitives = mesh
+                    .primitives()
+                    .map(|primitive| {
+                        let (vertices, indices) =
+                            read_primitive(&primitive, &buffers, joint_count, "").unwrap();
+                        let morph_targets =
+                            read_morph_targets(&primitive, &buffers, vertices.len());
+                        (vertices, indices, morph_targets)
+                    })
+                    .collect::<Vec<_>>();
+                (mesh.name().unwrap_or("Unnamed").to_string(), primitives)
+            })
+            .collect::<Vec<_>>();
+
+        // the character has no textures, cameras or lights
+        let image = Image {
+            name: "checker".to_string(),
+            size: UVec2::new(2, 1),
+            rgba: vec![255, 0, 0, 255, 0, 0, 255, 128],
+        };
+        let mut materials = gltf
+            .materials()
+            .map(MaterialProperties::from)
+            .collect::<Vec<_>>();
+        materials.push(MaterialProperties {
+            name: "textured".to_string(),
+            base_color_texture: Some(0),
+            normal_texture: Some(1),
+            alpha_mode: AlphaMode::Mask,
+            ..Default::default()
+        });
+        let mut scene = Scene::from_gltf(&gltf);
+        scene.cameras.push(SceneCamera {
+            name: "camera".to_string(),
+            node: 1,
+            projection: Projection::Perspective {
+                yfov: 0.8,
+                aspect_ratio: None,
+                znear: 0.1,
+                zfar: Some(100.0),
+            },
+        });
+        scene.lights.push(SceneLight {
+            name: "spot".to_string(),
+            node: 2,
+            kind: LightKind::Spot {
+                inner_cone_angle: 0.2,
+                outer_cone_angle: 0.6,
+            },
+            color: Vec3::new(1.0, 0.5, 0.25),
+            intensity: 40.0,
+            range: None,
+        });
+
+        let model = BakedModel {
+            skeleton,
+            images: vec![BakedImage::from_image(&image)],
+            textures: vec![
+                TextureInfo {
+                    image: 0,
+                    srgb: true,
+                    nearest: true,
+                },
+                TextureInfo {
+                    image: 0,
+                    srgb: false,
+                    nearest: false,
+                },
+            ],
+            materials,
+            meshes: meshes
+                .iter()
+                .map(|(name, primitives)| BakedMesh {
+                    name,
+                    morph_target_count: primitives
+                        .first()
+                        .map_or(0, |(_, _, targets)| targets.len()),
+                    primitives: primitives
+                        .iter()
+                        .map(|(vertices, indices, morph_targets)| BakedPrimitive {
+                            vertices,
+                            indices,
+                            morph_targets: morph_targets
+                                .iter()
+                                .map(|target| {
+                                    (target.positions.as_slice(), target.normals.as_slice())
+                                })
+                                .collect(),
+                            material: 0,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            animations: animations
+                .iter()
+                .map(BakedAnimation::from_animation)
+                .collect(),
+            clip_bounds: (0..animations.len())
+                .map(|i| Aabb {
+                    min: Vec3::splat(-(i as f32)),
+                    max: Vec3::ONE,
+                })
+                .collect(),
+            scene,
+        };
+
+        // the joints and weights of the skinned character are baked too
+        let mut vertices = model
+            .meshes
+            .iter()
+            .flat_map(|mesh| mesh.primitives.iter())
+            .flat_map(|primitive| primitive.vertices.iter());
+        assert!(vertices.any(|vertex| vertex.joints != [0; 4] && vertex.weights[0] > 0.0));
+        assert!(model.skeleton.nodes.iter().any(|node| node.skin.is_some()));
+
+        let baked = bake(&model);
+        with_aligned(&baked, |bytes| {
+            let parsed = parse(bytes).unwrap();
+            assert_same(&model, &parsed);
+
+            // the clips the object is made from are the ones that were baked
+            for (animation, parsed) in animations.iter().zip(parsed.animations.iter()) {
+                assert_same_animation(
+                    &BakedAnimation::from_animation(animation),
+                    &BakedAnimation::from_animation(&parsed.to_animation()),
+                );
+            }
+
+            // the arrays point into the bytes instead of being copied
+            let vertices = parsed.meshes[0].primitives[0].vertices.as_ptr() as *const u8;
+            assert!(bytes.as_ptr_range().contains(&vertices));
+
+            // and bake to the same file again
+            assert!(bake(&parsed) == bytes);
+        });
+    }
+
+    fn node(name: &str, children: Vec<usize>, parent: Option<usize>) -> Node {
+        Node {
+            name: name.to_string(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation: Vec3::ZERO,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        }
+    }
+
+    fn model(skeleton: Skeleton) -> BakedModel<'static> {
+        BakedModel {
+            skeleton,
+            images: Vec::new(),
+            textures: Vec::new(),
+            materials: Vec::new(),
+            meshes: Vec::new(),
+            animations: Vec::new(),
+            clip_bounds: Vec::new(),
+            scene: Scene::default(),
+        }
+    }
+
+    fn parse_error(model: &BakedModel) -> String {
+        with_aligned(&bake(model), |bytes| match parse(bytes) {
+            Err(BakeError::InvalidData(error)) => error,
+            result => panic!("expected invalid data, got {result:?}"),
+        })
+    }
+
+    #[test]
+    fn broken_hierarchies_are_rejected() {
+        let skeleton = |nodes: Vec<Node>, roots: Vec<usize>| Skeleton {
+            nodes,
+            roots,
+            skins: Vec::new(),
+        };
+
+        // a child that doesn't point back at its parent
+        let error = parse_error(&model(skeleton(
+            vec![node("a", vec![1], None), node("b", Vec::new(), None)],
+            vec![0, 1],
+        )));
+        assert!(error.contains("'b' is a child of 'a'"), "{error}");
+
+        // a node listed by the parent twice
+        let error = parse_error(&model(skeleton(
+            vec![node("a", vec![1, 1], None), node("b", Vec::new(), Some(0))],
+            vec![0],
+        )));
+        assert!(error.contains("more than once"), "{error}");
+
+        // a node that isn't a root
+        let error = parse_error(&model(skeleton(
+            vec![node("a", Vec::new(), None), node("b", Vec::new(), None)],
+            vec![0],
+        )));
+        assert!(error.contains("roots"), "{error}");
+
+        // two nodes that are each other's parent
+        let error = parse_error(&model(skeleton(
+            vec![
+                node("root", Vec::new(), None),
+                node("a", vec![2], Some(2)),
+                node("b", vec![1], Some(1)),
+            ],
+            vec![0],
+        )));
+        assert!(error.contains("cycle"), "{error}");
+    }
+
+    #[test]
+    fn joints_past_the_skin_are_rejected() {
+        let mut skinned = node("body", Vec::new(), Some(0));
+        skinned.node_type = NodeType::Mesh { mesh: 0 };
+        skinned.skin = Some(0);
+        let mut vertices = [AnimatedVertex::default(); 3];
+        vertices[1].joints = [0, 1, 0, 0];
+        vertices[1].weights = [0.5, 0.5, 0.0, 0.0];
+        // unused influences aren't looked at
+        vertices[2].joints = [0, 9, 0, 0];
+        vertices[2].weights = [1.0, 0.0, 0.0, 0.0];
+        let material = [MaterialProperties::default()];
+
+        let mut model = model(Skeleton {
+            nodes: vec![node("hips", vec![1], None), skinned],
+            roots: vec![0],
+            skins: vec![Skin {
+                joints: vec![0, 0],
+                inverse_bind_matrices: vec![Mat4::IDENTITY; 2],
+            }],
+        });
+        model.materials = material.to_vec();
+        model.meshes = vec![BakedMesh {
+            name: "body",
+            morph_target_count: 0,
+            primitives: vec![BakedPrimitive {
+                vertices: &vertices,
+                indices: &[0, 1, 2],
+                morph_targets: Vec::new(),
+                material: 0,
+            }],
+        }];
+        with_aligned(&bake(&model), |bytes| assert!(parse(bytes).is_ok()));
+
+        model.skeleton.skins[0].joints.pop();
+        model.skeleton.skins[0].inverse_bind_matrices.pop();
+        let error = parse_error(&model);
+        assert!(
+            error.contains("mesh 'body' of node 'body' uses joints past the 1 of its skin"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn unknown_interpolations_are_rejected() {
+        let animation = Animation {
+            name: "walk".to_string(),
+            length: 1.0,
+            tracks: vec![Track {
+                keyframes: Keyframes::Translations(vec![Vec3::ZERO, Vec3::ONE]),
+                interpolation: Interpolation::Linear,
+                target: 0,
+                timestamps: vec![0.0, 1.0],
+            }],
+            events: Vec::new(),
+        };
+        let mut model = model(Skeleton {
+            nodes: vec![node("root", Vec::new(), None)],
+            roots: vec![0],
+            skins: Vec::new(),
+        });
+        model.animations = vec![BakedAnimation::from_animation(&animation)];
+        model.clip_bounds = vec![Aabb::EMPTY];
+        let mut baked = bake(&model);
+
+        let record = TrackRecord {
+            target: 0,
+            interpolation: 1,
+            kind: 1,
+        };
+        let offset = baked
+            .windows(size_of::<TrackRecord>())
+            .position(|window| window == bytemuck::bytes_of(&record))
+            .unwrap();
+        baked[offset + 4..offset + 8].copy_from_slice(&7u32.to_le_bytes());
+        fix_checksum(&mut baked);
+
+        with_aligned(&baked, |bytes| {
+            assert!(matches!(
+                parse(bytes),
+                Err(BakeError::InvalidData(error)) if error.contains("unknown interpolation 7")
+            ));
+        });
+    }
+}
@@ -0,0 +1,185 @@
+use std::{hint::black_box, time::Instant};
+
+use crate::{
+    animated_object::{load_gltf, resolve_path},
+    animation::{keyframe_index, Animation, Interpolation, Keyframe, Keyframes, Track},
+    compression::ReductionTolerance,
+    error::GltfLoadError,
+    skeleton::Skeleton,
+};
+
+// samples per clip, spread evenly over its length
+const SAMPLES: usize = 1000;
+// motion capture is usually recorded at 120 Hz
+const MOCAP_RATE: f32 = 120.0;
+
+// `animations --bench [file]` times keyframe lookup and sampling of every clip of a model, with the
+// linear search keyframes used to be found with and the binary search they're found with now. The
+// clips are also resampled to 120 Hz, like long motion capture clips
+pub fn run(path: &str) -> Result<(), GltfLoadError> {
+    let (gltf, buffers) = load_gltf(&resolve_path(path))?;
+    let skeleton = Skeleton::from_gltf(&gltf, &buffers)?;
+    let animations = gltf
+        .animations()
+        .map(|animation| Animation::from_gltf(animation, &buffers))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    println!(
+        "{:<56} {:>8} {:>10} {:>14} {:>14} {:>14} {:>14}",
+        "clip", "tracks", "keyframes", "linear ns", "binary ns", "pose us", "joints us"
+    );
+    for animation in animations.iter() {
+        bench_animation(&skeleton, animation, &animation.name);
+
+        let mut mocap = resample(animation, MOCAP_RATE);
+        bench_animation(
+            &skeleton,
+            &mocap,
+            &format!("{} @{MOCAP_RATE}Hz", animation.name),
+        );
+
+        // the same clip after the compression passes
+        let keyframes = mocap.keyframe_count();
+        mocap.reduce_keyframes(ReductionTolerance::default());
+        mocap.quantize_rotations();
+        bench_animation(
+            &skeleton,
+            &mocap,
+            &format!(
+                "{} @{MOCAP_RATE}Hz reduced {keyframes} -> {}",
+                animation.name,
+                mocap.keyframe_count()
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+fn bench_animation(skeleton: &Skeleton, animation: &Animation, name: &str) {
+    let times = (0..SAMPLES)
+        .map(|i| animation.length * i as f32 / SAMPLES as f32)
+        .collect::<Vec<_>>();
+    let lookups = (SAMPLES * animation.tracks.len()).max(1) as f64;
+
+    // the lookup `sample` did before
+    let linear = time(|| {
+        for time in times.iter() {
+            for track in animation.tracks.iter() {
+                black_box(
+                    track
+                        .timestamps
+                        .iter()
+                        .rposition(|timestamp| *timestamp <= *time)
+                        .unwrap_or(0),
+                );
+            }
+        }
+    });
+    let binary = time(|| {
+        for time in times.iter() {
+            for track in animation.tracks.iter() {
+                black_box(keyframe_index(&track.timestamps, *time));
+            }
+        }
+    });
+
+    let mut pose = skeleton.rest_pose();
+    let sampling = time(|| {
+        for time in times.iter() {
+            pose.apply(animation, *time);
+            skeleton.update_world_matrices(&mut pose);
+            black_box(&pose);
+        }
+    });
+    // the same from the rest pose every time, up to the joint matrices of the first skin
+    let joints = time(|| {
+        for time in times.iter() {
+            if !skeleton.skins.is_empty() {
+                black_box(skeleton.sample(animation, 0, *time));
+            }
+        }
+    });
+
+    let keyframes = animation
+        .tracks
+        .iter()
+        .map(|track| track.timestamps.len())
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:<56} {:>8} {:>10} {:>14.1} {:>14.1} {:>14.2} {:>14.2}",
+        name,
+        animation.tracks.len(),
+        keyframes,
+        linear * 1e9 / lookups,
+        binary * 1e9 / lookups,
+        sampling * 1e6 / SAMPLES as f64,
+        joints * 1e6 / SAMPLES as f64
+    );
+}
+
+// seconds `f` takes, the fastest of a few runs so other work on the machine doesn't count
+fn time(mut f: impl FnMut()) -> f64 {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed().as_secs_f64()
+        })
+        .fold(f64::MAX, f64::min)
+}
+
+// `animation` with linear keyframes every 1 / `rate` seconds, morph weight tracks are left out
+fn resample(animation: &Animation, rate: f32) -> Animation {
+    let count = (animation.length * rate).ceil() as usize + 1;
+    let timestamps = (0..count)
+        .map(|i| (i as f32 / rate).min(animation.length))
+        .collect::<Vec<_>>();
+
+    let tracks = animation
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            let keyframes = match &track.keyframes {
+                Keyframes::Rotations(values) => {
+                    Keyframes::Rotations(resample_track(track, &timestamps, values))
+                }
+                Keyframes::Translations(values) => {
+                    Keyframes::Translations(resample_track(track, &timestamps, values))
+                }
+                Keyframes::Scales(values) => {
+                    Keyframes::Scales(resample_track(track, &timestamps, values))
+                }
+                Keyframes::QuantizedRotations(values) => {
+                    let values = values
+                        .iter()
+                        .map(|value| value.decode())
+                        .collect::<Vec<_>>();
+                    Keyframes::Rotations(resample_track(track, &timestamps, &values))
+                }
+                Keyframes::MorphTargetWeights(_) => return None,
+            };
+            Some(Track {
+                keyframes,
+                interpolation: Interpolation::Linear,
+                target: track.target,
+                timestamps: timestamps.clone(),
+            })
+        })
+        .collect();
+
+    Animation {
+        name: animation.name.clone(),
+        length: animation.length,
+        tracks,
+        events: Vec::new(),
+    }
+}
+
+fn resample_track<T: Keyframe>(track: &Track, timestamps: &[f32], values: &[T]) -> Vec<T> {
+    timestamps
+        .iter()
+        .filter_map(|time| track.sample(*time, |i| values[i]))
+        .collect()
+}
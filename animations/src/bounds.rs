@@ -0,0 +1,364 @@
+use jandering_engine::types::{Mat4, Vec3};
+
+use crate::{
+    animation::Animation,
+    mesh::Mesh,
+    skeleton::{NodeType, Pose, Skeleton},
+};
+
+// samples per keyframe interval when measuring clip bounds
+const CLIP_BOUNDS_SUBDIVISIONS: usize = 4;
+// fraction of the clip bounds' size they're grown by on every side
+const CLIP_BOUNDS_MARGIN: f32 = 0.05;
+
+// axis aligned bounding box in world space, `Aabb::EMPTY` contains nothing
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::splat(f32::MAX),
+        max: Vec3::splat(f32::MIN),
+    };
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        if self.is_empty() {
+            Vec3::ZERO
+        } else {
+            self.max - self.min
+        }
+    }
+
+    pub fn with_point(self, point: Vec3) -> Aabb {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn with_sphere(self, center: Vec3, radius: f32) -> Aabb {
+        Aabb {
+            min: self.min.min(center - radius),
+            max: self.max.max(center + radius),
+        }
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    // the box around this one after transforming it
+    pub fn transform(&self, matrix: Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+
+        (0..8).fold(Aabb::EMPTY, |aabb, corner| {
+            let select = |bit: usize, axis: usize| {
+                if corner & bit == 0 {
+                    self.min[axis]
+                } else {
+                    self.max[axis]
+                }
+            };
+            let corner = Vec3::new(select(1, 0), select(2, 1), select(4, 2));
+            aabb.with_point(matrix.transform_point3(corner))
+        })
+    }
+
+    // false only if the box is completely outside of the frustum of `view_proj`, with depth from 0 to 1
+    pub fn intersects_frustum(&self, view_proj: Mat4) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let rows = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[2],
+            rows[3] - rows[2],
+        ];
+
+        planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = Vec3::select(plane.truncate().cmpge(Vec3::ZERO), self.max, self.min);
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+// what the bounds of a pose are measured from, taken from the meshes once at load
+#[derive(Debug, Default)]
+pub struct PoseBounds {
+    // (node, radius) of joints, the sphere around a joint contains every vertex it influences no matter
+    // how it's posed, including morph target offsets
+    joints: Vec<(usize, f32)>,
+    // (node, box in the node's space) of vertices that aren't skinned and move with their node
+    meshes: Vec<(usize, Aabb)>,
+}
+
+impl PoseBounds {
+    pub fn new(skeleton: &Skeleton, meshes: &[Mesh]) -> Self {
+        let mut radii = vec![None::<f32>; skeleton.nodes.len()];
+        let mut mesh_bounds = Vec::new();
+
+        for (node_index, node) in skeleton.nodes.iter().enumerate() {
+            let NodeType::Mesh { mesh } = node.node_type else {
+                continue;
+            };
+            let skin = node.skin.and_then(|skin| skeleton.skins.get(skin));
+
+            let mut local = Aabb::EMPTY;
+            for primitive in meshes[mesh].primitives.iter() {
+                // the furthest any vertex can be moved by the morph targets with weights up to 1
+                let morph_offset = (0..primitive.vertices.len())
+                    .map(|vertex| {
+                        primitive
+                            .morph_targets
+                            .iter()
+                            .filter_map(|target| target.positions.get(vertex))
+                            .map(|offset| offset.length())
+                            .sum::<f32>()
+                    })
+                    .collect::<Vec<_>>();
+
+                for (vertex, morph_offset) in primitive.vertices.iter().zip(morph_offset) {
+                    let skinned = vertex.weights.iter().any(|weight| *weight > 0.0);
+                    let Some(skin) = skin.filter(|_| skinned) else {
+                        local = local.with_sphere(vertex.position, morph_offset);
+                        continue;
+                    };
+
+                    for (joint, weight) in vertex.joints.iter().zip(vertex.weights) {
+                        let joint = *joint as usize;
+                        if weight <= 0.0 || joint >= skin.joints.len() {
+                            continue;
+                        }
+                        // the vertex in the joint's space, how far it is from the joint stays the same
+                        let position =
+                            skin.inverse_bind_matrices[joint].transform_point3(vertex.position);
+                        let radius = &mut radii[skin.joints[joint]];
+                        *radius = Some(radius.unwrap_or(0.0).max(position.length() + morph_offset));
+                    }
+                }
+            }
+
+            if !local.is_empty() {
+                mesh_bounds.push((node_index, local));
+            }
+        }
+
+        Self {
+            joints: radii
+                .into_iter()
+                .enumerate()
+                .filter_map(|(node, radius)| Some((node, radius?)))
+                .collect(),
+            meshes: mesh_bounds,
+        }
+    }
+
+    // world matrices of `pose` have to be up to date
+    pub fn bounds(&self, pose: &Pose) -> Aabb {
+        let joints = self
+            .joints
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, (node, radius)| {
+                let world = pose.world_transforms[*node];
+                let scale = world
+                    .x_axis
+                    .truncate()
+                    .length()
+                    .max(world.y_axis.truncate().length())
+                    .max(world.z_axis.truncate().length());
+                aabb.with_sphere(world.w_axis.truncate(), radius * scale)
+            });
+
+        self.meshes.iter().fold(joints, |aabb, (node, local)| {
+            aabb.union(local.transform(pose.world_transforms[*node]))
+        })
+    }
+
+    // bounds around every pose of `animation` played from the rest pose. Sampled at all keyframes and
+    // `CLIP_BOUNDS_SUBDIVISIONS - 1` evenly spaced times between each pair of them, then padded by
+    // `CLIP_BOUNDS_MARGIN` of its size on every side for arcs and cubic overshoot between the samples.
+    // This is a heuristic, not a guarantee: a linear rotation turns a joint at most 45 degrees between
+    // samples, so spins of a single joint stay inside, but a child turning against its parent, cubic
+    // tangents overshooting further than the margin or a short arc off to one side of the clip can
+    // stick out a little. Culling with these bounds can then pop a limb at the edge of the screen
+    pub fn clip_bounds(&self, skeleton: &Skeleton, animation: &Animation) -> Aabb {
+        let mut keyframe_times = animation
+            .tracks
+            .iter()
+            .flat_map(|track| track.timestamps.iter().copied())
+            .chain([0.0, animation.length])
+            .collect::<Vec<_>>();
+        keyframe_times.sort_by(f32::total_cmp);
+        keyframe_times.dedup();
+
+        let times = keyframe_times
+            .windows(2)
+            .flat_map(|pair| {
+                (0..CLIP_BOUNDS_SUBDIVISIONS).map(move |i| {
+                    pair[0] + (pair[1] - pair[0]) * i as f32 / CLIP_BOUNDS_SUBDIVISIONS as f32
+                })
+            })
+            .chain(keyframe_times.last().copied());
+
+        let mut pose = skeleton.rest_pose();
+        let aabb = times.fold(Aabb::EMPTY, |aabb, time| {
+            pose.apply(animation, time);
+            skeleton.update_world_matrices(&mut pose);
+            aabb.union(self.bounds(&pose))
+        });
+
+        let margin = aabb.size() * CLIP_BOUNDS_MARGIN;
+        if aabb.is_empty() {
+            aabb
+        } else {
+            Aabb {
+                min: aabb.min - margin,
+                max: aabb.max + margin,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use jandering_engine::types::Qua;
+
+    use super::*;
+    use crate::{
+        animation::{Interpolation, Keyframes, Track},
+        skeleton::{Node, Transform},
+    };
+
+    fn node(translation: Vec3, children: Vec<usize>, parent: Option<usize>) -> Node {
+        Node {
+            name: String::new(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        }
+    }
+
+    #[test]
+    fn clip_bounds_contain_the_motion_between_keyframes() {
+        // a tip 1 above the root, swung almost half way around in one linear keyframe pair
+        let skeleton = Skeleton {
+            nodes: vec![
+                node(Vec3::ZERO, vec![1], None),
+                node(Vec3::Y, vec![], Some(0)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        };
+        let animation = Animation {
+            name: "swing".to_string(),
+            length: 1.0,
+            tracks: vec![Track {
+                keyframes: Keyframes::Rotations(vec![
+                    Qua::IDENTITY,
+                    Qua::from_rotation_z(PI * 0.95),
+                ]),
+                interpolation: Interpolation::Linear,
+                target: 0,
+                timestamps: vec![0.0, 1.0],
+            }],
+            events: Vec::new(),
+        };
+        let pose_bounds = PoseBounds {
+            joints: vec![(1, 0.0)],
+            meshes: Vec::new(),
+        };
+
+        let bounds = pose_bounds.clip_bounds(&skeleton, &animation);
+        let mut pose = skeleton.rest_pose();
+        for i in 0..=100 {
+            pose.apply(&animation, i as f32 / 100.0);
+            skeleton.update_world_matrices(&mut pose);
+            let tip = pose.world_transforms[1].w_axis.truncate();
+            assert!(
+                tip.cmpge(bounds.min).all() && tip.cmple(bounds.max).all(),
+                "{tip} outside of {bounds:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn clip_bounds_contain_fast_spins() {
+        // a tip 1 away from the root spun three times around in a second, each keyframe close to half a turn
+        let skeleton = Skeleton {
+            nodes: vec![
+                node(Vec3::ZERO, vec![1], None),
+                node(Vec3::X, vec![], Some(0)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        };
+        let keyframes = 7;
+        let timestamps = (0..=keyframes)
+            .map(|i| i as f32 / keyframes as f32)
+            .collect::<Vec<_>>();
+        let animation = Animation {
+            name: "spin".to_string(),
+            length: 1.0,
+            tracks: vec![Track {
+                keyframes: Keyframes::Rotations(
+                    timestamps
+                        .iter()
+                        .map(|time| Qua::from_rotation_y(time * 6.0 * PI))
+                        .collect(),
+                ),
+                interpolation: Interpolation::Linear,
+                target: 0,
+                timestamps,
+            }],
+            events: Vec::new(),
+        };
+        let pose_bounds = PoseBounds {
+            joints: vec![(1, 0.1)],
+            meshes: Vec::new(),
+        };
+
+        let bounds = pose_bounds.clip_bounds(&skeleton, &animation);
+        let mut pose = skeleton.rest_pose();
+        for i in 0..=1000 {
+            pose.apply(&animation, i as f32 / 1000.0);
+            skeleton.update_world_matrices(&mut pose);
+            let tip = pose_bounds.bounds(&pose);
+            assert!(
+                tip.min.cmpge(bounds.min).all() && tip.max.cmple(bounds.max).all(),
+                "{tip:?} outside of {bounds:?}"
+            );
+        }
+    }
+}
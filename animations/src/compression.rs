@@ -0,0 +1,366 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use jandering_engine::types::{Qua, Vec3};
+
+use crate::animation::{Interpolation, Keyframe, Keyframes, Track};
+
+// how far a reduced curve may be from the original one
+#[derive(Debug, Clone, Copy)]
+pub struct ReductionTolerance {
+    pub translation: f32,
+    // radians
+    pub rotation: f32,
+    pub scale: f32,
+    pub weight: f32,
+}
+
+impl Default for ReductionTolerance {
+    fn default() -> Self {
+        Self {
+            translation: 0.001,
+            rotation: 0.001,
+            scale: 0.001,
+            weight: 0.001,
+        }
+    }
+}
+
+// a unit quaternion in 8 bytes instead of 16. The largest component is left out and rebuilt from the
+// other three, which are stored in 16 bits each, so the error is below 0.0001 radians
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuantizedQua(u64);
+
+impl QuantizedQua {
+    pub fn new(rotation: Qua) -> Self {
+        let components = rotation.normalize().to_array();
+        let largest = (0..4)
+            .max_by(|a, b| components[*a].abs().total_cmp(&components[*b].abs()))
+            .unwrap_or(3);
+        // q and -q are the same rotation, flip it so the left out component is positive
+        let sign = components[largest].signum();
+
+        let mut packed = largest as u64;
+        for (_, component) in components.iter().enumerate().filter(|(i, _)| *i != largest) {
+            // the others are at most 1/sqrt(2) when the left out one is the largest
+            let normalized = (component * sign / FRAC_1_SQRT_2).clamp(-1.0, 1.0) * 0.5 + 0.5;
+            packed = (packed << 16) | (normalized * u16::MAX as f32).round() as u64;
+        }
+        Self(packed)
+    }
+
+    pub fn decode(self) -> Qua {
+        let largest = (self.0 >> 48) as usize & 3;
+
+        let mut components = [0.0; 4];
+        let mut shift = 32;
+        for (i, component) in components.iter_mut().enumerate() {
+            if i == largest {
+                continue;
+            }
+            let normalized = ((self.0 >> shift) & 0xffff) as f32 / u16::MAX as f32;
+            *component = (normalized * 2.0 - 1.0) * FRAC_1_SQRT_2;
+            shift -= 16;
+        }
+        let sum = components.iter().map(|c| c * c).sum::<f32>();
+        components[largest] = (1.0 - sum).max(0.0).sqrt();
+
+        Qua::from_array(components).normalize()
+    }
+}
+
+// drop the keyframes of a step or linear track that interpolating their neighbours reproduces within
+// `tolerance`, the first and last keyframes are always kept. Cubic spline and quantized tracks are left
+// alone, so reduce before quantizing
+pub fn reduce_keyframes(track: &mut Track, tolerance: ReductionTolerance) {
+    if track.interpolation == Interpolation::CubicSpline {
+        return;
+    }
+
+    let width = match track.keyframes {
+        Keyframes::MorphTargetWeights(_) => track.morph_target_count(),
+        _ => 1,
+    };
+    let interpolation = track.interpolation;
+    let timestamps = &track.timestamps;
+    let kept = match &track.keyframes {
+        Keyframes::Rotations(values) => {
+            kept_keyframes(interpolation, timestamps, values, 1, |a: Qua, b: Qua| {
+                rotation_error(a, b) <= tolerance.rotation
+            })
+        }
+        Keyframes::Translations(values) => {
+            kept_keyframes(interpolation, timestamps, values, 1, |a: Vec3, b: Vec3| {
+                a.distance(b) <= tolerance.translation
+            })
+        }
+        Keyframes::Scales(values) => {
+            kept_keyframes(interpolation, timestamps, values, 1, |a: Vec3, b: Vec3| {
+                a.distance(b) <= tolerance.scale
+            })
+        }
+        Keyframes::MorphTargetWeights(values) => kept_keyframes(
+            interpolation,
+            timestamps,
+            values,
+            width,
+            |a: f32, b: f32| (a - b).abs() <= tolerance.weight,
+        ),
+        Keyframes::QuantizedRotations(_) => return,
+    };
+
+    track.keyframes = match &track.keyframes {
+        Keyframes::Rotations(values) => Keyframes::Rotations(pick(values, &kept, width)),
+        Keyframes::Translations(values) => Keyframes::Translations(pick(values, &kept, width)),
+        Keyframes::Scales(values) => Keyframes::Scales(pick(values, &kept, width)),
+        Keyframes::MorphTargetWeights(values) => {
+            Keyframes::MorphTargetWeights(pick(values, &kept, width))
+        }
+        Keyframes::QuantizedRotations(_) => return,
+    };
+    track.timestamps = kept
+        .iter()
+        .map(|keyframe| track.timestamps[*keyframe])
+        .collect();
+}
+
+// store the rotations of step and linear tracks as `QuantizedQua`s, cubic spline tangents aren't unit
+// quaternions so those tracks are kept as they are
+pub fn quantize_rotations(track: &mut Track) {
+    if track.interpolation == Interpolation::CubicSpline {
+        return;
+    }
+
+    if let Keyframes::Rotations(rotations) = &track.keyframes {
+        track.keyframes = Keyframes::QuantizedRotations(
+            rotations
+                .iter()
+                .map(|rotation| QuantizedQua::new(*rotation))
+                .collect(),
+        );
+    }
+}
+
+// the values of the `kept` keyframes
+fn pick<T: Copy>(values: &[T], kept: &[usize], width: usize) -> Vec<T> {
+    kept.iter()
+        .flat_map(|keyframe| {
+            values[keyframe * width..(keyframe + 1) * width]
+                .iter()
+                .copied()
+        })
+        .collect()
+}
+
+// indices of the keyframes to keep, every keyframe has `width` values next to each other.
+// Grows each segment until a keyframe inside it can't be reproduced anymore, the curves between
+// keyframes are interpolated the same way on both sides so checking the keyframes is enough
+fn kept_keyframes<T: Keyframe>(
+    interpolation: Interpolation,
+    timestamps: &[f32],
+    values: &[T],
+    width: usize,
+    close_enough: impl Fn(T, T) -> bool,
+) -> Vec<usize> {
+    let count = timestamps.len();
+    if count <= 2 || width == 0 {
+        return (0..count).collect();
+    }
+
+    let fits = |start: usize, end: usize| {
+        (start + 1..end).all(|keyframe| {
+            let t =
+                (timestamps[keyframe] - timestamps[start]) / (timestamps[end] - timestamps[start]);
+            (0..width).all(|i| {
+                let start_value = values[start * width + i];
+                let predicted = match interpolation {
+                    Interpolation::Step => start_value,
+                    _ => start_value.interpolate(values[end * width + i], t),
+                };
+                close_enough(predicted, values[keyframe * width + i])
+            })
+        })
+    };
+
+    let mut kept = vec![0];
+    let mut start = 0;
+    for end in 2..count {
+        if !fits(start, end) {
+            start = end - 1;
+            kept.push(start);
+        }
+    }
+    kept.push(count - 1);
+    kept
+}
+
+// angle between two rotations, unlike `Qua::angle_between` this stays precise for tiny angles
+pub fn rotation_error(a: Qua, b: Qua) -> f32 {
+    let delta = a.inverse() * b;
+    2.0 * delta.xyz().length().atan2(delta.w.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::Transform;
+
+    const LENGTH: f32 = 4.0;
+
+    // `count` keyframes over `LENGTH` seconds
+    fn track(
+        interpolation: Interpolation,
+        count: usize,
+        keyframes: impl Fn(&[f32]) -> Keyframes,
+    ) -> Track {
+        let timestamps = (0..count)
+            .map(|i| i as f32 / (count - 1) as f32 * LENGTH)
+            .collect::<Vec<_>>();
+        Track {
+            keyframes: keyframes(&timestamps),
+            interpolation,
+            target: 0,
+            timestamps,
+        }
+    }
+
+    fn translations(interpolation: Interpolation) -> Track {
+        track(interpolation, 240, |timestamps| {
+            Keyframes::Translations(
+                timestamps
+                    .iter()
+                    // a curve, then a straight line that reduces to its end points
+                    .map(|t| Vec3::new((t * 3.0).sin(), t.min(2.0) * 0.5, (t * 2.0).cos() * 0.3))
+                    .collect(),
+            )
+        })
+    }
+
+    fn rotations() -> Track {
+        track(Interpolation::Linear, 240, |timestamps| {
+            Keyframes::Rotations(
+                timestamps
+                    .iter()
+                    .map(|t| {
+                        Qua::from_rotation_y(t.sin() * 1.5) * Qua::from_rotation_x(t.min(2.0) * 0.4)
+                    })
+                    .collect(),
+            )
+        })
+    }
+
+    fn transform_at(track: &Track, time: f32) -> Transform {
+        let mut transform = Transform {
+            translation: Vec3::ZERO,
+            rotation: Qua::IDENTITY,
+            scale: Vec3::ONE,
+        };
+        transform.apply(track, time);
+        transform
+    }
+
+    // the largest `error` between the two tracks, sampled much denser than their keyframes
+    fn max_error(
+        original: &Track,
+        compressed: &Track,
+        error: impl Fn(Transform, Transform) -> f32,
+    ) -> f32 {
+        (0..=4000)
+            .map(|i| i as f32 / 4000.0 * LENGTH)
+            .map(|time| error(transform_at(original, time), transform_at(compressed, time)))
+            .fold(0.0, f32::max)
+    }
+
+    fn reduced(mut track: Track, tolerance: ReductionTolerance) -> Track {
+        reduce_keyframes(&mut track, tolerance);
+        track
+    }
+
+    #[test]
+    fn reduced_linear_tracks_stay_within_tolerance() {
+        let original = translations(Interpolation::Linear);
+        for translation in [0.001, 0.01, 0.05] {
+            let tolerance = ReductionTolerance {
+                translation,
+                ..Default::default()
+            };
+            let reduced = reduced(translations(Interpolation::Linear), tolerance);
+            assert!(reduced.timestamps.len() < original.timestamps.len());
+
+            let error = max_error(&original, &reduced, |a, b| {
+                a.translation.distance(b.translation)
+            });
+            assert!(error <= translation + 1e-6, "{error} > {translation}");
+        }
+    }
+
+    #[test]
+    fn reduced_step_tracks_stay_within_tolerance() {
+        let original = translations(Interpolation::Step);
+        let tolerance = ReductionTolerance {
+            translation: 0.05,
+            ..Default::default()
+        };
+        let reduced = reduced(translations(Interpolation::Step), tolerance);
+        assert!(reduced.timestamps.len() < original.timestamps.len());
+
+        let error = max_error(&original, &reduced, |a, b| {
+            a.translation.distance(b.translation)
+        });
+        assert!(error <= tolerance.translation + 1e-6, "{error}");
+    }
+
+    #[test]
+    fn reduced_slerp_tracks_stay_within_tolerance() {
+        let original = rotations();
+        for rotation in [0.001, 0.01, 0.05] {
+            let tolerance = ReductionTolerance {
+                rotation,
+                ..Default::default()
+            };
+            let reduced = reduced(rotations(), tolerance);
+            assert!(reduced.timestamps.len() < original.timestamps.len());
+
+            let error = max_error(&original, &reduced, |a, b| {
+                rotation_error(a.rotation, b.rotation)
+            });
+            assert!(error <= rotation + 1e-5, "{error} > {rotation}");
+        }
+    }
+
+    #[test]
+    fn quantized_slerp_tracks_stay_within_tolerance() {
+        let original = rotations();
+        let rotation = 0.01;
+        // leave room for the quantization error
+        let mut compressed = reduced(
+            rotations(),
+            ReductionTolerance {
+                rotation: rotation - 0.0001,
+                ..Default::default()
+            },
+        );
+        quantize_rotations(&mut compressed);
+        assert!(matches!(
+            compressed.keyframes,
+            Keyframes::QuantizedRotations(_)
+        ));
+
+        let error = max_error(&original, &compressed, |a, b| {
+            rotation_error(a.rotation, b.rotation)
+        });
+        assert!(error <= rotation, "{error} > {rotation}");
+    }
+
+    #[test]
+    fn quantized_rotations_are_within_a_ten_thousandth_of_a_radian() {
+        let original = rotations();
+        let mut quantized = rotations();
+        quantize_rotations(&mut quantized);
+
+        let error = max_error(&original, &quantized, |a, b| {
+            rotation_error(a.rotation, b.rotation)
+        });
+        assert!(error < 0.0001, "{error}");
+    }
+}
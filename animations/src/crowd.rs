@@ -0,0 +1,495 @@
+use jandering_engine::{
+    bind_group::{BindGroupLayout, BindGroupLayoutEntry},
+    object::Renderable,
+    renderer::{BindGroupHandle, BufferHandle, Janderer, Renderer},
+    types::Mat4,
+};
+
+use crate::{
+    animated_object::{AnimatedObject, JointInfo},
+    animation::Animation,
+    bounds::Aabb,
+    material::AlphaMode,
+    mesh::Primitive,
+    playback::Playback,
+    skeleton::{NodeType, Pose, Skeleton},
+};
+
+// one character of a `Crowd`, with its own placement and playhead
+#[derive(Debug, Clone)]
+pub struct CrowdInstance {
+    pub transform: Mat4,
+    pub animation: usize,
+    pub playback: Playback,
+}
+
+impl CrowdInstance {
+    pub fn new(transform: Mat4, animation: usize) -> Self {
+        Self {
+            transform,
+            animation,
+            playback: Playback::default(),
+        }
+    }
+
+    pub fn with_playback(mut self, playback: Playback) -> Self {
+        self.playback = playback;
+        self
+    }
+}
+
+// many copies of one loaded character, drawn with one draw call per primitive. Meshes, materials,
+// skeleton and clips stay in the `AnimatedObject` the crowd was made from, the crowd only owns the
+// joint palettes and model matrices of its instances. Morph weights are shared with that object
+#[derive(Debug)]
+pub struct Crowd {
+    // the buffers are sized for the instances the crowd was created with, extra ones aren't drawn
+    pub instances: Vec<CrowdInstance>,
+    capacity: usize,
+    joint_buffer: BufferHandle,
+    // one per skin of the template, or a single one if it has none
+    joint_data_bind_groups: Vec<BindGroupHandle>,
+    // model matrices of every instance for each mesh node, only used by unskinned meshes
+    node_instance_buffers: Vec<(usize, BufferHandle)>,
+    palettes: CrowdPalettes,
+}
+
+// what a `Crowd` uploads, sampled on the cpu so it can be tested without a renderer. The buffers are
+// reused every update so sampling doesn't allocate once they've grown
+#[derive(Debug)]
+pub struct CrowdPalettes {
+    pose: Pose,
+    rest_pose: Pose,
+    // mesh nodes of the skeleton in order, one list of model matrices is kept for each
+    mesh_nodes: Vec<usize>,
+    // the joint palette of every visible instance one after another, a single identity matrix each
+    // when the skeleton has no skins
+    pub joint_matrices: Vec<Mat4>,
+    pub node_matrices: Vec<Vec<Mat4>>,
+    // instances that passed culling in the last update, they're packed at the start of the buffers
+    pub visible: usize,
+}
+
+// a primitive drawn once for every instance of a crowd
+#[derive(Debug)]
+pub struct InstancedPrimitive<'a> {
+    pub primitive: &'a Primitive,
+    pub instance_buffer: BufferHandle,
+    pub instance_count: u32,
+}
+
+// bind groups to bind before rendering `primitive`, see `Draw`
+#[derive(Debug)]
+pub struct CrowdDraw<'a> {
+    pub joint_data_bind_group: BindGroupHandle,
+    pub material_bind_group: BindGroupHandle,
+    pub morph_bind_group: BindGroupHandle,
+    pub primitive: InstancedPrimitive<'a>,
+}
+
+impl Crowd {
+    pub fn new(
+        renderer: &mut Renderer,
+        template: &AnimatedObject,
+        instances: Vec<CrowdInstance>,
+    ) -> Self {
+        let skeleton = &template.skeleton;
+        let capacity = instances.len().max(1);
+
+        let skin_offsets = skeleton
+            .skins
+            .iter()
+            .scan(0, |offset, skin| {
+                let skin_offset = *offset;
+                *offset += skin.joints.len();
+                Some(skin_offset)
+            })
+            .collect::<Vec<_>>();
+        let joint_stride = skeleton
+            .skins
+            .iter()
+            .map(|skin| skin.joints.len())
+            .sum::<usize>()
+            .max(1);
+
+        let joint_buffer = renderer.create_storage_buffer(bytemuck::cast_slice(&vec![
+            Mat4::IDENTITY;
+            capacity * joint_stride
+        ]));
+        let joint_data_bind_groups = if skin_offsets.is_empty() {
+            vec![0]
+        } else {
+            skin_offsets
+        }
+        .into_iter()
+        .map(|offset| {
+            let joint_info_buffer =
+                renderer.create_uniform_buffer(bytemuck::cast_slice(&[JointInfo::new(
+                    offset,
+                    joint_stride,
+                )]));
+            renderer.create_bind_group(BindGroupLayout {
+                entries: vec![
+                    BindGroupLayoutEntry::Data(joint_buffer),
+                    BindGroupLayoutEntry::Data(joint_info_buffer),
+                    BindGroupLayoutEntry::Data(template.light_buffer),
+                ],
+            })
+        })
+        .collect();
+
+        let node_instance_buffers = skeleton
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| matches!(node.node_type, NodeType::Mesh { .. }))
+            .map(|(i, _)| {
+                let matrices = vec![Mat4::IDENTITY; capacity];
+                (
+                    i,
+                    renderer.create_vertex_buffer(bytemuck::cast_slice(&matrices)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            instances,
+            capacity,
+            joint_buffer,
+            joint_data_bind_groups,
+            node_instance_buffers,
+            palettes: CrowdPalettes::new(skeleton, capacity),
+        }
+    }
+
+    // advance every instance by `dt` seconds and upload their palettes, IK, layers and events
+    // are only available on the `AnimatedObject` itself. With `view_proj` instances whose clip bounds
+    // are outside of the camera's frustum keep playing but aren't sampled or drawn
+    pub fn update(
+        &mut self,
+        renderer: &mut Renderer,
+        template: &AnimatedObject,
+        dt: f32,
+        view_proj: Option<Mat4>,
+    ) {
+        let count = self.instance_count();
+        self.palettes.sample(
+            &template.skeleton,
+            &template.animator.animations,
+            &template.clip_bounds,
+            &mut self.instances[..count],
+            dt,
+            view_proj,
+        );
+
+        if self.palettes.visible == 0 {
+            return;
+        }
+        renderer.write_buffer(
+            self.joint_buffer,
+            bytemuck::cast_slice(&self.palettes.joint_matrices),
+        );
+        for ((_, buffer), matrices) in self
+            .node_instance_buffers
+            .iter()
+            .zip(&self.palettes.node_matrices)
+        {
+            renderer.write_buffer(*buffer, bytemuck::cast_slice(matrices));
+        }
+    }
+
+    // every primitive of every mesh node of `template`, blended primitives come last.
+    // Empty when every instance was culled
+    pub fn draws<'a>(&'a self, template: &'a AnimatedObject) -> Vec<CrowdDraw<'a>> {
+        if self.palettes.visible == 0 {
+            return Vec::new();
+        }
+
+        let instance_count = self.palettes.visible as u32;
+        let mut draws = self
+            .node_instance_buffers
+            .iter()
+            .filter_map(|(node, instance_buffer)| {
+                let node = &template.skeleton.nodes[*node];
+                match node.node_type {
+                    NodeType::Mesh { mesh } => Some((
+                        self.joint_data_bind_groups[node.skin.unwrap_or(0)],
+                        *instance_buffer,
+                        mesh,
+                    )),
+                    NodeType::Generic => None,
+                }
+            })
+            .flat_map(|(joint_data_bind_group, instance_buffer, mesh)| {
+                template.meshes[mesh]
+                    .primitives
+                    .iter()
+                    .map(move |primitive| CrowdDraw {
+                        joint_data_bind_group,
+                        material_bind_group: template.materials[primitive.material].bind_group,
+                        morph_bind_group: primitive.render_data.morph_bind_group,
+                        primitive: InstancedPrimitive {
+                            primitive,
+                            instance_buffer,
+                            instance_count,
+                        },
+                    })
+            })
+            .collect::<Vec<_>>();
+        draws.sort_by_key(|draw| {
+            template.materials[draw.primitive.primitive.material]
+                .properties
+                .alpha_mode
+                == AlphaMode::Blend
+        });
+        draws
+    }
+
+    fn instance_count(&self) -> usize {
+        self.instances.len().min(self.capacity)
+    }
+}
+
+impl CrowdPalettes {
+    pub fn new(skeleton: &Skeleton, capacity: usize) -> Self {
+        let mesh_nodes = skeleton
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| matches!(node.node_type, NodeType::Mesh { .. }))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let joint_stride = skeleton
+            .skins
+            .iter()
+            .map(|skin| skin.joints.len())
+            .sum::<usize>()
+            .max(1);
+
+        Self {
+            pose: skeleton.rest_pose(),
+            rest_pose: skeleton.rest_pose(),
+            joint_matrices: Vec::with_capacity(capacity * joint_stride),
+            node_matrices: vec![Vec::with_capacity(capacity); mesh_nodes.len()],
+            mesh_nodes,
+            visible: 0,
+        }
+    }
+
+    // advance every instance by `dt` seconds and sample the ones whose clip bounds, one per animation,
+    // intersect `view_proj`. Culled instances keep playing
+    pub fn sample(
+        &mut self,
+        skeleton: &Skeleton,
+        animations: &[Animation],
+        clip_bounds: &[Aabb],
+        instances: &mut [CrowdInstance],
+        dt: f32,
+        view_proj: Option<Mat4>,
+    ) {
+        self.joint_matrices.clear();
+        for matrices in self.node_matrices.iter_mut() {
+            matrices.clear();
+        }
+        self.visible = 0;
+        for instance in instances.iter_mut() {
+            let animation = animations.get(instance.animation);
+            if let Some(animation) = animation {
+                instance.playback.advance(dt, animation.length);
+            }
+
+            let bounds = clip_bounds
+                .get(instance.animation)
+                .map(|bounds| bounds.transform(instance.transform));
+            if let (Some(view_proj), Some(bounds)) = (view_proj, bounds) {
+                if !bounds.intersects_frustum(view_proj) {
+                    continue;
+                }
+            }
+            self.visible += 1;
+
+            self.pose.transforms.clone_from(&self.rest_pose.transforms);
+            self.pose.weights.clone_from(&self.rest_pose.weights);
+            if let Some(animation) = animation {
+                self.pose
+                    .apply(animation, instance.playback.clip_time(animation.length));
+            }
+            skeleton.update_world_matrices(&mut self.pose);
+
+            if skeleton.skins.is_empty() {
+                self.joint_matrices.push(Mat4::IDENTITY);
+            }
+            // `Skeleton::joint_matrices` without its allocation
+            for skin in skeleton.skins.iter() {
+                self.joint_matrices.extend(
+                    skin.joints
+                        .iter()
+                        .zip(skin.inverse_bind_matrices.iter())
+                        .map(|(joint, inverse_bind_matrix)| {
+                            instance.transform
+                                * self.pose.world_transforms[*joint]
+                                * *inverse_bind_matrix
+                        }),
+                );
+            }
+            for (node, matrices) in self.mesh_nodes.iter().zip(&mut self.node_matrices) {
+                matrices.push(instance.transform * self.pose.world_transforms[*node]);
+            }
+        }
+    }
+}
+
+impl Renderable for InstancedPrimitive<'_> {
+    fn num_instances(&self) -> u32 {
+        self.instance_count
+    }
+
+    fn num_indices(&self) -> u32 {
+        self.primitive.num_indices()
+    }
+
+    fn get_buffers(&self) -> (BufferHandle, BufferHandle, Option<BufferHandle>) {
+        (
+            self.primitive.render_data.vertex_buffer,
+            self.primitive.render_data.index_buffer,
+            Some(self.instance_buffer),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jandering_engine::types::{Qua, Vec3};
+
+    use super::*;
+    use crate::{
+        animation::{Interpolation, Keyframes, Track},
+        skeleton::{Node, Skin, Transform},
+    };
+
+    fn node(node_type: NodeType, children: Vec<usize>, parent: Option<usize>) -> Node {
+        Node {
+            name: String::new(),
+            node_type,
+            transform: Transform {
+                translation: Vec3::ZERO,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        }
+    }
+
+    // a single joint skinning a mesh below it
+    fn skeleton() -> Skeleton {
+        let mut mesh = node(NodeType::Mesh { mesh: 0 }, Vec::new(), Some(0));
+        mesh.skin = Some(0);
+        Skeleton {
+            nodes: vec![node(NodeType::Generic, vec![1], None), mesh],
+            roots: vec![0],
+            skins: vec![Skin {
+                joints: vec![0],
+                inverse_bind_matrices: vec![Mat4::IDENTITY],
+            }],
+        }
+    }
+
+    // moves the joint 1 along `direction` over a second
+    fn slide(direction: Vec3) -> Animation {
+        Animation {
+            name: String::new(),
+            length: 1.0,
+            tracks: vec![Track {
+                keyframes: Keyframes::Translations(vec![Vec3::ZERO, direction]),
+                interpolation: Interpolation::Linear,
+                target: 0,
+                timestamps: vec![0.0, 1.0],
+            }],
+            events: Vec::new(),
+        }
+    }
+
+    fn instance(x: f32, animation: usize, time: f32) -> CrowdInstance {
+        let mut playback = Playback::default();
+        playback.seek(time);
+        CrowdInstance::new(Mat4::from_translation(Vec3::X * x), animation).with_playback(playback)
+    }
+
+    fn sample(instances: &mut [CrowdInstance], view_proj: Option<Mat4>) -> CrowdPalettes {
+        let skeleton = skeleton();
+        let animations = [slide(Vec3::X), slide(Vec3::Y)];
+        let clip_bounds = [
+            Aabb::EMPTY.with_sphere(Vec3::ZERO, 0.1).with_point(Vec3::X),
+            Aabb::EMPTY.with_sphere(Vec3::ZERO, 0.1).with_point(Vec3::Y),
+        ];
+        let mut palettes = CrowdPalettes::new(&skeleton, instances.len());
+        palettes.sample(
+            &skeleton,
+            &animations,
+            &clip_bounds,
+            instances,
+            0.25,
+            view_proj,
+        );
+        palettes
+    }
+
+    fn translation(matrix: Mat4) -> Vec3 {
+        matrix.w_axis.truncate()
+    }
+
+    #[test]
+    fn instances_sample_their_own_clip_and_time() {
+        let mut instances = [instance(0.0, 0, 0.0), instance(-3.0, 1, 0.5)];
+        let palettes = sample(&mut instances, None);
+
+        assert_eq!(palettes.visible, 2);
+        assert_eq!(palettes.joint_matrices.len(), 2);
+        assert!(
+            translation(palettes.joint_matrices[0]).abs_diff_eq(Vec3::new(0.25, 0.0, 0.0), 1e-5)
+        );
+        assert!(
+            translation(palettes.joint_matrices[1]).abs_diff_eq(Vec3::new(-3.0, 0.75, 0.0), 1e-5)
+        );
+        assert_eq!(palettes.node_matrices.len(), 1);
+        assert!(
+            translation(palettes.node_matrices[0][1]).abs_diff_eq(Vec3::new(-3.0, 0.75, 0.0), 1e-5)
+        );
+        assert_eq!(instances[1].playback.time, 0.75);
+    }
+
+    #[test]
+    fn culled_instances_keep_playing_but_are_not_drawn() {
+        let mut instances = [
+            instance(100.0, 0, 0.0),
+            instance(0.0, 1, 0.0),
+            instance(-100.0, 0, 0.5),
+            instance(3.0, 0, 0.0),
+        ];
+        let view_proj = Mat4::orthographic_rh(-5.0, 5.0, -5.0, 5.0, -5.0, 5.0);
+        let palettes = sample(&mut instances, Some(view_proj));
+
+        // the visible ones are packed at the start, in order
+        assert_eq!(palettes.visible, 2);
+        assert_eq!(palettes.joint_matrices.len(), 2);
+        assert_eq!(palettes.node_matrices[0].len(), 2);
+        assert!(
+            translation(palettes.joint_matrices[0]).abs_diff_eq(Vec3::new(0.0, 0.25, 0.0), 1e-5)
+        );
+        assert!(
+            translation(palettes.joint_matrices[1]).abs_diff_eq(Vec3::new(3.25, 0.0, 0.0), 1e-5)
+        );
+        assert_eq!(instances[0].playback.time, 0.25);
+        assert_eq!(instances[2].playback.time, 0.75);
+
+        let palettes = sample(
+            &mut instances,
+            Some(Mat4::from_translation(Vec3::Z * 100.0)),
+        );
+        assert_eq!(palettes.visible, 0);
+        assert!(palettes.joint_matrices.is_empty());
+    }
+}
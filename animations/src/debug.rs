@@ -0,0 +1,196 @@
+use jandering_engine::{
+    object::Renderable,
+    renderer::{BufferHandle, Janderer, Renderer},
+    shader::{BufferLayout, BufferLayoutEntry, BufferLayoutEntryDataType, BufferLayoutStepMode},
+    types::Vec3,
+};
+
+use crate::skeleton::{Pose, Skeleton};
+
+const BONE_COLOR: Vec3 = Vec3::new(0.8, 0.8, 0.9);
+const JOINT_COLOR: Vec3 = Vec3::new(1.0, 0.6, 0.1);
+const HIGHLIGHT_COLOR: Vec3 = Vec3::new(1.0, 0.1, 0.1);
+
+// every gizmo is an octahedron: a bottom vertex, four around the middle and a top vertex
+const GIZMO_VERTICES: usize = 6;
+const GIZMO_INDICES: [u32; 24] = [
+    0, 2, 1, 0, 3, 2, 0, 4, 3, 0, 1, 4, 5, 1, 2, 5, 2, 3, 5, 3, 4, 5, 4, 1,
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Default)]
+pub struct DebugVertex {
+    pub position: Vec3,
+    pub color: Vec3,
+}
+
+// the joints and bones of a skeleton drawn as gizmos over the model, rebuilt from the pose every frame
+#[derive(Debug)]
+pub struct SkeletonDebug {
+    // nodes drawn as joints, the joints of every skin or all nodes if there are no skins
+    pub joints: Vec<usize>,
+    // drawn in a different color
+    pub highlighted_node: Option<usize>,
+    // (parent, child) pairs of joints
+    bones: Vec<(usize, usize)>,
+    joint_size: f32,
+    vertices: Vec<DebugVertex>,
+    indices: Vec<u32>,
+    vertex_buffer: BufferHandle,
+    index_buffer: BufferHandle,
+}
+
+impl SkeletonDebug {
+    pub fn new(renderer: &mut Renderer, skeleton: &Skeleton) -> Self {
+        let mut joints = skeleton
+            .skins
+            .iter()
+            .flat_map(|skin| skin.joints.iter().copied())
+            .collect::<Vec<_>>();
+        if joints.is_empty() {
+            joints = (0..skeleton.nodes.len()).collect();
+        }
+        joints.sort();
+        joints.dedup();
+
+        let bones = joints
+            .iter()
+            .filter_map(|joint| {
+                skeleton.nodes[*joint]
+                    .parent
+                    .filter(|parent| joints.binary_search(parent).is_ok())
+                    .map(|parent| (parent, *joint))
+            })
+            .collect::<Vec<_>>();
+
+        // joints are sized by the average rest pose bone so they fit models of any scale
+        let rest_pose = skeleton.rest_pose();
+        let bone_length = bones
+            .iter()
+            .map(|(parent, child)| {
+                (position(&rest_pose, *child) - position(&rest_pose, *parent)).length()
+            })
+            .sum::<f32>()
+            / bones.len().max(1) as f32;
+        let joint_size = if bone_length > 0.0 {
+            bone_length * 0.1
+        } else {
+            0.05
+        };
+
+        let gizmo_count = joints.len() + bones.len();
+        let vertices = vec![DebugVertex::default(); gizmo_count * GIZMO_VERTICES];
+        let indices = (0..gizmo_count as u32)
+            .flat_map(|gizmo| {
+                GIZMO_INDICES
+                    .iter()
+                    .map(move |index| index + gizmo * GIZMO_VERTICES as u32)
+            })
+            .collect::<Vec<_>>();
+
+        let vertex_buffer = renderer.create_vertex_buffer(bytemuck::cast_slice(&vertices));
+        let index_buffer = renderer.create_index_buffer(bytemuck::cast_slice(&indices));
+
+        Self {
+            joints,
+            highlighted_node: None,
+            bones,
+            joint_size,
+            vertices,
+            indices,
+            vertex_buffer,
+            index_buffer,
+        }
+    }
+
+    // move the gizmos to the world transforms of `pose`
+    pub fn update(&mut self, renderer: &mut Renderer, pose: &Pose) {
+        self.vertices.clear();
+
+        for (parent, child) in self.bones.iter() {
+            let head = position(pose, *parent);
+            let tail = position(pose, *child);
+            let length = (tail - head).length();
+            let direction = (tail - head).try_normalize().unwrap_or(Vec3::Y);
+            let (u, v) = direction.any_orthonormal_pair();
+            let middle = head + direction * length * 0.1;
+            let width = length * 0.1;
+
+            self.vertices.extend(
+                [
+                    head,
+                    middle + u * width,
+                    middle + v * width,
+                    middle - u * width,
+                    middle - v * width,
+                    tail,
+                ]
+                .map(|position| DebugVertex {
+                    position,
+                    color: BONE_COLOR,
+                }),
+            );
+        }
+
+        for joint in self.joints.iter() {
+            let center = position(pose, *joint);
+            let color = if self.highlighted_node == Some(*joint) {
+                HIGHLIGHT_COLOR
+            } else {
+                JOINT_COLOR
+            };
+            let size = self.joint_size;
+
+            self.vertices.extend(
+                [
+                    center - Vec3::Y * size,
+                    center + Vec3::X * size,
+                    center + Vec3::Z * size,
+                    center - Vec3::X * size,
+                    center - Vec3::Z * size,
+                    center + Vec3::Y * size,
+                ]
+                .map(|position| DebugVertex { position, color }),
+            );
+        }
+
+        renderer.write_buffer(self.vertex_buffer, bytemuck::cast_slice(&self.vertices));
+    }
+
+    pub fn desc() -> BufferLayout {
+        BufferLayout {
+            step_mode: BufferLayoutStepMode::Vertex,
+            stride: std::mem::size_of::<DebugVertex>(),
+            entries: &[
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(DebugVertex, position) as u64,
+                    location: 0,
+                    data_type: BufferLayoutEntryDataType::Float32x3,
+                },
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(DebugVertex, color) as u64,
+                    location: 1,
+                    data_type: BufferLayoutEntryDataType::Float32x3,
+                },
+            ],
+        }
+    }
+}
+
+impl Renderable for SkeletonDebug {
+    fn num_instances(&self) -> u32 {
+        1
+    }
+
+    fn num_indices(&self) -> u32 {
+        self.indices.len() as u32
+    }
+
+    fn get_buffers(&self) -> (BufferHandle, BufferHandle, Option<BufferHandle>) {
+        (self.vertex_buffer, self.index_buffer, None)
+    }
+}
+
+fn position(pose: &Pose, node: usize) -> Vec3 {
+    pose.world_transforms[node].w_axis.truncate()
+}
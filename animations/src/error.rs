@@ -0,0 +1,164 @@
+#[derive(Debug)]
+pub enum GltfLoadError {
+    Io {
+        path: String,
+        error: String,
+    },
+    Gltf(gltf::Error),
+    MissingBlob,
+    JointOutOfRange {
+        mesh: String,
+        joint: u16,
+        joint_count: usize,
+    },
+    UnsupportedInput {
+        animation: String,
+        channel: usize,
+    },
+    MissingOutput {
+        animation: String,
+        channel: usize,
+    },
+    KeyframeCountMismatch {
+        animation: String,
+        channel: usize,
+        timestamps: usize,
+        values: usize,
+    },
+    Image {
+        image: usize,
+        error: image::ImageError,
+    },
+    InvalidEvents {
+        animation: String,
+        error: String,
+    },
+    InverseBindMatrixCountMismatch {
+        skin: usize,
+        joints: usize,
+        matrices: usize,
+    },
+    ImageViewOutOfRange {
+        image: usize,
+        buffer: usize,
+    },
+    InvalidDataUri {
+        error: &'static str,
+    },
+}
+
+impl std::fmt::Display for GltfLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GltfLoadError::Io { path, error } => write!(f, "couldn't load '{path}': {error}"),
+            GltfLoadError::Gltf(error) => write!(f, "invalid gltf: {error}"),
+            GltfLoadError::MissingBlob => {
+                write!(f, "a buffer references the binary chunk but the file has none")
+            }
+            GltfLoadError::JointOutOfRange {
+                mesh,
+                joint,
+                joint_count,
+            } => write!(
+                f,
+                "mesh '{mesh}' references joint {joint} but its skin only has {joint_count} joints"
+            ),
+            GltfLoadError::UnsupportedInput { animation, channel } => write!(
+                f,
+                "channel {channel} of animation '{animation}' doesn't have float timestamps"
+            ),
+            GltfLoadError::MissingOutput { animation, channel } => write!(
+                f,
+                "channel {channel} of animation '{animation}' has no readable output"
+            ),
+            GltfLoadError::KeyframeCountMismatch {
+                animation,
+                channel,
+                timestamps,
+                values,
+            } => write!(
+                f,
+                "channel {channel} of animation '{animation}' has {values} values for {timestamps} timestamps"
+            ),
+            GltfLoadError::Image { image, error } => {
+                write!(f, "couldn't decode image {image}: {error}")
+            }
+            GltfLoadError::InvalidEvents { animation, error } => {
+                write!(f, "invalid events in animation '{animation}': {error}")
+            }
+            GltfLoadError::InverseBindMatrixCountMismatch {
+                skin,
+                joints,
+                matrices,
+            } => write!(
+                f,
+                "skin {skin} has {joints} joints but {matrices} inverse bind matrices"
+            ),
+            GltfLoadError::ImageViewOutOfRange { image, buffer } => {
+                write!(f, "image {image} reads past the end of buffer {buffer}")
+            }
+            GltfLoadError::InvalidDataUri { error } => write!(f, "invalid data uri: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for GltfLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfLoadError::Gltf(error) => Some(error),
+            GltfLoadError::Image { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfLoadError {
+    fn from(error: gltf::Error) -> Self {
+        GltfLoadError::Gltf(error)
+    }
+}
+
+// why a baked model couldn't be loaded
+#[derive(Debug)]
+pub enum BakeError {
+    Io { path: String, error: String },
+    NotABake,
+    UnsupportedVersion { version: u32 },
+    ChecksumMismatch,
+    Truncated,
+    // arrays in the file have to start at 16 byte boundaries to be used in place
+    Misaligned,
+    InvalidData(String),
+}
+
+impl std::fmt::Display for BakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BakeError::Io { path, error } => write!(f, "couldn't load '{path}': {error}"),
+            BakeError::NotABake => write!(f, "not a baked model"),
+            BakeError::UnsupportedVersion { version } => {
+                write!(
+                    f,
+                    "baked with version {version} of the format, which isn't supported"
+                )
+            }
+            BakeError::ChecksumMismatch => {
+                write!(f, "the checksum doesn't match, the file is corrupted")
+            }
+            BakeError::Truncated => write!(f, "the file ends too early"),
+            BakeError::Misaligned => write!(f, "the file isn't aligned to 16 bytes in memory"),
+            BakeError::InvalidData(error) => write!(f, "invalid data: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BakeError {}
+
+impl From<GltfLoadError> for BakeError {
+    fn from(error: GltfLoadError) -> Self {
+        match error {
+            GltfLoadError::Io { path, error } => BakeError::Io { path, error },
+            error => BakeError::InvalidData(error.to_string()),
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{error::GltfLoadError, playback::WrapMode};
+
+// a named point on a clip's timeline, e.g. a footstep
+#[derive(Debug, Clone)]
+pub struct AnimationEvent {
+    pub name: String,
+    pub time: f32,
+}
+
+// an event the playhead crossed during the last update
+#[derive(Debug, Clone)]
+pub struct TriggeredEvent {
+    pub animation: usize,
+    pub name: String,
+    // weight of the clip when the event was crossed, lower while blending or crossfading
+    pub weight: f32,
+}
+
+// events are read from the animation's extras: { "events": [{ "name": "footstep", "time": 0.4 }] }
+pub fn events_from_gltf(animation: &gltf::Animation) -> Result<Vec<AnimationEvent>, GltfLoadError> {
+    let Some(extras) = animation.extras() else {
+        return Ok(Vec::new());
+    };
+
+    let invalid = |error: String| GltfLoadError::InvalidEvents {
+        animation: animation.name().unwrap_or("Unnamed").to_string(),
+        error,
+    };
+
+    let extras = serde_json::from_str::<serde_json::Value>(extras.get())
+        .map_err(|error| invalid(error.to_string()))?;
+    let Some(events) = extras.get("events") else {
+        return Ok(Vec::new());
+    };
+    let Some(events) = events.as_array() else {
+        return Err(invalid("'events' isn't an array".to_string()));
+    };
+
+    events
+        .iter()
+        .map(|event| {
+            let name = event.get("name").and_then(|name| name.as_str());
+            let time = event.get("time").and_then(|time| time.as_f64());
+            match (name, time) {
+                (Some(name), Some(time)) => Ok(AnimationEvent {
+                    name: name.to_string(),
+                    time: time as f32,
+                }),
+                _ => Err(invalid(format!("event {event} needs a name and a time"))),
+            }
+        })
+        .collect()
+}
+
+// events crossed while the playhead moved from `previous` to `time` (unwrapped, see `Playback::time`)
+// in a clip of `length` seconds, in the order they were crossed. The playhead crosses what lies between
+// where it was and where it is now, including the first but not the last, so an event it stops on is
+// crossed by the next update. Every event is crossed once per loop, also when playing in reverse or
+// skipping over several loops in one update. The end of a looping clip is its start, an event at both
+// ends is crossed once there
+pub fn crossed_events(
+    events: &[AnimationEvent],
+    previous: f32,
+    time: f32,
+    length: f32,
+    wrap_mode: WrapMode,
+) -> Vec<&AnimationEvent> {
+    if events.is_empty() || length.is_nan() || length <= 0.0 || previous == time {
+        return Vec::new();
+    }
+
+    let (from, to, period) = match wrap_mode {
+        WrapMode::Clamp | WrapMode::Once => {
+            (previous.clamp(0.0, length), time.clamp(0.0, length), None)
+        }
+        WrapMode::Loop => (previous, time, Some(length)),
+        WrapMode::PingPong => (previous, time, Some(length * 2.0)),
+    };
+    if from == to {
+        return Vec::new();
+    }
+    // playing clamped stops at the ends, events placed exactly there are crossed when reaching them
+    let reached_end = period.is_none() && (to == 0.0 || to == length);
+    let forward = to > from;
+    let crossed = |t: f32| {
+        if forward {
+            t >= from && (t < to || (reached_end && t == to))
+        } else {
+            t <= from && (t > to || (reached_end && t == to))
+        }
+    };
+
+    // unwrapped times the playhead is at each event
+    let mut instants = Vec::new();
+    for event in events {
+        let time = event.time.clamp(0.0, length);
+        let Some(period) = period else {
+            if crossed(time) {
+                instants.push((time, event));
+            }
+            continue;
+        };
+
+        let offsets = match wrap_mode {
+            WrapMode::PingPong if time > 0.0 && time < length => vec![time, period - time],
+            WrapMode::PingPong => vec![time],
+            _ if time < length => vec![time],
+            // the same event is already at the start
+            _ if events
+                .iter()
+                .any(|other| other.time == 0.0 && other.name == event.name) =>
+            {
+                continue
+            }
+            _ => vec![0.0],
+        };
+        for offset in offsets {
+            let mut cycle = ((from.min(to) - offset) / period).floor();
+            loop {
+                let instant = offset + cycle * period;
+                if instant > from.max(to) {
+                    break;
+                }
+                if crossed(instant) {
+                    instants.push((instant, event));
+                }
+                cycle += 1.0;
+            }
+        }
+    }
+
+    instants.sort_by(|(a, _), (b, _)| {
+        if forward {
+            a.total_cmp(b)
+        } else {
+            b.total_cmp(a)
+        }
+    });
+    instants.into_iter().map(|(_, event)| event).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playback::Playback;
+
+    fn events(events: &[(&str, f32)]) -> Vec<AnimationEvent> {
+        events
+            .iter()
+            .map(|(name, time)| AnimationEvent {
+                name: name.to_string(),
+                time: *time,
+            })
+            .collect()
+    }
+
+    fn names(crossed: Vec<&AnimationEvent>) -> Vec<&str> {
+        crossed.iter().map(|event| event.name.as_str()).collect()
+    }
+
+    #[test]
+    fn events_are_crossed_in_order_once_per_pass() {
+        use WrapMode::*;
+
+        // events, previous, time, wrap mode, expected for a clip of 1 second
+        type Case = (
+            &'static [(&'static str, f32)],
+            f32,
+            f32,
+            WrapMode,
+            &'static [&'static str],
+        );
+        #[rustfmt::skip]
+        let cases: &[Case] = &[
+            // inside the clip
+            (&[("a", 0.25), ("b", 0.5), ("c", 0.75)], 0.1, 0.6, Loop, &["a", "b"]),
+            (&[("a", 0.25), ("b", 0.5), ("c", 0.75)], 0.6, 0.1, Loop, &["b", "a"]),
+            // forward and reverse wraps
+            (&[("a", 0.25), ("b", 0.95)], 0.9, 1.3, Loop, &["b", "a"]),
+            (&[("a", 0.25), ("b", 0.95)], -0.1, 0.3, Loop, &["b", "a"]),
+            (&[("a", 0.05), ("b", 0.75)], 0.1, -0.3, Loop, &["a", "b"]),
+            (&[("a", 0.05), ("b", 0.75)], 1.1, 0.7, Loop, &["a", "b"]),
+            // several loops in one step
+            (&[("a", 0.25), ("b", 0.75)], 0.5, 2.75, Loop, &["b", "a", "b", "a"]),
+            (&[("a", 0.25), ("b", 0.75)], 2.5, 0.0, Loop, &["a", "b", "a", "b", "a"]),
+            // events the playhead stops on are crossed by the next step
+            (&[("a", 0.5)], 0.0, 0.5, Loop, &[]),
+            (&[("a", 0.5)], 0.5, 0.8, Loop, &["a"]),
+            (&[("a", 0.5)], 0.5, 0.2, Loop, &["a"]),
+            (&[("a", 0.0)], 0.0, 0.1, Loop, &["a"]),
+            // the ends of a loop are the same spot
+            (&[("step", 0.0), ("step", 1.0)], 0.9, 1.1, Loop, &["step"]),
+            (&[("step", 0.0), ("step", 1.0)], 1.0, 0.75, Loop, &["step"]),
+            (&[("start", 0.0)], 1.0, 0.75, Loop, &["start"]),
+            (&[("end", 1.0)], 0.9, 1.1, Loop, &["end"]),
+            (&[("start", 0.0), ("end", 1.0)], 1.0, 0.75, Loop, &["start", "end"]),
+            // ping-pong turns around at the end and at the start
+            (&[("a", 0.9), ("end", 1.0)], 0.75, 1.25, PingPong, &["a", "end", "a"]),
+            (&[("start", 0.0), ("a", 0.05)], 1.9, 2.1, PingPong, &["a", "start", "a"]),
+            (&[("a", 0.5)], 0.0, 4.0, PingPong, &["a", "a", "a", "a"]),
+            // clamped playback crosses events at the end it runs into
+            (&[("a", 0.5), ("end", 1.0)], 0.8, 1.5, Clamp, &["end"]),
+            (&[("start", 0.0), ("a", 0.1)], 0.2, -0.5, Once, &["a", "start"]),
+            (&[("end", 1.0)], 1.0, 1.0, Clamp, &[]),
+        ];
+
+        for (clip_events, previous, time, wrap_mode, expected) in cases {
+            let clip_events = events(clip_events);
+            let crossed = crossed_events(&clip_events, *previous, *time, 1.0, *wrap_mode);
+            assert_eq!(
+                names(crossed),
+                *expected,
+                "{previous} -> {time} with {wrap_mode:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn clips_without_a_length_have_no_events() {
+        let clip_events = events(&[("a", 0.0)]);
+        assert!(crossed_events(&clip_events, 0.0, 1.0, 0.0, WrapMode::Loop).is_empty());
+        assert!(crossed_events(&clip_events, 0.0, 1.0, f32::NAN, WrapMode::Loop).is_empty());
+    }
+
+    #[test]
+    fn playing_fires_an_event_once_per_loop() {
+        let clip_events = events(&[("step", 0.0), ("step", 1.0), ("mid", 0.5)]);
+        for speed in [1.0, -1.0, 0.3, -2.5] {
+            let mut playback = Playback::default().with_speed(speed);
+            let mut crossed = Vec::new();
+            // 4 loops in steps that land exactly on the events
+            for _ in 0..(16.0 / speed.abs()).round() as usize {
+                playback.advance(0.25, 1.0);
+                crossed.extend(names(crossed_events(
+                    &clip_events,
+                    playback.previous_time,
+                    playback.time,
+                    1.0,
+                    playback.wrap_mode,
+                )));
+            }
+
+            let count = |name| crossed.iter().filter(|event| **event == name).count();
+            assert_eq!(count("step"), 4, "speed {speed}");
+            assert_eq!(count("mid"), 4, "speed {speed}");
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use jandering_engine::types::{Qua, Vec3};
+
+use crate::skeleton::{Pose, Skeleton};
+
+// below this the solvers treat lengths and angles as zero
+const EPSILON: f32 = 1e-5;
+
+// constraints are solved in order after the clips are sampled, targets are in world space
+#[derive(Debug, Clone)]
+pub enum IkConstraint {
+    // analytic solver for limbs, `end` is a child of `middle` which is a child of `root`.
+    // The limb bends towards `pole` (e.g. in front of the knee), or keeps its current plane without one
+    TwoBone {
+        root: usize,
+        middle: usize,
+        end: usize,
+        target: Vec3,
+        pole: Option<Vec3>,
+        weight: f32,
+    },
+    // iterative solver for longer chains like tails and spines, `chain` goes from the root to the tip
+    Fabrik {
+        chain: Vec<usize>,
+        target: Vec3,
+        iterations: usize,
+        tolerance: f32,
+        weight: f32,
+    },
+    // rotates `node` so its local `forward` axis points at `target`
+    LookAt {
+        node: usize,
+        forward: Vec3,
+        target: Vec3,
+        weight: f32,
+    },
+}
+
+// world matrices of `pose` have to be up to date, they are kept up to date while solving
+pub fn solve(skeleton: &Skeleton, pose: &mut Pose, constraints: &[IkConstraint]) {
+    for constraint in constraints {
+        match constraint {
+            IkConstraint::TwoBone {
+                root,
+                middle,
+                end,
+                target,
+                pole,
+                weight,
+            } => solve_two_bone(
+                skeleton,
+                pose,
+                [*root, *middle, *end],
+                *target,
+                *pole,
+                *weight,
+            ),
+            IkConstraint::Fabrik {
+                chain,
+                target,
+                iterations,
+                tolerance,
+                weight,
+            } => solve_fabrik(
+                skeleton,
+                pose,
+                chain,
+                *target,
+                *iterations,
+                *tolerance,
+                *weight,
+            ),
+            IkConstraint::LookAt {
+                node,
+                forward,
+                target,
+                weight,
+            } => {
+                let world_forward = world_rotation(pose, *node) * *forward;
+                let to_target = *target - world_position(pose, *node);
+                let rotation = rotation_between(world_forward, to_target);
+                rotate_world(skeleton, pose, *node, rotation, *weight);
+            }
+        }
+    }
+}
+
+fn solve_two_bone(
+    skeleton: &Skeleton,
+    pose: &mut Pose,
+    [root, middle, end]: [usize; 3],
+    target: Vec3,
+    pole: Option<Vec3>,
+    weight: f32,
+) {
+    let a = world_position(pose, root);
+    let b = world_position(pose, middle);
+    let c = world_position(pose, end);
+
+    let ab_length = (b - a).length();
+    let cb_length = (b - c).length();
+    if ab_length < EPSILON || cb_length < EPSILON {
+        return;
+    }
+    // keep the limb slightly bent even when the target is out of reach
+    let at_length = (target - a)
+        .length()
+        .clamp(EPSILON, ab_length + cb_length - EPSILON);
+
+    // current and wanted angles at the root and middle joint, from the law of cosines
+    let ac_ab_0 = angle(c - a, b - a);
+    let ba_bc_0 = angle(a - b, c - b);
+    let ac_ab_1 = ((cb_length * cb_length - ab_length * ab_length - at_length * at_length)
+        / (-2.0 * ab_length * at_length))
+        .clamp(-1.0, 1.0)
+        .acos();
+    let ba_bc_1 = ((at_length * at_length - ab_length * ab_length - cb_length * cb_length)
+        / (-2.0 * ab_length * cb_length))
+        .clamp(-1.0, 1.0)
+        .acos();
+
+    // bend in the plane the limb already lies in, a straight limb can only use the pole to pick one
+    let bend_axis = (c - a)
+        .cross(b - a)
+        .try_normalize()
+        .or_else(|| pole.and_then(|pole| (c - a).cross(pole - a).try_normalize()))
+        .unwrap_or_else(|| {
+            (c - a)
+                .try_normalize()
+                .map_or(Vec3::X, |direction| direction.any_orthonormal_vector())
+        });
+
+    let bend_root = Qua::from_axis_angle(bend_axis, ac_ab_1 - ac_ab_0);
+    let bend_middle = Qua::from_axis_angle(bend_axis, ba_bc_1 - ba_bc_0);
+    let swing = rotation_between(c - a, target - a);
+
+    // the bends keep the end on the line from the root to the target, twisting about that line
+    // turns the middle joint towards the pole without moving the end
+    let twist = match (pole, (target - a).try_normalize()) {
+        (Some(pole), Some(twist_axis)) => {
+            let reject = |v: Vec3| v - twist_axis * v.dot(twist_axis);
+            let middle_direction = reject(swing * bend_root * (b - a));
+            let pole_direction = reject(pole - a);
+            if middle_direction.length_squared() < EPSILON
+                || pole_direction.length_squared() < EPSILON
+            {
+                Qua::IDENTITY
+            } else {
+                let twist_angle = twist_axis
+                    .dot(middle_direction.cross(pole_direction))
+                    .atan2(middle_direction.dot(pole_direction));
+                Qua::from_axis_angle(twist_axis, twist_angle)
+            }
+        }
+        _ => Qua::IDENTITY,
+    };
+
+    // the middle joint first, rotating the root afterwards carries it along
+    rotate_world(skeleton, pose, middle, bend_middle, weight);
+    rotate_world(skeleton, pose, root, twist * swing * bend_root, weight);
+}
+
+fn solve_fabrik(
+    skeleton: &Skeleton,
+    pose: &mut Pose,
+    chain: &[usize],
+    target: Vec3,
+    iterations: usize,
+    tolerance: f32,
+    weight: f32,
+) {
+    if chain.len() < 2 {
+        return;
+    }
+
+    let mut positions = chain
+        .iter()
+        .map(|node| world_position(pose, *node))
+        .collect::<Vec<_>>();
+    let lengths = positions
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).length())
+        .collect::<Vec<_>>();
+    let root = positions[0];
+
+    if (target - root).length() >= lengths.iter().sum::<f32>() {
+        // out of reach, stretch the chain towards the target
+        let direction = (target - root).normalize_or_zero();
+        for i in 1..positions.len() {
+            positions[i] = positions[i - 1] + direction * lengths[i - 1];
+        }
+    } else {
+        for _ in 0..iterations {
+            if (*positions.last().unwrap() - target).length() <= tolerance {
+                break;
+            }
+
+            // backward pass from the tip, then forward from the fixed root
+            *positions.last_mut().unwrap() = target;
+            for i in (0..positions.len() - 1).rev() {
+                let direction = (positions[i] - positions[i + 1]).normalize_or_zero();
+                positions[i] = positions[i + 1] + direction * lengths[i];
+            }
+
+            positions[0] = root;
+            for i in 1..positions.len() {
+                let direction = (positions[i] - positions[i - 1]).normalize_or_zero();
+                positions[i] = positions[i - 1] + direction * lengths[i - 1];
+            }
+        }
+    }
+
+    // turn every bone towards its solved child position, from the root down
+    for i in 0..chain.len() - 1 {
+        let current = world_position(pose, chain[i + 1]) - world_position(pose, chain[i]);
+        let wanted = positions[i + 1] - world_position(pose, chain[i]);
+        rotate_world(
+            skeleton,
+            pose,
+            chain[i],
+            rotation_between(current, wanted),
+            weight,
+        );
+    }
+}
+
+// apply a world space rotation to `node` and update the world matrices of its subtree
+fn rotate_world(skeleton: &Skeleton, pose: &mut Pose, node: usize, rotation: Qua, weight: f32) {
+    let rotation = Qua::IDENTITY.slerp(rotation, weight.clamp(0.0, 1.0));
+    let world = world_rotation(pose, node);
+    let parent = skeleton.nodes[node]
+        .parent
+        .map_or(Qua::IDENTITY, |parent| world_rotation(pose, parent));
+
+    pose.transforms[node].rotation = (parent.inverse() * rotation * world).normalize();
+    skeleton.update_subtree_world_matrices(pose, node);
+}
+
+fn world_position(pose: &Pose, node: usize) -> Vec3 {
+    pose.world_transforms[node].w_axis.truncate()
+}
+
+fn world_rotation(pose: &Pose, node: usize) -> Qua {
+    pose.world_transforms[node]
+        .to_scale_rotation_translation()
+        .1
+}
+
+fn angle(a: Vec3, b: Vec3) -> f32 {
+    match (a.try_normalize(), b.try_normalize()) {
+        (Some(a), Some(b)) => a.dot(b).clamp(-1.0, 1.0).acos(),
+        _ => 0.0,
+    }
+}
+
+fn rotation_between(from: Vec3, to: Vec3) -> Qua {
+    match (from.try_normalize(), to.try_normalize()) {
+        (Some(from), Some(to)) => Qua::from_rotation_arc(from, to),
+        _ => Qua::IDENTITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skeleton::{Node, NodeType, Transform};
+
+    fn node(translation: Vec3, children: Vec<usize>, parent: Option<usize>) -> Node {
+        Node {
+            name: String::new(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        }
+    }
+
+    // a leg hanging down from the origin with its knee bent forward along z
+    fn leg() -> Skeleton {
+        Skeleton {
+            nodes: vec![
+                node(Vec3::ZERO, vec![1], None),
+                node(Vec3::new(0.0, -1.0, 0.1), vec![2], Some(0)),
+                node(Vec3::new(0.0, -1.0, -0.1), vec![], Some(1)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    fn solve_leg(target: Vec3, pole: Option<Vec3>) -> Pose {
+        let skeleton = leg();
+        let mut pose = skeleton.rest_pose();
+        solve(
+            &skeleton,
+            &mut pose,
+            &[IkConstraint::TwoBone {
+                root: 0,
+                middle: 1,
+                end: 2,
+                target,
+                pole,
+                weight: 1.0,
+            }],
+        );
+        pose
+    }
+
+    #[test]
+    fn two_bone_reaches_the_target_in_its_own_plane() {
+        let target = Vec3::new(0.0, -1.5, 0.0);
+        let pose = solve_leg(target, None);
+
+        assert!(world_position(&pose, 2).abs_diff_eq(target, 1e-4));
+        // the knee still points forward
+        let knee = world_position(&pose, 1);
+        assert!(knee.x.abs() < 1e-4 && knee.z > 0.0);
+    }
+
+    #[test]
+    fn two_bone_twists_towards_the_pole() {
+        let target = Vec3::new(0.0, -1.5, 0.0);
+        let pose = solve_leg(target, Some(Vec3::new(5.0, -0.75, 0.0)));
+
+        assert!(world_position(&pose, 2).abs_diff_eq(target, 1e-4));
+        // the knee turned from z to x
+        let knee = world_position(&pose, 1);
+        assert!(knee.z.abs() < 1e-4 && knee.x > 0.0);
+    }
+
+    // three bones of length 1 standing up along y
+    fn tail() -> Skeleton {
+        Skeleton {
+            nodes: vec![
+                node(Vec3::ZERO, vec![1], None),
+                node(Vec3::Y, vec![2], Some(0)),
+                node(Vec3::Y, vec![3], Some(1)),
+                node(Vec3::Y, vec![], Some(2)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    fn solve_tail(target: Vec3) -> Pose {
+        let skeleton = tail();
+        let mut pose = skeleton.rest_pose();
+        solve(
+            &skeleton,
+            &mut pose,
+            &[IkConstraint::Fabrik {
+                chain: vec![0, 1, 2, 3],
+                target,
+                iterations: 20,
+                tolerance: 1e-4,
+                weight: 1.0,
+            }],
+        );
+        pose
+    }
+
+    #[test]
+    fn fabrik_reaches_the_target_without_stretching_bones() {
+        let target = Vec3::new(1.5, 1.5, 0.5);
+        let pose = solve_tail(target);
+
+        assert!(world_position(&pose, 3).abs_diff_eq(target, 1e-3));
+        assert!(world_position(&pose, 0).abs_diff_eq(Vec3::ZERO, 1e-5));
+        for bone in 0..3 {
+            let length = (world_position(&pose, bone + 1) - world_position(&pose, bone)).length();
+            assert!((length - 1.0).abs() < 1e-4, "bone {bone} is {length} long");
+        }
+    }
+
+    #[test]
+    fn fabrik_points_straight_at_targets_out_of_reach() {
+        let pose = solve_tail(Vec3::new(10.0, 0.0, 0.0));
+
+        for bone in 0..4 {
+            let expected = Vec3::new(bone as f32, 0.0, 0.0);
+            assert!(world_position(&pose, bone).abs_diff_eq(expected, 1e-4));
+        }
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        // a head on a neck turned about z, so its local and world axes differ
+        let mut skeleton = Skeleton {
+            nodes: vec![
+                node(Vec3::ZERO, vec![1], None),
+                node(Vec3::Y, vec![], Some(0)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        };
+        skeleton.nodes[0].transform.rotation = Qua::from_rotation_z(0.5);
+        let target = Vec3::new(2.0, 1.0, 3.0);
+        let look_at = |weight: f32| {
+            let mut pose = skeleton.rest_pose();
+            solve(
+                &skeleton,
+                &mut pose,
+                &[IkConstraint::LookAt {
+                    node: 1,
+                    forward: Vec3::Z,
+                    target,
+                    weight,
+                }],
+            );
+            let position = world_position(&pose, 1);
+            (
+                world_rotation(&pose, 1) * Vec3::Z,
+                (target - position).normalize(),
+                position,
+            )
+        };
+
+        let (forward, to_target, position) = look_at(1.0);
+        assert!(forward.abs_diff_eq(to_target, 1e-4));
+        // only turns, the head stays on the neck
+        assert!(position.abs_diff_eq(Qua::from_rotation_z(0.5) * Vec3::Y, 1e-5));
+
+        let (rest_forward, _, _) = look_at(0.0);
+        let (half_forward, _, _) = look_at(0.5);
+        assert!((angle(rest_forward, half_forward) - angle(half_forward, to_target)).abs() < 1e-4);
+    }
+}
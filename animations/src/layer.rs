@@ -0,0 +1,301 @@
+use jandering_engine::types::{Qua, Vec3};
+
+use crate::{
+    animation::Animation,
+    playback::Playback,
+    skeleton::{Pose, Skeleton},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerBlendMode {
+    // blends from the pose below towards the layer's clip
+    Override,
+    // adds the difference between the clip and a reference pose on top of the pose below
+    Additive,
+}
+
+// what an additive clip is relative to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferencePose {
+    Rest,
+    FirstFrame,
+    Clip { animation: usize, time: f32 },
+}
+
+// a clip played on top of the base animation, layers are applied in order
+#[derive(Debug, Clone)]
+pub struct AnimationLayer {
+    pub animation: usize,
+    pub weight: f32,
+    pub blend_mode: LayerBlendMode,
+    pub reference: ReferencePose,
+    // name of the node whose subtree the layer affects, e.g. the spine for upper body layers.
+    // Affects every node without one
+    pub mask_root: Option<String>,
+    pub playback: Playback,
+
+    // resolved by `prepare`
+    mask: Option<Vec<bool>>,
+    reference_pose: Option<Pose>,
+}
+
+impl AnimationLayer {
+    pub fn new(animation: usize) -> Self {
+        Self {
+            animation,
+            weight: 1.0,
+            blend_mode: LayerBlendMode::Override,
+            reference: ReferencePose::FirstFrame,
+            mask_root: None,
+            playback: Playback::default(),
+            mask: None,
+            reference_pose: None,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_mask(mut self, root: &str) -> Self {
+        self.mask_root = Some(root.to_string());
+        self
+    }
+
+    pub fn with_playback(mut self, playback: Playback) -> Self {
+        self.playback = playback;
+        self
+    }
+
+    pub fn additive(mut self, reference: ReferencePose) -> Self {
+        self.blend_mode = LayerBlendMode::Additive;
+        self.reference = reference;
+        self
+    }
+
+    // resolve the mask and sample the reference pose, returns false if the mask root doesn't exist
+    pub fn prepare(&mut self, skeleton: &Skeleton, animations: &[Animation]) -> bool {
+        self.mask = match &self.mask_root {
+            Some(root) => match skeleton.nodes.iter().position(|node| &node.name == root) {
+                Some(root) => Some(skeleton.subtree_mask(root)),
+                None => return false,
+            },
+            None => None,
+        };
+
+        let mut reference_pose = skeleton.rest_pose();
+        match self.reference {
+            ReferencePose::Rest => {}
+            ReferencePose::FirstFrame => {
+                if let Some(animation) = animations.get(self.animation) {
+                    reference_pose.apply(animation, 0.0);
+                }
+            }
+            ReferencePose::Clip { animation, time } => {
+                if let Some(animation) = animations.get(animation) {
+                    reference_pose.apply(animation, time);
+                }
+            }
+        }
+        self.reference_pose = Some(reference_pose);
+
+        true
+    }
+
+    // blend the layer into the local transforms of `pose`, world matrices have to be updated afterwards
+    pub fn apply(&self, skeleton: &Skeleton, animations: &[Animation], pose: &mut Pose) {
+        let Some(animation) = animations.get(self.animation) else {
+            return;
+        };
+        if self.weight <= 0.0 {
+            return;
+        }
+
+        let mut layer_pose = skeleton.rest_pose();
+        layer_pose.apply(animation, self.playback.clip_time(animation.length));
+
+        let masked = |node: usize| self.mask.as_ref().is_none_or(|mask| mask[node]);
+        match self.blend_mode {
+            LayerBlendMode::Override => {
+                pose.blend_masked(&layer_pose, self.weight.min(1.0), self.mask.as_deref())
+            }
+            LayerBlendMode::Additive => {
+                // not prepared
+                let Some(reference_pose) = &self.reference_pose else {
+                    return;
+                };
+
+                for node in (0..pose.transforms.len()).filter(|node| masked(*node)) {
+                    let transform = &mut pose.transforms[node];
+                    let layer = layer_pose.transforms[node];
+                    let reference = reference_pose.transforms[node];
+
+                    let rotation = reference.rotation.inverse() * layer.rotation;
+                    transform.rotation = (transform.rotation
+                        * Qua::IDENTITY.slerp(rotation, self.weight))
+                    .normalize();
+                    transform.translation +=
+                        (layer.translation - reference.translation) * self.weight;
+                    // bones scaled to 0 in the reference are hidden there, they're left as they are
+                    let scale = Vec3::select(
+                        reference.scale.cmpeq(Vec3::ZERO),
+                        Vec3::ONE,
+                        layer.scale / reference.scale,
+                    );
+                    transform.scale *= Vec3::ONE.lerp(scale, self.weight);
+
+                    for (weight, (layer, reference)) in pose.weights[node].iter_mut().zip(
+                        layer_pose.weights[node]
+                            .iter()
+                            .zip(reference_pose.weights[node].iter()),
+                    ) {
+                        *weight += (layer - reference) * self.weight;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::{
+        animation::{Interpolation, Keyframes, Track},
+        skeleton::{Node, NodeType, Transform},
+    };
+
+    // hips with a spine and an arm above them, each 1 above its parent
+    fn body() -> Skeleton {
+        let node = |name: &str, children, parent| Node {
+            name: name.to_string(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation: Vec3::Y,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        };
+        Skeleton {
+            nodes: vec![
+                node("hips", vec![1], None),
+                node("spine", vec![2], Some(0)),
+                node("arm", Vec::new(), Some(1)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    fn track(target: usize, keyframes: Keyframes) -> Track {
+        Track {
+            keyframes,
+            interpolation: Interpolation::Linear,
+            target,
+            timestamps: vec![0.0, 1.0],
+        }
+    }
+
+    fn clip(tracks: Vec<Track>) -> Animation {
+        Animation {
+            name: "layer".to_string(),
+            length: 1.0,
+            tracks,
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn override_layers_only_affect_their_mask() {
+        let skeleton = body();
+        let turned = Qua::from_rotation_z(FRAC_PI_2);
+        let animations = [clip(
+            (0..3)
+                .map(|node| track(node, Keyframes::Rotations(vec![turned; 2])))
+                .collect(),
+        )];
+
+        let mut layer = AnimationLayer::new(0).with_weight(0.5).with_mask("spine");
+        assert!(layer.prepare(&skeleton, &animations));
+        let mut pose = skeleton.rest_pose();
+        layer.apply(&skeleton, &animations, &mut pose);
+
+        assert_eq!(pose.transforms[0].rotation, Qua::IDENTITY);
+        for node in [1, 2] {
+            assert!(pose.transforms[node]
+                .rotation
+                .abs_diff_eq(Qua::from_rotation_z(FRAC_PI_2 * 0.5), 1e-5));
+        }
+
+        // masks have to name a node
+        assert!(!AnimationLayer::new(0)
+            .with_mask("tail")
+            .prepare(&skeleton, &animations));
+    }
+
+    #[test]
+    fn additive_layers_add_their_difference_to_the_reference() {
+        let skeleton = body();
+        let animations = [clip(vec![
+            track(
+                1,
+                Keyframes::Translations(vec![Vec3::Y, Vec3::new(1.0, 1.0, 0.0)]),
+            ),
+            track(
+                1,
+                Keyframes::Rotations(vec![Qua::IDENTITY, Qua::from_rotation_z(FRAC_PI_2)]),
+            ),
+            track(1, Keyframes::Scales(vec![Vec3::ONE, Vec3::splat(3.0)])),
+        ])];
+
+        let mut layer = AnimationLayer::new(0).additive(ReferencePose::FirstFrame);
+        layer.playback.seek(0.5);
+        assert!(layer.prepare(&skeleton, &animations));
+
+        let lean = Qua::from_rotation_x(0.3);
+        let mut pose = skeleton.rest_pose();
+        pose.transforms[1].translation = Vec3::new(0.0, 2.0, 0.0);
+        pose.transforms[1].rotation = lean;
+        pose.transforms[1].scale = Vec3::splat(1.5);
+        layer.apply(&skeleton, &animations, &mut pose);
+
+        // half of the clip's movement, turn and growth on top of the pose below
+        let transform = pose.transforms[1];
+        assert!(transform
+            .translation
+            .abs_diff_eq(Vec3::new(0.5, 2.0, 0.0), 1e-5));
+        assert!(transform
+            .rotation
+            .abs_diff_eq(lean * Qua::from_rotation_z(FRAC_PI_2 * 0.5), 1e-5));
+        assert!(transform.scale.abs_diff_eq(Vec3::splat(3.0), 1e-5));
+        // nodes the clip doesn't animate don't change
+        assert_eq!(pose.transforms[2].translation, Vec3::Y);
+    }
+
+    #[test]
+    fn hidden_bones_in_the_reference_keep_their_scale() {
+        let skeleton = body();
+        let hidden = Vec3::new(0.0, 1.0, 1.0);
+        let animations = [clip(vec![track(
+            1,
+            Keyframes::Scales(vec![hidden, Vec3::new(0.0, 2.0, 1.0)]),
+        )])];
+
+        let mut layer = AnimationLayer::new(0).additive(ReferencePose::FirstFrame);
+        layer.playback.seek(0.5);
+        assert!(layer.prepare(&skeleton, &animations));
+        let mut pose = skeleton.rest_pose();
+        layer.apply(&skeleton, &animations, &mut pose);
+
+        assert!(pose.transforms[1]
+            .scale
+            .abs_diff_eq(Vec3::new(1.0, 1.5, 1.0), 1e-5));
+    }
+}
@@ -1,14 +1,76 @@
 use std::env;
 
-use animated_object::{AnimatedObject, AnimatedVertex};
+use animated_object::{resolve_path, AnimatedObject};
+use animator::BlendClip;
+use crowd::{Crowd, CrowdInstance};
+use debug::SkeletonDebug;
 use jandering_engine::{
-    engine::{Engine, EngineConfig}, render_pass::RenderPass, renderer::Janderer, shader::ShaderDescriptor, texture::{texture_usage, TextureDescriptor, TextureFormat}, types::Vec3, utils::free_camera::{FreeCameraController, MatrixCamera}, window::{InputState, Key, WindowConfig, WindowManagerTrait, WindowTrait}
+    engine::{Engine, EngineConfig}, render_pass::RenderPass, renderer::Janderer, shader::ShaderDescriptor, texture::{texture_usage, TextureDescriptor, TextureFormat}, types::{Mat4, Vec3}, utils::free_camera::{FreeCameraController, MatrixCamera}, window::{InputState, Key, WindowConfig, WindowManagerTrait, WindowTrait}
 };
+use ik::IkConstraint;
+use layer::{AnimationLayer, ReferencePose};
+use material::Material;
+use mesh::{AnimatedVertex, Mesh};
+use playback::{Playback, WrapMode};
+use retarget::BoneMap;
+use state_machine::{Condition, Parameter, StateMachine, Transition};
 
 mod animated_object;
+mod animation;
+mod animator;
+mod bake;
+mod bench;
+mod bounds;
+mod compression;
+mod crowd;
+mod debug;
+mod error;
+mod events;
+mod ik;
+mod layer;
+mod material;
+mod mesh;
+mod playback;
+mod retarget;
+mod root_motion;
+mod scene;
+mod skeleton;
+mod socket;
+mod state_machine;
+
+const CROSSFADE_DURATION: f32 = 0.3;
+const CAMERA_FOV: f32 = 40.0;
+const CROWD_SIZE: (usize, usize) = (20, 10);
+const CROWD_SPACING: f32 = 3.0;
 
 fn main() {
-    let model_file_name = std::env::args().nth(1).unwrap_or("character.gltf".to_string());
+    let args = env::args().collect::<Vec<_>>();
+    if args.get(1).is_some_and(|arg| arg == "--bench") {
+        let model_file_name = args.get(2).cloned().unwrap_or("character.gltf".to_string());
+        if let Err(e) = bench::run(&model_file_name) {
+            eprintln!("failed to load '{model_file_name}': {e}");
+        }
+        return;
+    }
+
+    // `--retarget <model> [source=target]...` adds the clips of another model, played by the bones with the same name
+    // and the ones given
+    let (args, retarget_args) = match args.iter().position(|arg| arg == "--retarget") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (&args[..], &[][..]),
+    };
+
+    // `--bake <model> [file]` writes the loaded model to a file that loads without parsing the gltf,
+    // next to the model by default
+    let baking = args.get(1).is_some_and(|arg| arg == "--bake");
+    let args = if baking { &args[1..] } else { args };
+    let model_file_name = args.get(1).cloned().unwrap_or("character.gltf".to_string());
+    let bake_file_name = args.get(2).cloned().unwrap_or_else(|| {
+        resolve_path(&model_file_name)
+            .with_extension(bake::BAKE_EXTENSION.trim_start_matches('.'))
+            .display()
+            .to_string()
+    });
 
     let mut engine = pollster::block_on(Engine::new(EngineConfig {
         writable_storage: true,
@@ -27,9 +89,7 @@ fn main() {
     let renderer = &mut engine.renderer;
 
     let mut camera = MatrixCamera::with_controller(renderer, FreeCameraController::default());
-    camera.make_perspective(40.0, 1.0, 0.01, 10000.0);
-    camera.set_position(Vec3::new(10.0, 10.0, 10.0));
-    camera.set_direction(-camera.position().normalize());
+    camera.make_perspective(CAMERA_FOV, 1.0, 0.01, 10000.0);
 
     let depth_texture = renderer.create_texture(TextureDescriptor {
         name: "depth_texture",
@@ -44,12 +104,185 @@ fn main() {
             jandering_engine::utils::FilePath::FileName("shader.wgsl"),
         ),
         descriptors: vec![AnimatedVertex::desc(), AnimatedObject::instance_desc()],
-        bind_group_layout_descriptors: vec![MatrixCamera::get_layout_descriptor(), AnimatedObject::joint_data_bind_group_layout_descriptor()],
+        bind_group_layout_descriptors: vec![
+            MatrixCamera::get_layout_descriptor(),
+            AnimatedObject::joint_data_bind_group_layout_descriptor(),
+            Material::get_layout_descriptor(),
+            Mesh::morph_bind_group_layout_descriptor(),
+        ],
         depth: true,
         ..Default::default()
     });
 
-    let mut animated_object = pollster::block_on(AnimatedObject::from_gltf(renderer, &model_file_name));
+    let debug_shader = renderer.create_shader(ShaderDescriptor {
+        name: "debug_shader",
+        source: jandering_engine::shader::ShaderSource::File(
+            jandering_engine::utils::FilePath::FileName("debug_shader.wgsl"),
+        ),
+        descriptors: vec![SkeletonDebug::desc()],
+        bind_group_layout_descriptors: vec![MatrixCamera::get_layout_descriptor()],
+        depth: true,
+        backface_culling: false,
+        ..Default::default()
+    });
+
+    let loaded = if model_file_name.ends_with(bake::BAKE_EXTENSION) {
+        AnimatedObject::from_bake(renderer, &model_file_name).map_err(|e| e.to_string())
+    } else {
+        AnimatedObject::from_gltf(renderer, &model_file_name).map_err(|e| e.to_string())
+    };
+    let mut animated_object = match loaded {
+        Ok(animated_object) => animated_object,
+        Err(e) => {
+            eprintln!("failed to load '{model_file_name}': {e}");
+            return;
+        }
+    };
+
+    if let Some(source_file_name) = retarget_args.first() {
+        match AnimatedObject::from_gltf(renderer, source_file_name) {
+            Ok(source) => {
+                let bone_map = retarget_args[1..]
+                    .iter()
+                    .filter_map(|bones| bones.split_once('='))
+                    .fold(BoneMap::matching_names(&source.skeleton, &animated_object.skeleton), |bone_map, (source, target)| bone_map.with_bone(source, target));
+                for animation in 0..source.animator.animations.len() {
+                    animated_object.add_retargeted(&source, animation, &bone_map);
+                }
+            }
+            Err(e) => eprintln!("failed to load '{source_file_name}': {e}"),
+        }
+    }
+
+    // every clip starts with an event, so G shows where they loop
+    for animation in animated_object.animator.animations.iter_mut() {
+        animation.add_event("start", 0.0);
+    }
+
+    if baking {
+        match std::fs::write(&bake_file_name, bake::bake(&animated_object.to_baked())) {
+            Ok(()) => println!("baked '{model_file_name}' to '{bake_file_name}'"),
+            Err(e) => eprintln!("failed to write '{bake_file_name}': {e}"),
+        }
+        return;
+    }
+
+    // look at the model from above and to the side, far enough away that every clip fits on screen
+    let model_bounds = animated_object
+        .clip_bounds
+        .iter()
+        .fold(animated_object.bounds, |bounds, clip| bounds.union(*clip));
+    let radius = (model_bounds.size().length() * 0.5).max(0.01);
+    let view_direction = Vec3::ONE.normalize();
+    let distance = radius / (CAMERA_FOV.to_radians() * 0.5).sin();
+    camera.set_position(model_bounds.center() + view_direction * distance);
+    camera.set_direction(-view_direction);
+
+    // every clip is a state that's crossfaded to from any other one while the clip parameter is its index, Q and E
+    // change the parameter. N plays them one after another instead, moving on to the next one at the end of a loop
+    let animations = &animated_object.animator.animations;
+    let mut state_machine = animations.iter().enumerate().fold(
+        StateMachine::new(animations.first().map_or("", |animation| &animation.name)),
+        |state_machine, (i, animation)| {
+            state_machine
+                .with_state(&animation.name, &animation.name)
+                .with_transition(
+                    Transition::new(None, &animation.name)
+                        .with_condition(Condition::False("playlist".to_string()))
+                        .with_condition(Condition::Greater("clip".to_string(), i as f32 - 0.5))
+                        .with_condition(Condition::Less("clip".to_string(), i as f32 + 0.5))
+                        .with_blend_duration(CROSSFADE_DURATION),
+                )
+                .with_transition(
+                    Transition::new(Some(&animation.name), &animations[(i + 1) % animations.len()].name)
+                        .with_condition(Condition::True("playlist".to_string()))
+                        .with_exit_time(1.0)
+                        .with_blend_duration(CROSSFADE_DURATION),
+                )
+        },
+    );
+    let mut clip = 0;
+    state_machine.set_float("clip", 0.0);
+    state_machine.set_bool("playlist", false);
+
+    // 1 to 4 loop, clamp, ping-pong or play the clip once, T starts it again and 7 blends the first two clips
+    let mut finished = false;
+    let mut blending = false;
+
+    // 5 plays the second clip slowly on the upper body, 6 adds the last clip on top, relative to the rest pose, its
+    // first frame or the first frame of the first clip, one after another
+    let clip_count = animated_object.animator.animations.len();
+    let upper_body_layer = (clip_count > 1)
+        .then(|| animated_object.add_layer(AnimationLayer::new(1).with_mask("mixamorig:Spine").with_weight(0.0).with_playback(Playback::default().with_speed(0.5))))
+        .flatten();
+    let additive_layers = [ReferencePose::Rest, ReferencePose::FirstFrame, ReferencePose::Clip { animation: 0, time: 0.0 }]
+        .map(|reference| clip_count.checked_sub(1).and_then(|last| animated_object.add_layer(AnimationLayer::new(last).additive(reference).with_weight(0.0))));
+    let mut upper_body = false;
+    let mut additive_layer = None;
+
+    // M takes the root motion out of the hips, the character walks on the spot and the camera moves back by how far it
+    // would have walked
+    let hips = animated_object.find_node("mixamorig:Hips");
+    let mut root_motion = false;
+
+    // the view starts from the first camera of the gltf and follows its node while it's animated, V switches to
+    // the free camera and back
+    let mut follow_scene_camera = !animated_object.scene.cameras.is_empty();
+    let mut aspect_ratio = 1.0;
+
+    // L makes the head follow the camera, its forward axis is taken from the rest pose where the model faces +Z
+    let head = animated_object.find_node("mixamorig:Head");
+    let head_forward = head.map(|head| {
+        animated_object
+            .node_world_transform(head)
+            .to_scale_rotation_translation()
+            .1
+            .inverse()
+            * Vec3::Z
+    });
+    let mut look_at_camera = false;
+
+    // K makes it look at its right hand instead, through a socket at about the fingertips
+    let right_hand = animated_object
+        .find_node("mixamorig:RightHandMiddle1")
+        .map(|knuckles| Mat4::from_translation(animated_object.skeleton.nodes[knuckles].transform.translation * 2.0))
+        .and_then(|fingertips| animated_object.socket("mixamorig:RightHand", fingertips));
+    let mut look_at_hand = false;
+
+    // F plants the feet where they are with two bone IK, J makes the left arm reach for the camera with FABRIK
+    let legs = ["Left", "Right"].map(|side| {
+        let [root, middle, end] = ["UpLeg", "Leg", "Foot"].map(|bone| animated_object.find_node(&format!("mixamorig:{side}{bone}")));
+        Some([root?, middle?, end?])
+    });
+    let mut planted_feet = Vec::new();
+    let left_arm = ["mixamorig:LeftArm", "mixamorig:LeftForeArm", "mixamorig:LeftHand"]
+        .iter()
+        .map(|name| animated_object.find_node(name))
+        .collect::<Option<Vec<_>>>();
+    let mut reach = false;
+
+    // C shows a crowd behind the character, every copy playing a different clip from a different time at a different speed
+    let crowd_instances = (0..CROWD_SIZE.0 * CROWD_SIZE.1)
+        .map(|i| {
+            let (x, z) = ((i % CROWD_SIZE.0) as f32, (i / CROWD_SIZE.0) as f32);
+            let position = Vec3::new((x - CROWD_SIZE.0 as f32 / 2.0) * CROWD_SPACING, 0.0, -(z + 1.0) * CROWD_SPACING);
+            let mut instance = CrowdInstance::new(Mat4::from_translation(position), i % animated_object.animator.animations.len().max(1))
+                .with_playback(Playback::default().with_speed(0.75 + (i % 3) as f32 * 0.25));
+            instance.playback.seek(i as f32 * 0.37);
+            instance
+        })
+        .collect();
+    let mut crowd = Crowd::new(renderer, &animated_object, crowd_instances);
+    let mut show_crowd = false;
+
+    // B draws the skeleton over the model, H shows the weights of the selected joint, Z and X select the joint
+    let mut skeleton_debug = SkeletonDebug::new(renderer, &animated_object.skeleton);
+    let mut show_skeleton = false;
+    let mut show_heat_map = false;
+    let mut selected_joint = 0;
+
+    // G prints the events the clips cross and the selected joint
+    let mut logging = false;
 
     let mut time = 0.0;
     let mut last_time = std::time::Instant::now();
@@ -67,6 +300,8 @@ fn main() {
                 jandering_engine::engine::EngineEvent::FileChanged(file_name) => {
                     if file_name == "shader.wgsl" {
                         renderer.reload_shader(shader);
+                    } else if file_name == "debug_shader.wgsl" {
+                        renderer.reload_shader(debug_shader);
                     }
                 }
             }
@@ -95,7 +330,8 @@ fn main() {
                 }
                 jandering_engine::window::WindowEvent::Resized((width, height)) => {
                     renderer.resize(&window, *width, *height);
-                    camera.make_perspective(40.0, *width as f32 / *height as f32, 0.01, 10000.0);
+                    aspect_ratio = *width as f32 / (*height).max(1) as f32;
+                    camera.make_perspective(CAMERA_FOV, aspect_ratio, 0.01, 10000.0);
                     renderer.re_create_texture(
                         TextureDescriptor {
                             name: "depth_texture",
@@ -112,39 +348,188 @@ fn main() {
                     state: InputState::Pressed,
                 } => match key {
                     Key::Q => {
-                        animated_object.current_animation = if animated_object.current_animation == 0 {
-                            animated_object.animations.len() - 1
-                        }else {
-                            animated_object.current_animation - 1
-                        };
+                        clip = (clip + animated_object.animator.animations.len().max(1) - 1) % animated_object.animator.animations.len().max(1);
+                        state_machine.set_float("clip", clip as f32);
                     }
                     Key::E => {
-                        animated_object.current_animation = if animated_object.current_animation + 1 >= animated_object.animations.len() {
+                        clip = (clip + 1) % animated_object.animator.animations.len().max(1);
+                        state_machine.set_float("clip", clip as f32);
+                    }
+                    Key::N => {
+                        // carry on from the clip the playlist got to
+                        if let Some(state) = state_machine.current_state() {
+                            clip = animated_object.animator.find_animation(&state.animation).unwrap_or(clip);
+                        }
+                        state_machine.set_float("clip", clip as f32);
+                        let playlist = state_machine.parameter("playlist") == Some(Parameter::Bool(true));
+                        state_machine.set_bool("playlist", !playlist);
+                    }
+                    Key::Key1 => animated_object.animator.playback = animated_object.animator.playback.with_wrap_mode(WrapMode::Loop),
+                    Key::Key2 => animated_object.animator.playback = animated_object.animator.playback.with_wrap_mode(WrapMode::Clamp),
+                    Key::Key3 => animated_object.animator.playback = animated_object.animator.playback.with_wrap_mode(WrapMode::PingPong),
+                    Key::Key4 => animated_object.animator.playback = animated_object.animator.playback.with_wrap_mode(WrapMode::Once),
+                    Key::T => {
+                        animated_object.animator.seek(0.0);
+                        animated_object.animator.playback.paused = false;
+                    }
+                    Key::Key5 => {
+                        upper_body = !upper_body;
+                        if let Some(layer) = upper_body_layer {
+                            animated_object.animator.set_layer_weight(layer, if upper_body { 1.0 } else { 0.0 });
+                        }
+                    }
+                    Key::Key6 => {
+                        additive_layer = match additive_layer {
+                            None => Some(0),
+                            Some(i) if i + 1 < additive_layers.len() => Some(i + 1),
+                            Some(_) => None,
+                        };
+                        for (i, layer) in additive_layers.iter().enumerate() {
+                            if let Some(layer) = layer {
+                                animated_object.animator.set_layer_weight(*layer, if additive_layer == Some(i) { 1.0 } else { 0.0 });
+                            }
+                        }
+                    }
+                    Key::Key7 => {
+                        blending = !blending && clip_count > 1;
+                        let clips = [0, 1].map(|animation| BlendClip { animation, weight: 0.5 });
+                        animated_object.animator.set_blend(if blending { clips.to_vec() } else { Vec::new() });
+                    }
+                    Key::M => {
+                        root_motion = !root_motion && hips.is_some();
+                        animated_object.set_root_motion_node(hips.filter(|_| root_motion));
+                    }
+                    Key::L => look_at_camera = !look_at_camera,
+                    Key::K => look_at_hand = !look_at_hand,
+                    Key::F => {
+                        planted_feet = if planted_feet.is_empty() {
+                            legs.iter()
+                                .flatten()
+                                .map(|&[root, middle, end]| IkConstraint::TwoBone {
+                                    root,
+                                    middle,
+                                    end,
+                                    target: animated_object.node_world_transform(end).w_axis.truncate(),
+                                    pole: None,
+                                    weight: 1.0,
+                                })
+                                .collect()
+                        } else {
+                            Vec::new()
+                        };
+                    }
+                    Key::J => reach = !reach,
+                    Key::V => {
+                        follow_scene_camera = !follow_scene_camera && !animated_object.scene.cameras.is_empty();
+                        camera.make_perspective(CAMERA_FOV, aspect_ratio, 0.01, 10000.0);
+                    }
+                    Key::C => show_crowd = !show_crowd,
+                    Key::B => show_skeleton = !show_skeleton,
+                    Key::H => show_heat_map = !show_heat_map,
+                    Key::G => logging = !logging,
+                    Key::Z => {
+                        selected_joint = if selected_joint == 0 {
+                            skeleton_debug.joints.len().saturating_sub(1)
+                        } else {
+                            selected_joint - 1
+                        };
+                        if let Some(joint) = skeleton_debug.joints.get(selected_joint).filter(|_| logging) {
+                            println!("selected joint: {}", animated_object.skeleton.nodes[*joint].name);
+                        }
+                    }
+                    Key::X => {
+                        selected_joint = if selected_joint + 1 >= skeleton_debug.joints.len() {
                             0
-                        }else {
-                            animated_object.current_animation + 1
+                        } else {
+                            selected_joint + 1
                         };
+                        if let Some(joint) = skeleton_debug.joints.get(selected_joint).filter(|_| logging) {
+                            println!("selected joint: {}", animated_object.skeleton.nodes[*joint].name);
+                        }
                     }
+                    Key::P => animated_object.animator.playback.paused = !animated_object.animator.playback.paused,
+                    Key::R => animated_object.animator.playback = animated_object.animator.playback.with_speed(-animated_object.animator.playback.speed),
                     _ => {}
                 },
                 _ => {}
             }
         }
 
-        animated_object.update(renderer);
+        animated_object.ik_constraints.clone_from(&planted_feet);
+        if let (true, Some(chain)) = (reach, &left_arm) {
+            animated_object.ik_constraints.push(IkConstraint::Fabrik {
+                chain: chain.clone(),
+                target: camera.position(),
+                iterations: 10,
+                tolerance: 0.01,
+                weight: 1.0,
+            });
+        }
+        // the hand from the last frame, its pose for this one isn't known before the head is turned
+        let hand = right_hand.filter(|_| look_at_hand).map(|socket| socket.world_transform(&animated_object).w_axis.truncate());
+        if let (true, Some(node), Some(forward)) = (look_at_camera || hand.is_some(), head, head_forward) {
+            animated_object.ik_constraints.push(IkConstraint::LookAt {
+                node,
+                forward,
+                target: hand.unwrap_or(camera.position()),
+                weight: 1.0,
+            });
+        }
+
+        state_machine.update(&mut animated_object.animator);
+        for event in animated_object.update(renderer, dt).into_iter().filter(|event| logging && event.weight > 0.0) {
+            println!("{} ({})", event.name, animated_object.animator.animations[event.animation].name);
+        }
+        if animated_object.animator.is_finished() && !finished && logging {
+            println!("{} finished", animated_object.animator.animations[animated_object.animator.current_animation].name);
+        }
+        finished = animated_object.animator.is_finished();
+
+        if root_motion {
+            camera.set_position(camera.position() - animated_object.animator.root_motion().translation);
+        }
+
+        let selected_node = skeleton_debug.joints.get(selected_joint).copied();
+        animated_object.set_highlighted_node(renderer, selected_node.filter(|_| show_heat_map));
+        if show_skeleton {
+            skeleton_debug.highlighted_node = selected_node;
+            skeleton_debug.update(renderer, &animated_object.pose);
+        }
 
+        if let Some(scene_camera) = animated_object.scene.cameras.first().filter(|_| follow_scene_camera) {
+            scene_camera.apply(&mut camera, &animated_object.pose, aspect_ratio);
+        }
         camera.update(renderer, &events, dt);
 
-        if window.is_initialized() {
-            let animated_meshes = animated_object.meshes.iter().collect::<Vec<_>>();
+        if show_crowd {
+            crowd.update(renderer, &animated_object, dt, Some(camera.matrix()));
+        }
 
-            let main_pass = RenderPass::new(&mut window)
+        if window.is_initialized() {
+            let mut main_pass = RenderPass::new(&mut window)
                 .set_shader(shader)
                 .with_depth(depth_texture, Some(1.0))
                 .with_clear_color(0.6, 0.5, 0.4)
-                .bind(0, camera.bind_group())
-                .bind(1, animated_object.joint_data_bind_group)
-                .render(&animated_meshes);
+                .bind(0, camera.bind_group());
+            for draw in animated_object.draws() {
+                main_pass = main_pass
+                    .bind(1, draw.joint_data_bind_group)
+                    .bind(2, draw.material_bind_group)
+                    .bind(3, draw.morph_bind_group)
+                    .render(&[draw.primitive]);
+            }
+            if show_crowd {
+                for draw in crowd.draws(&animated_object) {
+                    main_pass = main_pass
+                        .bind(1, draw.joint_data_bind_group)
+                        .bind(2, draw.material_bind_group)
+                        .bind(3, draw.morph_bind_group)
+                        .render(&[&draw.primitive]);
+                }
+            }
+            if show_skeleton {
+                main_pass = main_pass.set_shader(debug_shader).bind(0, camera.bind_group()).render(&[&skeleton_debug]);
+            }
             renderer.submit_pass(main_pass);
 
             window.request_redraw();
@@ -0,0 +1,377 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use jandering_engine::{
+    bind_group::{
+        BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutDescriptorEntry,
+        BindGroupLayoutEntry, SamplerType, TextureSampleType,
+    },
+    renderer::{BindGroupHandle, BufferHandle, Janderer, Renderer, SamplerHandle, TextureHandle},
+    texture::{
+        sampler::{SamplerAddressMode, SamplerDescriptor, SamplerFilterMode},
+        texture_usage, TextureDescriptor, TextureFormat,
+    },
+    types::UVec2,
+};
+
+use crate::{animated_object::load_uri, error::GltfLoadError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    // drawn after all opaque primitives
+    Blend,
+}
+
+impl From<gltf::material::AlphaMode> for AlphaMode {
+    fn from(value: gltf::material::AlphaMode) -> Self {
+        match value {
+            gltf::material::AlphaMode::Opaque => Self::Opaque,
+            gltf::material::AlphaMode::Mask => Self::Mask,
+            gltf::material::AlphaMode::Blend => Self::Blend,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct MaterialData {
+    base_color_factor: [f32; 4],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    alpha_cutoff: f32,
+    alpha_mode: u32,
+    padding: [u32; 3],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Texture {
+    pub texture: TextureHandle,
+    pub sampler: SamplerHandle,
+}
+
+// rgba8 pixels of an image, kept on the cpu so models can be baked with their textures
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub name: String,
+    pub size: UVec2,
+    pub rgba: Vec<u8>,
+}
+
+// how a texture samples its image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureInfo {
+    pub image: usize,
+    // base color textures are srgb, the rest hold linear data and are uploaded as is
+    pub srgb: bool,
+    pub nearest: bool,
+}
+
+// textures bound in place of the ones a material doesn't have
+#[derive(Debug, Clone, Copy)]
+pub struct FallbackTextures {
+    pub white: Texture,
+    pub flat_normal: Texture,
+}
+
+// cpu side description of a material, the defaults are the ones the gltf spec uses when a
+// primitive has no material
+#[derive(Debug, Clone)]
+pub struct MaterialProperties {
+    #[allow(dead_code)]
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    // indices into the gltf textures
+    pub base_color_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    #[allow(dead_code)]
+    pub double_sided: bool,
+}
+
+impl Default for MaterialProperties {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+impl From<gltf::Material<'_>> for MaterialProperties {
+    fn from(material: gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal_texture = material.normal_texture();
+
+        Self {
+            name: material.name().unwrap_or("Unnamed").to_string(),
+            base_color_factor: pbr.base_color_factor(),
+            base_color_texture: pbr.base_color_texture().map(|info| info.texture().index()),
+            normal_scale: normal_texture.as_ref().map_or(1.0, |info| info.scale()),
+            normal_texture: normal_texture.map(|info| info.texture().index()),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            alpha_mode: material.alpha_mode().into(),
+            alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            double_sided: material.double_sided(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Material {
+    pub properties: MaterialProperties,
+
+    #[allow(dead_code)]
+    buffer: BufferHandle,
+    pub bind_group: BindGroupHandle,
+}
+
+impl Material {
+    pub fn new(
+        renderer: &mut Renderer,
+        properties: MaterialProperties,
+        textures: &[Texture],
+        fallback: FallbackTextures,
+    ) -> Self {
+        let data = MaterialData {
+            base_color_factor: properties.base_color_factor,
+            metallic_factor: properties.metallic_factor,
+            roughness_factor: properties.roughness_factor,
+            normal_scale: properties.normal_scale,
+            alpha_cutoff: properties.alpha_cutoff,
+            alpha_mode: properties.alpha_mode as u32,
+            padding: [0; 3],
+        };
+
+        let base_color = properties
+            .base_color_texture
+            .map_or(fallback.white, |i| textures[i]);
+        let normal = properties
+            .normal_texture
+            .map_or(fallback.flat_normal, |i| textures[i]);
+
+        let buffer = renderer.create_uniform_buffer(bytemuck::cast_slice(&[data]));
+        let bind_group = renderer.create_bind_group(BindGroupLayout {
+            entries: vec![
+                BindGroupLayoutEntry::Data(buffer),
+                BindGroupLayoutEntry::Texture {
+                    handle: base_color.texture,
+                    sample_type: TextureSampleType::Filterable,
+                },
+                BindGroupLayoutEntry::Sampler {
+                    handle: base_color.sampler,
+                    sampler_type: SamplerType::Filtering,
+                },
+                BindGroupLayoutEntry::Texture {
+                    handle: normal.texture,
+                    sample_type: TextureSampleType::Filterable,
+                },
+                BindGroupLayoutEntry::Sampler {
+                    handle: normal.sampler,
+                    sampler_type: SamplerType::Filtering,
+                },
+            ],
+        });
+
+        Self {
+            properties,
+            buffer,
+            bind_group,
+        }
+    }
+
+    pub fn get_layout_descriptor() -> BindGroupLayoutDescriptor {
+        BindGroupLayoutDescriptor {
+            entries: vec![
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: true },
+                BindGroupLayoutDescriptorEntry::Texture {
+                    sample_type: TextureSampleType::Filterable,
+                },
+                BindGroupLayoutDescriptorEntry::Sampler {
+                    sampler_type: SamplerType::Filtering,
+                },
+                BindGroupLayoutDescriptorEntry::Texture {
+                    sample_type: TextureSampleType::Filterable,
+                },
+                BindGroupLayoutDescriptorEntry::Sampler {
+                    sampler_type: SamplerType::Filtering,
+                },
+            ],
+        }
+    }
+}
+
+impl FallbackTextures {
+    pub fn new(renderer: &mut Renderer) -> Self {
+        let sampler = renderer.create_sampler(SamplerDescriptor::default());
+        Self {
+            white: Texture {
+                texture: create_texture(renderer, "white", UVec2::ONE, &[255, 255, 255, 255], true),
+                sampler,
+            },
+            flat_normal: Texture {
+                texture: create_texture(
+                    renderer,
+                    "flat_normal",
+                    UVec2::ONE,
+                    &[128, 128, 255, 255],
+                    false,
+                ),
+                sampler,
+            },
+        }
+    }
+}
+
+// every image of the gltf decoded to rgba, doesn't need a renderer
+pub fn read_images(
+    gltf: &gltf::Gltf,
+    buffers: &[Vec<u8>],
+    base: &Path,
+) -> Result<Vec<Image>, GltfLoadError> {
+    let mut images = Vec::new();
+    for image in gltf.images() {
+        let bytes = match image.source() {
+            gltf::image::Source::View { view, .. } => buffers[view.buffer().index()]
+                .get(view.offset()..view.offset() + view.length())
+                .ok_or(GltfLoadError::ImageViewOutOfRange {
+                    image: image.index(),
+                    buffer: view.buffer().index(),
+                })?
+                .to_vec(),
+            gltf::image::Source::Uri { uri, .. } => load_uri(uri, base)?,
+        };
+
+        let decoded = image::load_from_memory(&bytes)
+            .map_err(|error| GltfLoadError::Image {
+                image: image.index(),
+                error,
+            })?
+            .to_rgba8();
+        images.push(Image {
+            name: image.name().unwrap_or("gltf_image").to_string(),
+            size: UVec2::new(decoded.width(), decoded.height()),
+            rgba: decoded.into_raw(),
+        });
+    }
+
+    Ok(images)
+}
+
+impl TextureInfo {
+    // one per gltf texture
+    pub fn from_gltf(gltf: &gltf::Document) -> Vec<Self> {
+        let base_color_textures = gltf
+            .materials()
+            .filter_map(|material| material.pbr_metallic_roughness().base_color_texture())
+            .map(|info| info.texture().index())
+            .collect::<HashSet<_>>();
+
+        gltf.textures()
+            .map(|texture| TextureInfo {
+                image: texture.source().index(),
+                srgb: base_color_textures.contains(&texture.index()),
+                nearest: texture.sampler().mag_filter() == Some(gltf::texture::MagFilter::Nearest),
+            })
+            .collect()
+    }
+}
+
+// upload `images` and create the samplers of `textures`, indexed like them
+pub fn load_textures(
+    renderer: &mut Renderer,
+    textures: &[TextureInfo],
+    images: &[Image],
+) -> Vec<Texture> {
+    // an image can be used as both, it's uploaded once per format
+    let mut uploaded = HashMap::new();
+    textures
+        .iter()
+        .map(|texture| {
+            let handle = *uploaded
+                .entry((texture.image, texture.srgb))
+                .or_insert_with(|| {
+                    let image = &images[texture.image];
+                    create_texture(renderer, &image.name, image.size, &image.rgba, texture.srgb)
+                });
+
+            let filter = if texture.nearest {
+                SamplerFilterMode::Nearest
+            } else {
+                SamplerFilterMode::Linear
+            };
+            Texture {
+                texture: handle,
+                sampler: renderer.create_sampler(SamplerDescriptor {
+                    address_mode: SamplerAddressMode::Repeat,
+                    filter,
+                    ..Default::default()
+                }),
+            }
+        })
+        .collect()
+}
+
+fn create_texture(
+    renderer: &mut Renderer,
+    name: &str,
+    size: UVec2,
+    rgba: &[u8],
+    srgb: bool,
+) -> TextureHandle {
+    let format = if srgb {
+        TextureFormat::Rgba8UnormSrgb
+    } else {
+        TextureFormat::Rgba8U
+    };
+    renderer.create_texture(TextureDescriptor {
+        name,
+        size,
+        data: Some(rgba),
+        format,
+        usage: texture_usage::GENERIC,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_views_past_the_end_of_the_buffer_are_rejected() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "bufferView": 0, "mimeType": "image/png" }],
+            "bufferViews": [{ "buffer": 0, "byteOffset": 4, "byteLength": 16 }],
+            "buffers": [{ "byteLength": 20 }]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let buffer = vec![0; 8];
+
+        assert!(matches!(
+            read_images(&gltf, &[buffer], Path::new("res")),
+            Err(GltfLoadError::ImageViewOutOfRange {
+                image: 0,
+                buffer: 0
+            })
+        ));
+    }
+}
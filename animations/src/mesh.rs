@@ -0,0 +1,559 @@
+use jandering_engine::{
+    bind_group::{
+        BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutDescriptorEntry,
+        BindGroupLayoutEntry,
+    },
+    object::Renderable,
+    renderer::{BindGroupHandle, BufferHandle, Janderer, Renderer},
+    shader::{BufferLayout, BufferLayoutEntry, BufferLayoutEntryDataType, BufferLayoutStepMode},
+    types::{Mat4, Vec2, Vec3, Vec4},
+};
+
+use crate::error::GltfLoadError;
+
+#[derive(Debug)]
+pub struct Mesh {
+    #[allow(dead_code)]
+    pub name: String,
+    pub primitives: Vec<Primitive>,
+    pub instance_buffer: BufferHandle,
+    // morph target weights of the node using this mesh, shared by all of its primitives
+    pub morph_weight_buffer: BufferHandle,
+    pub morph_target_count: usize,
+}
+
+#[derive(Debug)]
+pub struct PrimitiveRenderData {
+    pub vertex_buffer: BufferHandle,
+    pub index_buffer: BufferHandle,
+    pub instance_buffer: BufferHandle,
+    #[allow(dead_code)]
+    pub morph_info_buffer: BufferHandle,
+    #[allow(dead_code)]
+    pub morph_delta_buffer: BufferHandle,
+    pub morph_bind_group: BindGroupHandle,
+}
+
+// offsets added to the base vertices, scaled by the target's weight
+#[derive(Debug, Clone)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+struct MorphInfo {
+    target_count: u32,
+    vertex_count: u32,
+    padding: [u32; 2],
+}
+
+#[derive(Debug)]
+pub struct Primitive {
+    #[allow(dead_code)]
+    pub vertices: Vec<AnimatedVertex>,
+    pub indices: Vec<u32>,
+    pub morph_targets: Vec<MorphTarget>,
+    // index into `AnimatedObject::materials`
+    pub material: usize,
+    pub render_data: PrimitiveRenderData,
+}
+
+impl Mesh {
+    // `joint_count` is the smallest skin this mesh is used with, `None` if it isn't skinned
+    pub fn from_gltf(
+        renderer: &mut Renderer,
+        mesh: gltf::Mesh,
+        buffers: &[Vec<u8>],
+        joint_count: Option<usize>,
+        default_material: usize,
+    ) -> Result<Self, GltfLoadError> {
+        let name = mesh.name().unwrap_or("Unnamed").to_string();
+
+        // the spec requires every primitive to have the same number of targets
+        let morph_target_count = mesh
+            .primitives()
+            .map(|primitive| primitive.morph_targets().len())
+            .max()
+            .unwrap_or(0);
+        let mut result = Self::new(
+            renderer,
+            name.clone(),
+            morph_target_count,
+            mesh.weights().unwrap_or_default(),
+        );
+
+        for primitive in mesh.primitives() {
+            let (vertices, indices) = read_primitive(&primitive, buffers, joint_count, &name)?;
+            let morph_targets = read_morph_targets(&primitive, buffers, vertices.len());
+            result.add_primitive(
+                renderer,
+                vertices,
+                indices,
+                morph_targets,
+                primitive.material().index().unwrap_or(default_material),
+            );
+        }
+
+        Ok(result)
+    }
+
+    // a mesh without primitives, add them with `add_primitive`
+    pub fn new(
+        renderer: &mut Renderer,
+        name: String,
+        morph_target_count: usize,
+        weights: &[f32],
+    ) -> Self {
+        let instance_buffer =
+            renderer.create_vertex_buffer(bytemuck::cast_slice(&[Mat4::IDENTITY]));
+
+        let mut weights = weights.to_vec();
+        weights.resize(morph_target_count.max(1), 0.0);
+        let morph_weight_buffer = renderer.create_storage_buffer(bytemuck::cast_slice(&weights));
+
+        Self {
+            name,
+            primitives: Vec::new(),
+            instance_buffer,
+            morph_weight_buffer,
+            morph_target_count,
+        }
+    }
+
+    // upload a primitive drawn with `material`, its morph targets have an offset for every vertex
+    pub fn add_primitive(
+        &mut self,
+        renderer: &mut Renderer,
+        vertices: Vec<AnimatedVertex>,
+        indices: Vec<u32>,
+        morph_targets: Vec<MorphTarget>,
+        material: usize,
+    ) {
+        let mut deltas = morph_deltas(&morph_targets);
+        if deltas.is_empty() {
+            // storage buffers can't be empty
+            deltas.push(Vec3::ZERO.extend(0.0));
+        }
+        let morph_info = MorphInfo {
+            target_count: morph_targets.len() as u32,
+            vertex_count: vertices.len() as u32,
+            padding: [0; 2],
+        };
+        let morph_info_buffer = renderer.create_uniform_buffer(bytemuck::cast_slice(&[morph_info]));
+        let morph_delta_buffer = renderer.create_storage_buffer(bytemuck::cast_slice(&deltas));
+        let morph_bind_group = renderer.create_bind_group(BindGroupLayout {
+            entries: vec![
+                BindGroupLayoutEntry::Data(morph_info_buffer),
+                BindGroupLayoutEntry::Data(morph_delta_buffer),
+                BindGroupLayoutEntry::Data(self.morph_weight_buffer),
+            ],
+        });
+
+        let render_data = PrimitiveRenderData {
+            vertex_buffer: renderer.create_vertex_buffer(bytemuck::cast_slice(&vertices)),
+            index_buffer: renderer.create_index_buffer(bytemuck::cast_slice(&indices)),
+            instance_buffer: self.instance_buffer,
+            morph_info_buffer,
+            morph_delta_buffer,
+            morph_bind_group,
+        };
+        self.primitives.push(Primitive {
+            vertices,
+            indices,
+            morph_targets,
+            material,
+            render_data,
+        });
+    }
+
+    // upload the morph weights of the node being drawn, missing weights are treated as 0
+    pub fn write_morph_weights(&self, renderer: &mut Renderer, weights: &[f32]) {
+        if self.morph_target_count == 0 {
+            return;
+        }
+
+        let mut weights = weights.to_vec();
+        weights.resize(self.morph_target_count, 0.0);
+        renderer.write_buffer(self.morph_weight_buffer, bytemuck::cast_slice(&weights));
+    }
+
+    pub fn morph_bind_group_layout_descriptor() -> BindGroupLayoutDescriptor {
+        BindGroupLayoutDescriptor {
+            entries: vec![
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: true },
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: false },
+                BindGroupLayoutDescriptorEntry::Data { is_uniform: false },
+            ],
+        }
+    }
+}
+
+// cpu reference of the morphing the vertex shader does before skinning
+#[cfg(test)]
+pub fn morph_vertices(
+    vertices: &[AnimatedVertex],
+    targets: &[MorphTarget],
+    weights: &[f32],
+) -> Vec<AnimatedVertex> {
+    let mut vertices = vertices.to_vec();
+    for (target, weight) in targets.iter().zip(weights) {
+        if *weight == 0.0 {
+            continue;
+        }
+
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            vertex.position += target.positions[i] * *weight;
+            vertex.normal += target.normals[i] * *weight;
+        }
+    }
+
+    for vertex in vertices.iter_mut() {
+        vertex.normal = vertex.normal.normalize_or_zero();
+    }
+    vertices
+}
+
+// the layout of the morph delta storage buffer, per target all position deltas followed by all
+// normal deltas, padded to vec4s
+fn morph_deltas(targets: &[MorphTarget]) -> Vec<Vec4> {
+    targets
+        .iter()
+        .flat_map(|target| target.positions.iter().chain(target.normals.iter()))
+        .map(|delta| delta.extend(0.0))
+        .collect()
+}
+
+pub fn read_morph_targets(
+    primitive: &gltf::Primitive,
+    buffers: &[Vec<u8>],
+    vertex_count: usize,
+) -> Vec<MorphTarget> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    reader
+        .read_morph_targets()
+        .map(|(positions, normals, _tangents)| {
+            let mut target = MorphTarget {
+                positions: vec![Vec3::ZERO; vertex_count],
+                normals: vec![Vec3::ZERO; vertex_count],
+            };
+            for (delta, position) in target
+                .positions
+                .iter_mut()
+                .zip(positions.into_iter().flatten())
+            {
+                *delta = Vec3::from_array(position);
+            }
+            for (delta, normal) in target.normals.iter_mut().zip(normals.into_iter().flatten()) {
+                *delta = Vec3::from_array(normal);
+            }
+            target
+        })
+        .collect()
+}
+
+pub fn read_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[Vec<u8>],
+    joint_count: Option<usize>,
+    mesh_name: &str,
+) -> Result<(Vec<AnimatedVertex>, Vec<u32>), GltfLoadError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let mut vertices = Vec::new();
+    if let Some(positions) = reader.read_positions() {
+        for position in positions {
+            vertices.push(AnimatedVertex {
+                position: Vec3::from_array(position),
+                ..Default::default()
+            })
+        }
+    }
+
+    if let Some(normals) = reader.read_normals() {
+        for (vertex, normal) in vertices.iter_mut().zip(normals) {
+            vertex.normal = Vec3::from_array(normal);
+        }
+    }
+
+    if let Some(tangents) = reader.read_tangents() {
+        for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+            vertex.tangent = tangent;
+        }
+    }
+
+    if let Some(uvs) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
+        for (vertex, uv) in vertices.iter_mut().zip(uvs) {
+            vertex.uv = uv.into();
+        }
+    }
+
+    // gather influences from every JOINTS_n/WEIGHTS_n set, the 4 strongest ones are kept
+    let mut influences = vec![Vec::new(); vertices.len()];
+    let mut set = 0;
+    while let (Some(joints), Some(weights)) = (reader.read_joints(set), reader.read_weights(set)) {
+        for (influences, (joints, weights)) in influences
+            .iter_mut()
+            .zip(joints.into_u16().zip(weights.into_f32()))
+        {
+            influences.extend(joints.into_iter().zip(weights));
+        }
+        set += 1;
+    }
+
+    // unskinned meshes keep zero weights and use their node's transform instead
+    if let Some(joint_count) = joint_count {
+        for (vertex, influences) in vertices.iter_mut().zip(influences) {
+            if let Some(&(joint, _)) = influences
+                .iter()
+                .find(|(joint, weight)| *weight > 0.0 && *joint as usize >= joint_count)
+            {
+                return Err(GltfLoadError::JointOutOfRange {
+                    mesh: mesh_name.to_string(),
+                    joint,
+                    joint_count,
+                });
+            }
+
+            vertex.set_influences(influences);
+        }
+    }
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..vertices.len() as u32).collect(),
+    };
+
+    Ok((vertices, indices))
+}
+
+impl Renderable for Primitive {
+    fn num_instances(&self) -> u32 {
+        1
+    }
+
+    fn num_indices(&self) -> u32 {
+        self.indices.len() as u32
+    }
+
+    fn get_buffers(&self) -> (BufferHandle, BufferHandle, Option<BufferHandle>) {
+        (
+            self.render_data.vertex_buffer,
+            self.render_data.index_buffer,
+            Some(self.render_data.instance_buffer),
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug, Default)]
+pub struct AnimatedVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+    pub weights: [f32; 4],
+    pub joints: [u16; 4],
+    // w is the handedness, all zeros when the mesh has no tangents and the shader derives them
+    pub tangent: [f32; 4],
+}
+
+impl AnimatedVertex {
+    // keeps the 4 strongest influences and renormalizes their weights so they sum up to 1
+    pub fn set_influences(&mut self, mut influences: Vec<(u16, f32)>) {
+        influences.sort_by(|a, b| b.1.total_cmp(&a.1));
+        influences.truncate(4);
+
+        let total_weight = influences.iter().map(|(_, weight)| weight).sum::<f32>();
+        self.joints = [0; 4];
+        self.weights = [0.0; 4];
+        for (i, (joint, weight)) in influences.into_iter().enumerate() {
+            if total_weight > 0.0 {
+                self.joints[i] = joint;
+                self.weights[i] = weight / total_weight;
+            }
+        }
+    }
+
+    pub fn desc() -> BufferLayout {
+        BufferLayout {
+            step_mode: BufferLayoutStepMode::Vertex,
+            stride: std::mem::size_of::<AnimatedVertex>(),
+            entries: &[
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, position) as u64,
+                    location: 0,
+                    data_type: BufferLayoutEntryDataType::Float32x3,
+                },
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, normal) as u64,
+                    location: 1,
+                    data_type: BufferLayoutEntryDataType::Float32x3,
+                },
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, uv) as u64,
+                    location: 2,
+                    data_type: BufferLayoutEntryDataType::Float32x2,
+                },
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, weights) as u64,
+                    location: 3,
+                    data_type: BufferLayoutEntryDataType::Float32x4,
+                },
+                // the 4 u16 joint indices are read as two packed u32s, locations 5-8 are taken by the instance
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, joints) as u64,
+                    location: 4,
+                    data_type: BufferLayoutEntryDataType::U32,
+                },
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, joints) as u64 + 4,
+                    location: 9,
+                    data_type: BufferLayoutEntryDataType::U32,
+                },
+                BufferLayoutEntry {
+                    offset: std::mem::offset_of!(AnimatedVertex, tangent) as u64,
+                    location: 10,
+                    data_type: BufferLayoutEntryDataType::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: Vec3, normal: Vec3) -> AnimatedVertex {
+        AnimatedVertex {
+            position,
+            normal,
+            ..Default::default()
+        }
+    }
+
+    // two vertices, one target moving them up and one tilting their normals towards x
+    fn morphable() -> (Vec<AnimatedVertex>, Vec<MorphTarget>) {
+        let vertices = vec![
+            vertex(Vec3::ZERO, Vec3::Z),
+            vertex(Vec3::new(1.0, 0.0, 0.0), Vec3::Z),
+        ];
+        let targets = vec![
+            MorphTarget {
+                positions: vec![Vec3::Y, Vec3::new(0.0, 2.0, 0.0)],
+                normals: vec![Vec3::ZERO; 2],
+            },
+            MorphTarget {
+                positions: vec![Vec3::ZERO; 2],
+                normals: vec![Vec3::X, Vec3::new(2.0, 0.0, -1.0)],
+            },
+        ];
+        (vertices, targets)
+    }
+
+    // the vertex shader's morph loop over the uploaded deltas, normals are normalized after skinning there
+    fn shader_morph(
+        vertices: &[AnimatedVertex],
+        deltas: &[Vec4],
+        weights: &[f32],
+        index: usize,
+    ) -> (Vec3, Vec3) {
+        let vertex_count = vertices.len();
+        let mut position = vertices[index].position;
+        let mut normal = vertices[index].normal;
+        for (t, weight) in weights.iter().enumerate() {
+            if *weight == 0.0 {
+                continue;
+            }
+            let offset = t * vertex_count * 2;
+            position += *weight * deltas[offset + index].truncate();
+            normal += *weight * deltas[offset + vertex_count + index].truncate();
+        }
+        (position, normal.normalize_or_zero())
+    }
+
+    #[test]
+    fn zero_weights_keep_the_base_mesh() {
+        let (vertices, targets) = morphable();
+        let morphed = morph_vertices(&vertices, &targets, &[0.0, 0.0]);
+        for (morphed, vertex) in morphed.iter().zip(&vertices) {
+            assert_eq!(morphed.position, vertex.position);
+            assert_eq!(morphed.normal, vertex.normal);
+        }
+    }
+
+    #[test]
+    fn weights_scale_and_sum_the_deltas() {
+        let (vertices, targets) = morphable();
+
+        let morphed = morph_vertices(&vertices, &targets, &[0.5, 0.0]);
+        assert!(morphed[0]
+            .position
+            .abs_diff_eq(Vec3::new(0.0, 0.5, 0.0), 1e-6));
+        assert!(morphed[1]
+            .position
+            .abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-6));
+
+        // z + x, normalized
+        let morphed = morph_vertices(&vertices, &targets, &[1.0, 1.0]);
+        assert!(morphed[0].position.abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(morphed[0]
+            .normal
+            .abs_diff_eq(Vec3::new(1.0, 0.0, 1.0).normalize(), 1e-6));
+        // the delta cancels z out completely
+        assert!(morphed[1].normal.abs_diff_eq(Vec3::X, 1e-6));
+
+        // missing weights count as 0
+        let morphed = morph_vertices(&vertices, &targets, &[1.0]);
+        assert!(morphed[1]
+            .position
+            .abs_diff_eq(Vec3::new(1.0, 2.0, 0.0), 1e-6));
+        assert_eq!(morphed[1].normal, Vec3::Z);
+    }
+
+    #[test]
+    fn matches_the_shader() {
+        let (vertices, targets) = morphable();
+        let deltas = morph_deltas(&targets);
+        for weights in [[0.25, 0.75], [1.0, -0.5], [2.0, 0.3], [0.0, 1.0]] {
+            let morphed = morph_vertices(&vertices, &targets, &weights);
+            for (index, morphed) in morphed.iter().enumerate() {
+                let (position, normal) = shader_morph(&vertices, &deltas, &weights, index);
+                assert!(morphed.position.abs_diff_eq(position, 1e-6));
+                assert!(morphed.normal.abs_diff_eq(normal, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn joints_outside_the_skin_are_rejected() {
+        // one vertex fully weighted to joint 3 of a skin with 2 joints
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "meshes": [{ "name": "body", "primitives": [{ "attributes": { "POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2 } }] }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC3", "min": [0, 0, 0], "max": [0, 0, 0] },
+                { "bufferView": 1, "componentType": 5123, "count": 1, "type": "VEC4" },
+                { "bufferView": 2, "componentType": 5126, "count": 1, "type": "VEC4" }
+            ],
+            "bufferViews": [
+                { "buffer": 0, "byteLength": 12 },
+                { "buffer": 0, "byteOffset": 12, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 20, "byteLength": 16 }
+            ],
+            "buffers": [{ "byteLength": 36 }]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let mut buffer = bytemuck::cast_slice(&[0.0f32; 3]).to_vec();
+        buffer.extend_from_slice(bytemuck::cast_slice(&[3u16, 0, 0, 0]));
+        buffer.extend_from_slice(bytemuck::cast_slice(&[1.0f32, 0.0, 0.0, 0.0]));
+
+        let mesh = gltf.meshes().next().unwrap();
+        let primitive = mesh.primitives().next().unwrap();
+        assert!(matches!(
+            read_primitive(&primitive, &[buffer], Some(2), "body"),
+            Err(GltfLoadError::JointOutOfRange { mesh, joint: 3, joint_count: 2 }) if mesh == "body"
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapMode {
+    #[default]
+    Loop,
+    // holds the first or last frame when running past either end, but keeps playing
+    Clamp,
+    // plays forwards, then backwards, then forwards again...
+    PingPong,
+    // like clamp, but pauses and counts as finished when reaching the end
+    Once,
+}
+
+// playhead of one group of clips, advanced by `advance` so playback is deterministic and can be scrubbed
+#[derive(Debug, Clone, Copy)]
+pub struct Playback {
+    // seconds into the clip, `advance` wraps it into [0, length) when looping and [0, 2 * length) when
+    // ping-ponging so it doesn't lose precision over long sessions
+    pub time: f32,
+    // negative plays in reverse
+    pub speed: f32,
+    pub paused: bool,
+    pub wrap_mode: WrapMode,
+    // `time` before the last `advance`, shifted along when `time` wraps so it can lie outside of the
+    // clip. Root motion and events are extracted between the two
+    pub previous_time: f32,
+    // how often `advance` wrapped `time`, negative when a looping clip plays in reverse
+    pub loops: i32,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+            wrap_mode: WrapMode::Loop,
+            previous_time: 0.0,
+            loops: 0,
+        }
+    }
+}
+
+impl Playback {
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_wrap_mode(mut self, wrap_mode: WrapMode) -> Self {
+        self.wrap_mode = wrap_mode;
+        self
+    }
+
+    // starts at the end when playing in reverse
+    pub fn restart(&mut self, length: f32) {
+        let time = if self.speed < 0.0 && self.wrap_mode != WrapMode::Loop {
+            length
+        } else {
+            0.0
+        };
+        self.seek(time);
+        self.paused = false;
+    }
+
+    // jump to `time` without producing root motion
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+        self.previous_time = time;
+    }
+
+    pub fn advance(&mut self, dt: f32, length: f32) {
+        self.previous_time = self.time;
+        if self.paused {
+            return;
+        }
+
+        self.time += dt * self.speed;
+        match self.wrap_mode {
+            WrapMode::Loop => self.wrap(length),
+            WrapMode::PingPong => self.wrap(length * 2.0),
+            WrapMode::Clamp => self.time = self.time.clamp(0.0, length.max(0.0)),
+            WrapMode::Once => {
+                self.time = self.time.clamp(0.0, length.max(0.0));
+                if self.is_finished(length) {
+                    self.paused = true;
+                }
+            }
+        }
+    }
+
+    fn wrap(&mut self, period: f32) {
+        // nothing to wrap into, a NaN period would also make the time NaN for good
+        if period.is_nan() || period <= 0.0 {
+            self.time = 0.0;
+            self.previous_time = 0.0;
+            return;
+        }
+        let wraps = (self.time / period).floor();
+        self.time -= wraps * period;
+        self.previous_time -= wraps * period;
+        // floating point can round a tiny negative time up to the period
+        if self.time >= period {
+            self.time = 0.0;
+        }
+        self.loops += wraps as i32;
+    }
+
+    // only clips played with `WrapMode::Once` ever finish
+    pub fn is_finished(&self, length: f32) -> bool {
+        self.wrap_mode == WrapMode::Once
+            && ((self.speed > 0.0 && self.time >= length) || (self.speed < 0.0 && self.time <= 0.0))
+    }
+
+    // where in a clip of `length` seconds the playhead is
+    pub fn clip_time(&self, length: f32) -> f32 {
+        self.clip_time_at(self.time, length)
+    }
+
+    // `time` wrapped into a clip of `length` seconds with this playback's wrap mode
+    pub fn clip_time_at(&self, time: f32, length: f32) -> f32 {
+        if length <= 0.0 {
+            return 0.0;
+        }
+
+        match self.wrap_mode {
+            WrapMode::Loop => time.rem_euclid(length),
+            WrapMode::Clamp | WrapMode::Once => time.clamp(0.0, length),
+            WrapMode::PingPong => {
+                let time = time.rem_euclid(length * 2.0);
+                if time > length {
+                    length * 2.0 - time
+                } else {
+                    time
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looping_wraps_time_into_the_clip() {
+        let mut playback = Playback::default();
+        for _ in 0..10 {
+            playback.advance(0.25, 1.0);
+        }
+        assert_eq!(playback.time, 0.5);
+        assert_eq!(playback.loops, 2);
+
+        playback.seek(0.9);
+        playback.advance(0.3, 1.0);
+        assert!((playback.time - 0.2).abs() < 1e-5);
+        // the step across the end is kept for root motion and events
+        assert!((playback.previous_time - -0.1).abs() < 1e-5);
+        assert_eq!(playback.loops, 3);
+    }
+
+    #[test]
+    fn reverse_loops_count_down() {
+        let mut playback = Playback::default().with_speed(-1.0);
+        playback.advance(0.25, 1.0);
+        assert!((playback.time - 0.75).abs() < 1e-5);
+        assert!((playback.previous_time - 1.0).abs() < 1e-5);
+        assert_eq!(playback.loops, -1);
+    }
+
+    #[test]
+    fn ping_pong_wraps_after_going_back_and_forth() {
+        let mut playback = Playback::default().with_wrap_mode(WrapMode::PingPong);
+        playback.advance(1.5, 1.0);
+        assert!((playback.time - 1.5).abs() < 1e-5);
+        assert!((playback.clip_time(1.0) - 0.5).abs() < 1e-5);
+
+        playback.advance(1.0, 1.0);
+        assert!((playback.time - 0.5).abs() < 1e-5);
+        assert_eq!(playback.loops, 1);
+    }
+
+    #[test]
+    fn long_sessions_stay_precise() {
+        let mut playback = Playback::default();
+        // a day at 60 fps
+        for _ in 0..(60 * 60 * 60 * 24) {
+            playback.advance(1.0 / 60.0, 1.5);
+        }
+        assert!(playback.time >= 0.0 && playback.time < 1.5);
+        assert!(playback.time - playback.previous_time > 0.0);
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use jandering_engine::types::{Mat4, Qua, Vec3};
+
+use crate::{
+    animation::{Animation, Interpolation, Keyframes, Track},
+    compression::QuantizedQua,
+    skeleton::{Skeleton, Transform},
+};
+
+// which node of the target skeleton plays the animation of a node of the source skeleton, by name
+#[derive(Debug, Clone, Default)]
+pub struct BoneMap {
+    pub bones: HashMap<String, String>,
+}
+
+impl BoneMap {
+    pub fn with_bone(mut self, source: &str, target: &str) -> Self {
+        self.bones.insert(source.to_string(), target.to_string());
+        self
+    }
+
+    // nodes with the same name once everything up to a ':' is left out, e.g. "mixamorig:Hips" and
+    // "mixamorig1:Hips"
+    pub fn matching_names(source: &Skeleton, target: &Skeleton) -> Self {
+        let strip = |name: &str| name.rsplit(':').next().unwrap_or(name).to_string();
+        let targets = target
+            .nodes
+            .iter()
+            .map(|node| (strip(&node.name), node.name.clone()))
+            .collect::<HashMap<_, _>>();
+
+        let bones = source
+            .nodes
+            .iter()
+            .filter_map(|node| {
+                let target = targets.get(&strip(&node.name))?;
+                Some((node.name.clone(), target.clone()))
+            })
+            .collect();
+        Self { bones }
+    }
+}
+
+// the rest poses of both skeletons around one mapped node
+struct RestFrames {
+    // world transforms of the parents
+    source_parent: Mat4,
+    target_parent: Mat4,
+    source_local: Transform,
+    target_local: Transform,
+    // how much longer the target bone is, for translations
+    length_ratio: f32,
+}
+
+// `animation` of `source` played by `target`. Both skeletons should have the same rest pose in world
+// space (e.g. both a T-pose), bones can point along different local axes and have different lengths.
+// Rotations are moved over as the change from the rest pose in world space, translations are scaled by
+// the ratio of the bone lengths. Tracks of unmapped nodes and morph weights are left out
+pub fn retarget(
+    animation: &Animation,
+    source: &Skeleton,
+    target: &Skeleton,
+    bone_map: &BoneMap,
+) -> Animation {
+    let source_rest = source.rest_pose();
+    let target_rest = target.rest_pose();
+
+    let find =
+        |skeleton: &Skeleton, name: &str| skeleton.nodes.iter().position(|node| node.name == name);
+    let parent_matrix = |skeleton: &Skeleton, world: &[Mat4], node: usize| {
+        skeleton.nodes[node]
+            .parent
+            .map_or(Mat4::IDENTITY, |parent| world[parent])
+    };
+    // bones without a length (e.g. the root at the origin) use the size of the whole skeleton
+    let skeleton_ratio = size(&target_rest.world_transforms) / size(&source_rest.world_transforms);
+    let skeleton_ratio = if skeleton_ratio.is_finite() && skeleton_ratio > 0.0 {
+        skeleton_ratio
+    } else {
+        1.0
+    };
+
+    let tracks = animation
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            let source_node = track.target;
+            let target_node = find(target, bone_map.bones.get(&source.nodes[source_node].name)?)?;
+
+            let source_parent = parent_matrix(source, &source_rest.world_transforms, source_node);
+            let target_parent = parent_matrix(target, &target_rest.world_transforms, target_node);
+            let source_length = (source_parent
+                .transform_vector3(source.nodes[source_node].transform.translation))
+            .length();
+            let target_length = (target_parent
+                .transform_vector3(target.nodes[target_node].transform.translation))
+            .length();
+            let length_ratio = if source_length > 1e-6 && target_length > 1e-6 {
+                target_length / source_length
+            } else {
+                skeleton_ratio
+            };
+
+            let frames = RestFrames {
+                source_parent,
+                target_parent,
+                source_local: source.nodes[source_node].transform,
+                target_local: target.nodes[target_node].transform,
+                length_ratio,
+            };
+
+            Some(Track {
+                keyframes: retarget_keyframes(track, &frames)?,
+                interpolation: track.interpolation,
+                target: target_node,
+                timestamps: track.timestamps.clone(),
+            })
+        })
+        .collect();
+
+    Animation {
+        name: animation.name.clone(),
+        length: animation.length,
+        tracks,
+        events: animation.events.clone(),
+    }
+}
+
+// every value of the track goes through `value * linear + offset` with constant factors, which also
+// works for cubic spline tangents as long as they skip the offset
+fn retarget_keyframes(track: &Track, frames: &RestFrames) -> Option<Keyframes> {
+    let is_tangent = |i: usize| track.interpolation == Interpolation::CubicSpline && i % 3 != 1;
+
+    let (_, source_parent_rotation, _) = frames.source_parent.to_scale_rotation_translation();
+    let (_, target_parent_rotation, _) = frames.target_parent.to_scale_rotation_translation();
+
+    // target local = target parent⁻¹ · (source parent · source local · source rest⁻¹ · source parent⁻¹)
+    //                · target parent · target rest
+    let rotation_before = target_parent_rotation.inverse() * source_parent_rotation;
+    let rotation_after = frames.source_local.rotation.inverse()
+        * source_parent_rotation.inverse()
+        * target_parent_rotation
+        * frames.target_local.rotation;
+    let rotate = |rotation: Qua| rotation_before * rotation * rotation_after;
+
+    // the offset from the rest position, moved into the target parent's space and scaled
+    let translate = |i: usize, translation: Vec3| {
+        let offset = if is_tangent(i) {
+            translation
+        } else {
+            translation - frames.source_local.translation
+        };
+        let offset = frames.target_parent.inverse().transform_vector3(
+            frames.source_parent.transform_vector3(offset) * frames.length_ratio,
+        );
+        if is_tangent(i) {
+            offset
+        } else {
+            frames.target_local.translation + offset
+        }
+    };
+    // bones the source hides with a scale of 0 play the target's scales as they are
+    let scale_ratio = Vec3::select(
+        frames.source_local.scale.cmpeq(Vec3::ZERO),
+        Vec3::ONE,
+        frames.target_local.scale / frames.source_local.scale,
+    );
+
+    Some(match &track.keyframes {
+        Keyframes::Rotations(rotations) => {
+            Keyframes::Rotations(rotations.iter().map(|rotation| rotate(*rotation)).collect())
+        }
+        Keyframes::QuantizedRotations(rotations) => Keyframes::QuantizedRotations(
+            rotations
+                .iter()
+                .map(|rotation| QuantizedQua::new(rotate(rotation.decode())))
+                .collect(),
+        ),
+        Keyframes::Translations(translations) => Keyframes::Translations(
+            translations
+                .iter()
+                .enumerate()
+                .map(|(i, translation)| translate(i, *translation))
+                .collect(),
+        ),
+        Keyframes::Scales(scales) => {
+            Keyframes::Scales(scales.iter().map(|scale| *scale * scale_ratio).collect())
+        }
+        Keyframes::MorphTargetWeights(_) => return None,
+    })
+}
+
+// longest side of the box around all nodes
+fn size(world_transforms: &[Mat4]) -> f32 {
+    let (min, max) = world_transforms.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), transform| {
+            let position = transform.w_axis.truncate();
+            (min.min(position), max.max(position))
+        },
+    );
+    (max - min).max_element()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::skeleton::{Node, NodeType};
+
+    fn node(
+        name: &str,
+        translation: Vec3,
+        rotation: Qua,
+        children: Vec<usize>,
+        parent: Option<usize>,
+    ) -> Node {
+        Node {
+            name: name.to_string(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation,
+                rotation,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        }
+    }
+
+    // hips 1 above the ground, a spine 1 above them and an arm 1 along +x
+    fn source() -> Skeleton {
+        Skeleton {
+            nodes: vec![
+                node("src:Hips", Vec3::Y, Qua::IDENTITY, vec![1], None),
+                node("src:Spine", Vec3::Y, Qua::IDENTITY, vec![2], Some(0)),
+                node("src:Arm", Vec3::X, Qua::IDENTITY, Vec::new(), Some(1)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    // the same pose twice as big, with the hips' axes turned around y so the arm lies along local +z
+    fn target() -> Skeleton {
+        Skeleton {
+            nodes: vec![
+                node(
+                    "dst:Hips",
+                    Vec3::Y * 2.0,
+                    Qua::from_rotation_y(FRAC_PI_2),
+                    vec![1],
+                    None,
+                ),
+                node("dst:Spine", Vec3::Y * 2.0, Qua::IDENTITY, vec![2], Some(0)),
+                node("dst:Arm", Vec3::Z * 2.0, Qua::IDENTITY, Vec::new(), Some(1)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    fn track(target: usize, keyframes: Keyframes) -> Track {
+        Track {
+            keyframes,
+            interpolation: Interpolation::Linear,
+            target,
+            timestamps: vec![0.0, 1.0],
+        }
+    }
+
+    fn position(skeleton: &Skeleton, animation: &Animation, node: usize, time: f32) -> Vec3 {
+        let mut pose = skeleton.rest_pose();
+        pose.apply(animation, time);
+        skeleton.update_world_matrices(&mut pose);
+        pose.world_transforms[node].w_axis.truncate()
+    }
+
+    #[test]
+    fn motion_is_moved_over_in_world_space_and_scaled_to_the_bones() {
+        let (source, target) = (source(), target());
+        // the hips step along +x and the spine bends the arm up
+        let animation = Animation {
+            name: "wave".to_string(),
+            length: 1.0,
+            tracks: vec![
+                track(
+                    0,
+                    Keyframes::Translations(vec![Vec3::Y, Vec3::new(1.0, 1.0, 0.0)]),
+                ),
+                track(
+                    1,
+                    Keyframes::Rotations(vec![Qua::IDENTITY, Qua::from_rotation_z(FRAC_PI_2)]),
+                ),
+            ],
+            events: Vec::new(),
+        };
+        assert!(position(&source, &animation, 2, 1.0).abs_diff_eq(Vec3::new(1.0, 3.0, 0.0), 1e-5));
+
+        let retargeted = retarget(
+            &animation,
+            &source,
+            &target,
+            &BoneMap::matching_names(&source, &target),
+        );
+        assert_eq!(retargeted.tracks.len(), 2);
+
+        // the rest pose stays the target's
+        for (node, expected) in [
+            (0, Vec3::Y * 2.0),
+            (1, Vec3::Y * 4.0),
+            (2, Vec3::new(2.0, 4.0, 0.0)),
+        ] {
+            assert!(position(&target, &retargeted, node, 0.0).abs_diff_eq(expected, 1e-5));
+        }
+        // twice the step, and the arm points up like the source's
+        for (node, expected) in [
+            (0, Vec3::new(2.0, 2.0, 0.0)),
+            (1, Vec3::new(2.0, 4.0, 0.0)),
+            (2, Vec3::new(2.0, 6.0, 0.0)),
+        ] {
+            let actual = position(&target, &retargeted, node, 1.0);
+            assert!(
+                actual.abs_diff_eq(expected, 1e-5),
+                "node {node} at {actual}"
+            );
+        }
+    }
+
+    #[test]
+    fn bones_hidden_in_the_source_keep_finite_scales() {
+        let mut source = source();
+        source.nodes[2].transform.scale = Vec3::new(0.0, 1.0, 1.0);
+        let target = target();
+        let animation = Animation {
+            name: "grow".to_string(),
+            length: 1.0,
+            tracks: vec![track(
+                2,
+                Keyframes::Scales(vec![Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.5, 2.0, 1.0)]),
+            )],
+            events: Vec::new(),
+        };
+
+        let retargeted = retarget(
+            &animation,
+            &source,
+            &target,
+            &BoneMap::default().with_bone("src:Arm", "dst:Arm"),
+        );
+        let Keyframes::Scales(scales) = &retargeted.tracks[0].keyframes else {
+            panic!("expected scales");
+        };
+        assert_eq!(
+            scales,
+            &vec![Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.5, 2.0, 1.0)]
+        );
+    }
+}
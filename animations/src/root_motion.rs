@@ -0,0 +1,358 @@
+use std::{
+    f32::consts::{PI, TAU},
+    ops::{Add, Mul},
+};
+
+use jandering_engine::types::{Mat4, Qua, Vec3};
+
+use crate::{
+    animation::Animation,
+    skeleton::{Skeleton, Transform},
+};
+
+// horizontal movement and turning of the root node, expressed relative to the direction the root
+// was facing at the start of the delta so it can be applied to a character transform directly:
+// position += rotation_y(yaw) * translation, yaw += delta yaw
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RootMotion {
+    pub translation: Vec3,
+    pub yaw: f32,
+}
+
+impl RootMotion {
+    // this motion followed by `next`
+    pub fn then(self, next: RootMotion) -> RootMotion {
+        RootMotion {
+            translation: self.translation + Qua::from_rotation_y(self.yaw) * next.translation,
+            yaw: self.yaw + next.yaw,
+        }
+    }
+}
+
+// used for blending the motion of several clips by weight
+impl Add for RootMotion {
+    type Output = RootMotion;
+
+    fn add(self, rhs: RootMotion) -> RootMotion {
+        RootMotion {
+            translation: self.translation + rhs.translation,
+            yaw: self.yaw + rhs.yaw,
+        }
+    }
+}
+
+impl Mul<f32> for RootMotion {
+    type Output = RootMotion;
+
+    fn mul(self, rhs: f32) -> RootMotion {
+        RootMotion {
+            translation: self.translation * rhs,
+            yaw: self.yaw * rhs,
+        }
+    }
+}
+
+// the root node in model space, its parents are assumed to not be animated
+#[derive(Debug)]
+pub struct RootMotionExtractor {
+    pub node: usize,
+    parent_matrix: Mat4,
+    rest_position: Vec3,
+    rest_yaw: f32,
+}
+
+impl RootMotionExtractor {
+    pub fn new(skeleton: &Skeleton, node: usize) -> Self {
+        let rest_pose = skeleton.rest_pose();
+        let parent_matrix = skeleton.nodes[node]
+            .parent
+            .map_or(Mat4::IDENTITY, |parent| rest_pose.world_transforms[parent]);
+        let (_, rotation, rest_position) =
+            rest_pose.world_transforms[node].to_scale_rotation_translation();
+
+        Self {
+            node,
+            parent_matrix,
+            rest_position,
+            rest_yaw: yaw(rotation),
+        }
+    }
+
+    // model space position and yaw of the root in `animation` at `time`
+    fn sample(&self, skeleton: &Skeleton, animation: &Animation, time: f32) -> (Vec3, f32) {
+        let mut transform = skeleton.nodes[self.node].transform;
+        for track in animation.tracks.iter().filter(|t| t.target == self.node) {
+            transform.apply(track, time);
+        }
+
+        let (_, rotation, position) =
+            (self.parent_matrix * transform.matrix()).to_scale_rotation_translation();
+        (position, yaw(rotation))
+    }
+
+    // motion between two times within one cycle of the clip, `to` can be before `from` when playing in reverse
+    pub fn segment(
+        &self,
+        skeleton: &Skeleton,
+        animation: &Animation,
+        from: f32,
+        to: f32,
+    ) -> RootMotion {
+        let (from_position, from_yaw) = self.sample(skeleton, animation, from);
+        let (to_position, to_yaw) = self.sample(skeleton, animation, to);
+
+        let delta = to_position - from_position;
+        RootMotion {
+            translation: Qua::from_rotation_y(self.rest_yaw - from_yaw)
+                * Vec3::new(delta.x, 0.0, delta.z),
+            yaw: wrap_angle(to_yaw - from_yaw),
+        }
+    }
+
+    // motion of a looping clip from `from` to `to` seconds of playing time, every time the clip
+    // wraps around the motion of the cycles before is kept instead of snapping back
+    pub fn extract(
+        &self,
+        skeleton: &Skeleton,
+        animation: &Animation,
+        from: f32,
+        to: f32,
+    ) -> RootMotion {
+        let length = animation.length;
+        if length <= 0.0 || to == from {
+            return RootMotion::default();
+        }
+
+        let reverse = to < from;
+        let cycles = ((to / length).floor() - (from / length).floor()).abs() as usize;
+        let from = from.rem_euclid(length);
+        let to = to.rem_euclid(length);
+        if cycles == 0 {
+            return self.segment(skeleton, animation, from, to);
+        }
+
+        // the ends of a cycle in the direction of playing
+        let (cycle_start, cycle_end) = if reverse {
+            (length, 0.0)
+        } else {
+            (0.0, length)
+        };
+        let mut motion = self.segment(skeleton, animation, from, cycle_end);
+        let cycle = self.segment(skeleton, animation, cycle_start, cycle_end);
+        for _ in 1..cycles {
+            motion = motion.then(cycle);
+        }
+        motion.then(self.segment(skeleton, animation, cycle_start, to))
+    }
+
+    // keep the root at its rest position and facing horizontally, the motion is applied by the caller instead
+    pub fn strip(&self, transform: &mut Transform) {
+        let (scale, rotation, position) =
+            (self.parent_matrix * transform.matrix()).to_scale_rotation_translation();
+
+        let rotation = Qua::from_rotation_y(self.rest_yaw - yaw(rotation)) * rotation;
+        let position = Vec3::new(self.rest_position.x, position.y, self.rest_position.z);
+
+        let (scale, rotation, position) = (self.parent_matrix.inverse()
+            * Mat4::from_scale_rotation_translation(scale, rotation, position))
+        .to_scale_rotation_translation();
+        *transform = Transform {
+            translation: position,
+            rotation,
+            scale,
+        };
+    }
+}
+
+// rotation around the Y axis, from the twist part of a swing-twist decomposition
+fn yaw(rotation: Qua) -> f32 {
+    wrap_angle(2.0 * rotation.y.atan2(rotation.w))
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::{
+        animation::{Interpolation, Keyframes, Track},
+        animator::Animator,
+        skeleton::{Node, NodeType},
+    };
+
+    // a hip 1 above the ground under an unanimated root
+    fn hips() -> Skeleton {
+        let node = |name: &str, translation, children, parent| Node {
+            name: name.to_string(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: Vec::new(),
+            skin: None,
+        };
+        Skeleton {
+            nodes: vec![
+                node("root", Vec3::ZERO, vec![1], None),
+                node("hips", Vec3::Y, Vec::new(), Some(0)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        }
+    }
+
+    // moves the hips 2 forward over a second while turning them by `turn`
+    fn walk(turn: f32) -> Animation {
+        Animation {
+            name: "walk".to_string(),
+            length: 1.0,
+            tracks: vec![
+                Track {
+                    keyframes: Keyframes::Translations(vec![Vec3::Y, Vec3::new(0.0, 1.0, 2.0)]),
+                    interpolation: Interpolation::Linear,
+                    target: 1,
+                    timestamps: vec![0.0, 1.0],
+                },
+                Track {
+                    keyframes: Keyframes::Rotations(vec![
+                        Qua::IDENTITY,
+                        Qua::from_rotation_y(turn),
+                    ]),
+                    interpolation: Interpolation::Linear,
+                    target: 1,
+                    timestamps: vec![0.0, 1.0],
+                },
+            ],
+            events: Vec::new(),
+        }
+    }
+
+    fn assert_motion(motion: RootMotion, translation: Vec3, yaw: f32) {
+        assert!(
+            motion.translation.abs_diff_eq(translation, 1e-4) && (motion.yaw - yaw).abs() < 1e-4,
+            "expected {translation} and {yaw}, got {motion:?}"
+        );
+    }
+
+    #[test]
+    fn one_cycle_moves_the_length_of_the_clip() {
+        let skeleton = hips();
+        let extractor = RootMotionExtractor::new(&skeleton, 1);
+        let animation = walk(0.0);
+
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            0.0,
+        );
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 0.25, 0.75),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn loops_keep_accumulating_motion() {
+        let skeleton = hips();
+        let extractor = RootMotionExtractor::new(&skeleton, 1);
+        let animation = walk(0.0);
+
+        // the rest of the first cycle, two full ones and a quarter of the next in one update
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 0.5, 3.25),
+            Vec3::new(0.0, 0.0, 5.5),
+            0.0,
+        );
+
+        // the same in small steps across the wraps
+        let motion = (0..11).fold(RootMotion::default(), |motion, i| {
+            let from = 0.5 + i as f32 * 0.25;
+            motion.then(extractor.extract(&skeleton, &animation, from, from + 0.25))
+        });
+        assert_motion(motion, Vec3::new(0.0, 0.0, 5.5), 0.0);
+    }
+
+    #[test]
+    fn turning_loops_walk_in_the_new_direction() {
+        let skeleton = hips();
+        let extractor = RootMotionExtractor::new(&skeleton, 1);
+        let animation = walk(FRAC_PI_2);
+
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            FRAC_PI_2,
+        );
+        // the second cycle starts facing +x
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 0.0, 2.0),
+            Vec3::new(2.0, 0.0, 2.0),
+            FRAC_PI_2 * 2.0,
+        );
+    }
+
+    #[test]
+    fn reverse_playback_moves_backwards() {
+        let skeleton = hips();
+        let extractor = RootMotionExtractor::new(&skeleton, 1);
+        let animation = walk(0.0);
+
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 0.75, 0.25),
+            Vec3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        // wrapping twice backwards
+        assert_motion(
+            extractor.extract(&skeleton, &animation, 1.25, -0.5),
+            Vec3::new(0.0, 0.0, -3.5),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn stripping_keeps_the_root_in_place() {
+        let skeleton = hips();
+        let extractor = RootMotionExtractor::new(&skeleton, 1);
+
+        let lean = Qua::from_rotation_x(0.3);
+        let mut transform = Transform {
+            translation: Vec3::new(1.0, 0.5, 3.0),
+            rotation: Qua::from_rotation_y(1.2) * lean,
+            scale: Vec3::ONE,
+        };
+        extractor.strip(&mut transform);
+
+        // only the height and the lean are kept
+        assert!(transform
+            .translation
+            .abs_diff_eq(Vec3::new(0.0, 0.5, 0.0), 1e-5));
+        assert!(transform.rotation.abs_diff_eq(lean, 1e-5));
+    }
+
+    #[test]
+    fn playing_a_loop_accumulates_its_motion() {
+        let skeleton = hips();
+        let mut animator = Animator::new(vec![walk(0.0)]);
+        animator.set_root_motion_node(&skeleton, Some(1));
+
+        // two and a half loops
+        let mut motion = RootMotion::default();
+        for _ in 0..10 {
+            let (pose, _) = animator.animate(&skeleton, 0.25);
+            motion = motion.then(animator.root_motion());
+            assert!(pose.transforms[1].translation.abs_diff_eq(Vec3::Y, 1e-5));
+        }
+        assert_eq!(animator.playback.loops, 2);
+        assert_motion(motion, Vec3::new(0.0, 0.0, 5.0), 0.0);
+    }
+}
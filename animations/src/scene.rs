@@ -0,0 +1,234 @@
+use jandering_engine::{
+    types::{Mat4, Vec3},
+    utils::free_camera::MatrixCamera,
+};
+
+use crate::skeleton::Pose;
+
+// lights the model from above when the gltf doesn't contain any lights
+const DEFAULT_LIGHT_DIRECTION: Vec3 = Vec3::new(-1.0, -1.0, -1.0);
+// gltf cameras without a far plane are infinite
+const DEFAULT_ZFAR: f32 = 10000.0;
+
+// the cameras and KHR_lights_punctual lights of a gltf. Both are attached to nodes of the skeleton,
+// so animations move them like any other node
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub cameras: Vec<SceneCamera>,
+    pub lights: Vec<SceneLight>,
+}
+
+// the authored aspect ratio and width aren't used, the window decides those
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    Perspective {
+        // radians
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        // half the width and height of the view
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneCamera {
+    #[allow(dead_code)]
+    pub name: String,
+    pub node: usize,
+    pub projection: Projection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        // radians from the light's direction
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneLight {
+    #[allow(dead_code)]
+    pub name: String,
+    pub node: usize,
+    pub kind: LightKind,
+    pub color: Vec3,
+    // lux for directional lights, candela for point and spot lights
+    pub intensity: f32,
+    // where the light stops, infinite if `None`
+    pub range: Option<f32>,
+}
+
+// one light as the shader reads it
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, Debug)]
+pub struct LightData {
+    pub position: Vec3,
+    // 0 directional, 1 point, 2 spot
+    pub kind: u32,
+    // where the light points to
+    pub direction: Vec3,
+    // 0 when infinite
+    pub range: f32,
+    // multiplied with the intensity
+    pub color: Vec3,
+    pub inner_cone_cos: f32,
+    pub outer_cone_cos: f32,
+    padding: [f32; 3],
+}
+
+impl Scene {
+    pub fn from_gltf(gltf: &gltf::Document) -> Self {
+        let mut cameras = Vec::new();
+        let mut lights = Vec::new();
+
+        for node in gltf.nodes() {
+            if let Some(camera) = node.camera() {
+                let projection = match camera.projection() {
+                    gltf::camera::Projection::Perspective(perspective) => Projection::Perspective {
+                        yfov: perspective.yfov(),
+                        aspect_ratio: perspective.aspect_ratio(),
+                        znear: perspective.znear(),
+                        zfar: perspective.zfar(),
+                    },
+                    gltf::camera::Projection::Orthographic(orthographic) => {
+                        Projection::Orthographic {
+                            xmag: orthographic.xmag(),
+                            ymag: orthographic.ymag(),
+                            znear: orthographic.znear(),
+                            zfar: orthographic.zfar(),
+                        }
+                    }
+                };
+                cameras.push(SceneCamera {
+                    name: camera
+                        .name()
+                        .or(node.name())
+                        .unwrap_or("Unnamed")
+                        .to_string(),
+                    node: node.index(),
+                    projection,
+                });
+            }
+
+            if let Some(light) = node.light() {
+                let kind = match light.kind() {
+                    gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+                    gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+                    gltf::khr_lights_punctual::Kind::Spot {
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    } => LightKind::Spot {
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    },
+                };
+                lights.push(SceneLight {
+                    name: light
+                        .name()
+                        .or(node.name())
+                        .unwrap_or("Unnamed")
+                        .to_string(),
+                    node: node.index(),
+                    kind,
+                    color: Vec3::from_array(light.color()),
+                    intensity: light.intensity(),
+                    range: light.range(),
+                });
+            }
+        }
+
+        Self { cameras, lights }
+    }
+
+    // the lights at their nodes in `pose`, or a single white directional light if there are none
+    pub fn light_data(&self, pose: &Pose) -> Vec<LightData> {
+        if self.lights.is_empty() {
+            return vec![LightData {
+                position: Vec3::ZERO,
+                kind: 0,
+                direction: DEFAULT_LIGHT_DIRECTION.normalize(),
+                range: 0.0,
+                color: Vec3::ONE,
+                inner_cone_cos: 0.0,
+                outer_cone_cos: 0.0,
+                padding: [0.0; 3],
+            }];
+        }
+
+        self.lights
+            .iter()
+            .map(|light| {
+                let world = pose.world_transforms[light.node];
+                let (kind, inner_cone_angle, outer_cone_angle) = match light.kind {
+                    LightKind::Directional => (0, 0.0, 0.0),
+                    LightKind::Point => (1, 0.0, 0.0),
+                    LightKind::Spot {
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    } => (2, inner_cone_angle, outer_cone_angle),
+                };
+                LightData {
+                    position: world.w_axis.truncate(),
+                    kind,
+                    // lights point down their node's -Z
+                    direction: (-world.z_axis.truncate()).normalize_or_zero(),
+                    range: light.range.unwrap_or(0.0),
+                    color: light.color * light.intensity,
+                    inner_cone_cos: inner_cone_angle.cos(),
+                    outer_cone_cos: outer_cone_angle.cos(),
+                    padding: [0.0; 3],
+                }
+            })
+            .collect()
+    }
+}
+
+impl SceneCamera {
+    // world transform of the camera's node in `pose` without scale, looking down -Z with +Y up
+    pub fn world_transform(&self, pose: &Pose) -> Mat4 {
+        let (_, rotation, translation) =
+            pose.world_transforms[self.node].to_scale_rotation_translation();
+        Mat4::from_rotation_translation(rotation, translation)
+    }
+
+    // move `camera` to this camera in `pose` and use its projection. The authored aspect ratio is
+    // ignored so the view isn't stretched when the window has a different one, roll is lost since
+    // `camera` only keeps a direction
+    pub fn apply(&self, camera: &mut MatrixCamera, pose: &Pose, aspect_ratio: f32) {
+        match self.projection {
+            Projection::Perspective {
+                yfov, znear, zfar, ..
+            } => camera.make_perspective(
+                yfov.to_degrees(),
+                aspect_ratio,
+                znear,
+                zfar.unwrap_or(DEFAULT_ZFAR),
+            ),
+            Projection::Orthographic {
+                ymag, znear, zfar, ..
+            } => camera.make_ortho(
+                -ymag * aspect_ratio,
+                ymag * aspect_ratio,
+                -ymag,
+                ymag,
+                znear,
+                zfar,
+            ),
+        }
+
+        let transform = self.world_transform(pose);
+        camera.set_position(transform.w_axis.truncate());
+        camera.set_direction(-transform.z_axis.truncate());
+    }
+}
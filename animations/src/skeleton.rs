@@ -0,0 +1,524 @@
+use jandering_engine::types::{Mat4, Qua, Vec3};
+
+use crate::{
+    animation::{Animation, Keyframes, Track},
+    error::GltfLoadError,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Qua,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    // overwrite the part of the transform `track` animates with its value at `time`, morph weight tracks are ignored
+    pub fn apply(&mut self, track: &Track, time: f32) {
+        match &track.keyframes {
+            Keyframes::Rotations(rotations) => {
+                if let Some(rotation) = track.sample(time, |i| rotations[i]) {
+                    self.rotation = rotation;
+                }
+            }
+            Keyframes::Translations(translations) => {
+                if let Some(translation) = track.sample(time, |i| translations[i]) {
+                    self.translation = translation;
+                }
+            }
+            Keyframes::Scales(scales) => {
+                if let Some(scale) = track.sample(time, |i| scales[i]) {
+                    self.scale = scale;
+                }
+            }
+            Keyframes::QuantizedRotations(rotations) => {
+                if let Some(rotation) = track.sample(time, |i| rotations[i].decode()) {
+                    self.rotation = rotation;
+                }
+            }
+            Keyframes::MorphTargetWeights(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum NodeType {
+    Mesh { mesh: usize },
+    Generic,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub node_type: NodeType,
+    // rest pose
+    pub transform: Transform,
+    pub children: Vec<usize>,
+    pub parent: Option<usize>,
+    pub weights: Vec<f32>,
+    pub skin: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Skin {
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+// plain cpu side data, doesn't need a renderer so it can be loaded and sampled headlessly
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    pub skins: Vec<Skin>,
+}
+
+// local transforms of every node plus the world matrices calculated from them
+#[derive(Debug, Clone)]
+pub struct Pose {
+    pub transforms: Vec<Transform>,
+    pub weights: Vec<Vec<f32>>,
+    pub world_transforms: Vec<Mat4>,
+}
+
+impl Skeleton {
+    pub fn from_gltf(gltf: &gltf::Document, buffers: &[Vec<u8>]) -> Result<Self, GltfLoadError> {
+        let mut nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                let weights = node
+                    .weights()
+                    .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+                    .map(|weights| weights.to_vec())
+                    .unwrap_or_default();
+
+                Node {
+                    name: node.name().unwrap_or("Unnamed").to_string(),
+                    node_type: if let Some(mesh) = node.mesh() {
+                        NodeType::Mesh { mesh: mesh.index() }
+                    } else {
+                        NodeType::Generic
+                    },
+                    transform: Transform {
+                        translation: translation.into(),
+                        rotation: Qua::from_array(rotation),
+                        scale: scale.into(),
+                    },
+                    children: node.children().map(|n| n.index()).collect(),
+                    parent: None,
+                    weights,
+                    skin: node.skin().map(|skin| skin.index()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for i in 0..nodes.len() {
+            for child in nodes[i].children.clone() {
+                nodes[child].parent = Some(i);
+            }
+        }
+
+        let roots = (0..nodes.len())
+            .filter(|i| nodes[*i].parent.is_none())
+            .collect();
+
+        let skins = gltf
+            .skins()
+            .map(|skin| {
+                let joints = skin.joints().map(|n| n.index()).collect::<Vec<_>>();
+
+                let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+                let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+                    Some(inverse_matrices) => inverse_matrices
+                        .map(|mat| Mat4::from_cols_array_2d(&mat))
+                        .collect(),
+                    None => vec![Mat4::IDENTITY; joints.len()],
+                };
+
+                // joint matrices pair every joint with its inverse bind matrix
+                if inverse_bind_matrices.len() != joints.len() {
+                    return Err(GltfLoadError::InverseBindMatrixCountMismatch {
+                        skin: skin.index(),
+                        joints: joints.len(),
+                        matrices: inverse_bind_matrices.len(),
+                    });
+                }
+
+                Ok(Skin {
+                    joints,
+                    inverse_bind_matrices,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            nodes,
+            roots,
+            skins,
+        })
+    }
+
+    pub fn rest_pose(&self) -> Pose {
+        let mut pose = Pose {
+            transforms: self.nodes.iter().map(|node| node.transform).collect(),
+            weights: self.nodes.iter().map(|node| node.weights.clone()).collect(),
+            world_transforms: vec![Mat4::IDENTITY; self.nodes.len()],
+        };
+        self.update_world_matrices(&mut pose);
+        pose
+    }
+
+    // calculate world matrices for all nodes from the root nodes down
+    pub fn update_world_matrices(&self, pose: &mut Pose) {
+        for root in self.roots.iter() {
+            self.update_world_matrix(pose, *root, Mat4::IDENTITY);
+        }
+    }
+
+    // true for `root` and every node below it
+    pub fn subtree_mask(&self, root: usize) -> Vec<bool> {
+        let mut mask = vec![false; self.nodes.len()];
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            mask[node] = true;
+            stack.extend(self.nodes[node].children.iter().copied());
+        }
+        mask
+    }
+
+    // recalculate the world matrices of `node` and everything below it, e.g. after changing its local transform
+    pub fn update_subtree_world_matrices(&self, pose: &mut Pose, node: usize) {
+        let parent_matrix = self.nodes[node]
+            .parent
+            .map_or(Mat4::IDENTITY, |parent| pose.world_transforms[parent]);
+        self.update_world_matrix(pose, node, parent_matrix);
+    }
+
+    fn update_world_matrix(&self, pose: &mut Pose, node: usize, parent_matrix: Mat4) {
+        let world = parent_matrix * pose.transforms[node].matrix();
+        pose.world_transforms[node] = world;
+        for child in self.nodes[node].children.iter() {
+            self.update_world_matrix(pose, *child, world);
+        }
+    }
+
+    pub fn joint_matrices(&self, skin: usize, pose: &Pose) -> Vec<Mat4> {
+        let skin = &self.skins[skin];
+        skin.joints
+            .iter()
+            .zip(skin.inverse_bind_matrices.iter())
+            .map(|(joint, inverse_bind_matrix)| {
+                pose.world_transforms[*joint] * *inverse_bind_matrix
+            })
+            .collect()
+    }
+
+    // joint matrices of `skin` for `animation` at `time`, starting from the rest pose
+    pub fn sample(&self, animation: &Animation, skin: usize, time: f32) -> Vec<Mat4> {
+        let mut pose = self.rest_pose();
+        pose.apply(animation, time);
+        self.update_world_matrices(&mut pose);
+        self.joint_matrices(skin, &pose)
+    }
+}
+
+impl Pose {
+    // blend local transforms and morph weights towards `other`, world matrices have to be updated afterwards
+    pub fn blend(&mut self, other: &Pose, t: f32) {
+        self.blend_masked(other, t, None);
+    }
+
+    // like `blend`, but only for the nodes `mask` is true for
+    pub fn blend_masked(&mut self, other: &Pose, t: f32, mask: Option<&[bool]>) {
+        for node in 0..self.transforms.len() {
+            if mask.is_some_and(|mask| !mask[node]) {
+                continue;
+            }
+
+            let transform = &mut self.transforms[node];
+            let other_transform = &other.transforms[node];
+            transform.translation = transform.translation.lerp(other_transform.translation, t);
+            transform.rotation = transform.rotation.slerp(other_transform.rotation, t);
+            transform.scale = transform.scale.lerp(other_transform.scale, t);
+
+            let weights = &mut self.weights[node];
+            weights.resize(other.weights[node].len(), 0.0);
+            for (weight, other) in weights.iter_mut().zip(other.weights[node].iter()) {
+                *weight += (other - *weight) * t;
+            }
+        }
+    }
+
+    // overwrite the local transforms targeted by `animation` with its values at `time`,
+    // world matrices have to be updated afterwards
+    pub fn apply(&mut self, animation: &Animation, time: f32) {
+        for track in animation.tracks.iter() {
+            if let Keyframes::MorphTargetWeights(weights) = &track.keyframes {
+                let n_targets = track.morph_target_count();
+                // refilled in place so reused poses keep their allocation
+                let target_weights = &mut self.weights[track.target];
+                target_weights.clear();
+                target_weights.extend(
+                    (0..n_targets).filter_map(|target| {
+                        track.sample(time, |i| weights[i * n_targets + target])
+                    }),
+                );
+            } else {
+                self.transforms[track.target].apply(track, time);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::FRAC_PI_2, path::Path};
+
+    use super::*;
+    use crate::{animated_object::load_gltf, animation::Interpolation};
+
+    fn node(name: &str, translation: Vec3, children: Vec<usize>, parent: Option<usize>) -> Node {
+        Node {
+            name: name.to_string(),
+            node_type: NodeType::Generic,
+            transform: Transform {
+                translation,
+                rotation: Qua::IDENTITY,
+                scale: Vec3::ONE,
+            },
+            children,
+            parent,
+            weights: vec![0.0, 0.0],
+            skin: None,
+        }
+    }
+
+    // a root at y 1 with a child 1 above it, skinned with both as joints
+    fn two_bones() -> Skeleton {
+        let mut skeleton = Skeleton {
+            nodes: vec![
+                node("root", Vec3::Y, vec![1], None),
+                node("tip", Vec3::Y, vec![], Some(0)),
+            ],
+            roots: vec![0],
+            skins: Vec::new(),
+        };
+        let rest = skeleton.rest_pose();
+        skeleton.skins.push(Skin {
+            joints: vec![0, 1],
+            inverse_bind_matrices: rest
+                .world_transforms
+                .iter()
+                .map(|matrix| matrix.inverse())
+                .collect(),
+        });
+        skeleton
+    }
+
+    fn animation(tracks: Vec<Track>) -> Animation {
+        Animation {
+            name: "test".to_string(),
+            length: 1.0,
+            tracks,
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn rest_pose_joint_matrices_are_identity() {
+        let skeleton = two_bones();
+        for matrix in skeleton.sample(&animation(Vec::new()), 0, 0.5) {
+            assert!(matrix.abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        }
+    }
+
+    #[test]
+    fn rotating_the_root_moves_the_child() {
+        let skeleton = two_bones();
+        let animation = animation(vec![Track {
+            keyframes: Keyframes::Rotations(vec![Qua::IDENTITY, Qua::from_rotation_z(FRAC_PI_2)]),
+            interpolation: Interpolation::Linear,
+            target: 0,
+            timestamps: vec![0.0, 1.0],
+        }]);
+
+        let mut pose = skeleton.rest_pose();
+        pose.apply(&animation, 0.5);
+        skeleton.update_world_matrices(&mut pose);
+        // 45 degrees around z from (0, 1, 0)
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(pose.world_transforms[0]
+            .w_axis
+            .truncate()
+            .abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(pose.world_transforms[1]
+            .w_axis
+            .truncate()
+            .abs_diff_eq(Vec3::new(-half, 1.0 + half, 0.0), 1e-5));
+
+        // at the end the tip lies on -x, and the joint matrix moves the rest position of the tip there
+        let joints = skeleton.sample(&animation, 0, 1.0);
+        assert!(joints[1]
+            .transform_point3(Vec3::new(0.0, 2.0, 0.0))
+            .abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-5));
+        assert!(joints[0]
+            .transform_point3(Vec3::new(0.0, 2.0, 0.0))
+            .abs_diff_eq(Vec3::new(-1.0, 1.0, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn translation_scale_and_weights_are_applied() {
+        let skeleton = two_bones();
+        let animation = animation(vec![
+            Track {
+                keyframes: Keyframes::Translations(vec![Vec3::Y, Vec3::new(2.0, 1.0, 0.0)]),
+                interpolation: Interpolation::Linear,
+                target: 0,
+                timestamps: vec![0.0, 1.0],
+            },
+            Track {
+                keyframes: Keyframes::Scales(vec![Vec3::ONE, Vec3::splat(3.0)]),
+                interpolation: Interpolation::Step,
+                target: 0,
+                timestamps: vec![0.0, 1.0],
+            },
+            Track {
+                keyframes: Keyframes::MorphTargetWeights(vec![0.0, 1.0, 1.0, 0.0]),
+                interpolation: Interpolation::Linear,
+                target: 1,
+                timestamps: vec![0.0, 1.0],
+            },
+        ]);
+
+        let mut pose = skeleton.rest_pose();
+        pose.apply(&animation, 0.25);
+        skeleton.update_world_matrices(&mut pose);
+        assert!(pose.transforms[0]
+            .translation
+            .abs_diff_eq(Vec3::new(0.5, 1.0, 0.0), 1e-6));
+        assert_eq!(pose.transforms[0].scale, Vec3::ONE);
+        assert_eq!(pose.weights[1], vec![0.25, 0.75]);
+        assert!(pose.world_transforms[1]
+            .w_axis
+            .truncate()
+            .abs_diff_eq(Vec3::new(0.5, 2.0, 0.0), 1e-6));
+
+        pose.apply(&animation, 1.0);
+        skeleton.update_world_matrices(&mut pose);
+        // the child is scaled with its parent, 3 above it
+        assert!(pose.world_transforms[1]
+            .w_axis
+            .truncate()
+            .abs_diff_eq(Vec3::new(2.0, 4.0, 0.0), 1e-5));
+        assert_eq!(pose.weights[1], vec![1.0, 0.0]);
+    }
+
+    #[test]
+    fn skins_need_an_inverse_bind_matrix_per_joint() {
+        // two joints but only one matrix in the accessor
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "nodes": [{ "children": [1] }, {}],
+            "skins": [{ "joints": [0, 1], "inverseBindMatrices": 0 }],
+            "accessors": [{ "bufferView": 0, "componentType": 5126, "count": 1, "type": "MAT4" }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 64 }],
+            "buffers": [{ "byteLength": 64 }]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let buffer = bytemuck::cast_slice(&[Mat4::IDENTITY]).to_vec();
+
+        assert!(matches!(
+            Skeleton::from_gltf(&gltf, &[buffer]),
+            Err(GltfLoadError::InverseBindMatrixCountMismatch {
+                skin: 0,
+                joints: 2,
+                matrices: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn blending_poses_mixes_the_local_transforms() {
+        let skeleton = two_bones();
+        let mut pose = skeleton.rest_pose();
+        let mut other = skeleton.rest_pose();
+        other.transforms[0].translation = Vec3::new(4.0, 1.0, 0.0);
+        other.transforms[0].rotation = Qua::from_rotation_z(FRAC_PI_2);
+        other.weights[1] = vec![1.0, 1.0];
+
+        pose.blend_masked(&other, 0.5, Some(&[true, false]));
+        assert!(pose.transforms[0]
+            .translation
+            .abs_diff_eq(Vec3::new(2.0, 1.0, 0.0), 1e-6));
+        assert!(pose.transforms[0]
+            .rotation
+            .abs_diff_eq(Qua::from_rotation_z(FRAC_PI_2 * 0.5), 1e-6));
+        // masked out
+        assert_eq!(pose.weights[1], vec![0.0, 0.0]);
+    }
+
+    // joint matrices of `res/character.gltf` at fixed times, from an independent implementation of
+    // the gltf sampling rules rather than this one. 0 is before the first keyframe and 10 after the last
+    #[test]
+    fn character_poses_match_the_golden_values() {
+        let (gltf, buffers) = load_gltf(Path::new("res/character.gltf")).unwrap();
+        let skeleton = Skeleton::from_gltf(&gltf, &buffers).unwrap();
+        let animations = gltf
+            .animations()
+            .map(|animation| Animation::from_gltf(animation, &buffers))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // animation, time, joint, columns of the joint matrix
+        #[rustfmt::skip]
+        let golden: &[(usize, f32, usize, [f32; 16])] = &[
+            // Hips
+            (0, 0.0, 0, [0.986968, 0.140047, -0.079253, 0.0, -0.111006, 0.949104, 0.294754, 0.0, 0.116499, -0.282115, 0.952281, 0.0, 0.11189, -0.195232, -0.276592, 1.0]),
+            // Head
+            (0, 0.0, 5, [0.997021, -0.074, 0.021769, 0.0, 0.056559, 0.893241, 0.446006, 0.0, -0.052449, -0.443446, 0.894765, 0.0, -0.196324, -0.151748, -0.422153, 1.0]),
+            // LeftHand
+            (0, 0.0, 34, [-0.748308, 0.108333, -0.654445, 0.0, 0.136459, 0.990613, 0.00795, 0.0, 0.649163, -0.083356, -0.756068, 0.0, 0.1036, -0.387264, 0.823014, 1.0]),
+            // RightFoot
+            (0, 0.0, 57, [0.855278, -0.000004, 0.518169, 0.0, 0.003513, 0.999977, -0.00579, 0.0, -0.518157, 0.006773, 0.855258, 0.0, 0.061915, 0.00077, 0.555171, 1.0]),
+            // Hips
+            (0, 1.234, 0, [0.817695, 0.464023, -0.340671, 0.0, -0.521168, 0.84806, -0.095803, 0.0, 0.244455, 0.255885, 0.935288, 0.0, 1.560968, -0.439779, 0.692729, 1.0]),
+            // Head
+            (0, 1.234, 5, [0.219518, 0.950702, 0.219039, 0.0, -0.935864, 0.268629, -0.228027, 0.0, -0.275626, -0.154935, 0.948697, 0.0, 2.170922, 0.454146, 0.985256, 1.0]),
+            // LeftHand
+            (0, 1.234, 34, [-0.213134, 0.750006, 0.626151, 0.0, -0.279878, 0.56715, -0.774602, 0.0, -0.936078, -0.340339, 0.089031, 0.0, 1.565909, -0.079415, 1.530082, 1.0]),
+            // RightFoot
+            (0, 1.234, 57, [0.483168, 0.158194, 0.861117, 0.0, 0.391477, 0.84071, -0.374101, 0.0, -0.783131, 0.517861, 0.344275, 0.0, 0.653256, 0.083488, 1.538454, 1.0]),
+            // Hips
+            (0, 10.0, 0, [0.96828, -0.140419, 0.20668, 0.0, 0.072151, 0.949048, 0.306761, 0.0, -0.239225, -0.282118, 0.929075, 0.0, 1.363866, -0.194806, 0.248571, 1.0]),
+            // Head
+            (0, 10.0, 5, [0.991392, 0.073959, 0.108036, 0.0, -0.114041, 0.893143, 0.435074, 0.0, -0.064314, -0.44365, 0.893889, 0.0, 1.68883, -0.151274, 0.144141, 1.0]),
+            // LeftHand
+            (0, 10.0, 34, [0.806867, -0.486292, -0.335388, 0.0, 0.539902, 0.837464, 0.084608, 0.0, 0.239731, -0.249344, 0.938272, 0.0, 0.460933, -0.067625, 0.423868, 1.0]),
+            // RightFoot
+            (0, 10.0, 57, [0.380585, -0.471314, 0.795624, 0.0, -0.639576, 0.487254, 0.59458, 0.0, -0.667905, -0.735151, -0.115999, 0.0, 1.681394, 0.057177, 0.08054, 1.0]),
+            // Hips
+            (1, 0.7, 0, [0.830007, 0.009833, 0.557666, 0.0, 0.073063, 0.989312, -0.126187, 0.0, -0.552946, 0.14548, 0.820418, 0.0, -0.143964, -0.106299, 0.263552, 1.0]),
+            // Head
+            (1, 0.7, 5, [0.898144, -0.229678, 0.374947, 0.0, 0.439052, 0.51477, -0.736373, 0.0, -0.023882, 0.82599, 0.563178, 0.0, -0.670576, 0.638912, 1.184417, 1.0]),
+            // LeftHand
+            (1, 0.7, 34, [0.868422, -0.490662, -0.071373, 0.0, -0.442219, -0.701362, -0.559045, 0.0, 0.224244, 0.51705, -0.826059, 0.0, 0.515217, 2.232165, 0.840955, 1.0]),
+            // RightFoot
+            (1, 0.7, 57, [0.476251, -0.061217, 0.877175, 0.0, -0.096133, 0.987969, 0.121143, 0.0, -0.874038, -0.142021, 0.464637, 0.0, -0.156159, 0.01265, -0.231047, 1.0]),
+        ];
+
+        for (animation, time, joint, expected) in golden {
+            let joints = skeleton.sample(&animations[*animation], 0, *time);
+            let expected = Mat4::from_cols_array(expected);
+            assert!(
+                joints[*joint].abs_diff_eq(expected, 1e-4),
+                "joint {joint} of animation {animation} at {time}: {} != {expected}",
+                joints[*joint]
+            );
+        }
+    }
+}
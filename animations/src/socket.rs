@@ -0,0 +1,31 @@
+use jandering_engine::types::Mat4;
+
+use crate::animated_object::AnimatedObject;
+
+// a point on a node of the skeleton other objects can be attached to, e.g. a sword in the right hand
+#[derive(Debug, Clone, Copy)]
+pub struct Socket {
+    pub node: usize,
+    // relative to the node, so the attached object can be moved and rotated into place
+    pub offset: Mat4,
+}
+
+impl Socket {
+    pub fn new(node: usize) -> Self {
+        Self {
+            node,
+            offset: Mat4::IDENTITY,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Mat4) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    // world transform of the socket in the last animated pose, objects are attached by placing them there
+    // after every `AnimatedObject::update`
+    pub fn world_transform(&self, animated_object: &AnimatedObject) -> Mat4 {
+        animated_object.node_world_transform(self.node) * self.offset
+    }
+}
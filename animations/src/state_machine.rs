@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+
+use crate::{animator::Animator, playback::WrapMode};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parameter {
+    Float(f32),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    True(String),
+    False(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    pub name: String,
+    // referenced by name so the machine can be described before the model is loaded
+    pub animation: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    // `None` means the transition can be taken from any state
+    pub from: Option<String>,
+    pub to: String,
+    pub conditions: Vec<Condition>,
+    // normalized time of the current clip that has to pass before the transition can be taken, checked
+    // against every loop of a looping clip
+    pub exit_time: Option<f32>,
+    pub blend_duration: f32,
+}
+
+#[derive(Debug)]
+pub struct StateMachine {
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+    parameters: HashMap<String, Parameter>,
+    current_state: Option<usize>,
+    initial_state: String,
+}
+
+impl Condition {
+    fn is_met(&self, parameters: &HashMap<String, Parameter>) -> bool {
+        match self {
+            Condition::Greater(name, value) => {
+                matches!(parameters.get(name), Some(Parameter::Float(v)) if v > value)
+            }
+            Condition::Less(name, value) => {
+                matches!(parameters.get(name), Some(Parameter::Float(v)) if v < value)
+            }
+            Condition::True(name) => matches!(parameters.get(name), Some(Parameter::Bool(true))),
+            Condition::False(name) => {
+                matches!(parameters.get(name), Some(Parameter::Bool(false)))
+            }
+        }
+    }
+}
+
+impl Transition {
+    pub fn new(from: Option<&str>, to: &str) -> Self {
+        Self {
+            from: from.map(|from| from.to_string()),
+            to: to.to_string(),
+            conditions: Vec::new(),
+            exit_time: None,
+            blend_duration: 0.0,
+        }
+    }
+
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn with_exit_time(mut self, exit_time: f32) -> Self {
+        self.exit_time = Some(exit_time);
+        self
+    }
+
+    pub fn with_blend_duration(mut self, blend_duration: f32) -> Self {
+        self.blend_duration = blend_duration;
+        self
+    }
+}
+
+impl StateMachine {
+    pub fn new(initial_state: &str) -> Self {
+        Self {
+            states: Vec::new(),
+            transitions: Vec::new(),
+            parameters: HashMap::new(),
+            current_state: None,
+            initial_state: initial_state.to_string(),
+        }
+    }
+
+    pub fn with_state(mut self, name: &str, animation: &str) -> Self {
+        self.states.push(State {
+            name: name.to_string(),
+            animation: animation.to_string(),
+        });
+        self
+    }
+
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.transitions.push(transition);
+        self
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.parameters
+            .insert(name.to_string(), Parameter::Float(value));
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.parameters
+            .insert(name.to_string(), Parameter::Bool(value));
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<Parameter> {
+        self.parameters.get(name).copied()
+    }
+
+    pub fn current_state(&self) -> Option<&State> {
+        self.current_state.map(|state| &self.states[state])
+    }
+
+    fn find_state(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|state| state.name == name)
+    }
+
+    // call once per frame before `AnimatedObject::update`, takes at most one transition per call
+    pub fn update(&mut self, animator: &mut Animator) {
+        let Some(current_state) = self.current_state else {
+            if let Some(state) = self.find_state(&self.initial_state) {
+                if let Some(animation) = animator.find_animation(&self.states[state].animation) {
+                    animator.play(animation);
+                }
+                self.current_state = Some(state);
+            }
+            return;
+        };
+
+        let current = &self.states[current_state];
+        // how far into its current loop the clip is, and whether it wrapped around during the last update so exit
+        // times close to the end aren't skipped
+        let (normalized_time, looped) = match animator.find_animation(&current.animation) {
+            Some(animation) if animator.animations[animation].length > 0.0 => {
+                let length = animator.animations[animation].length;
+                let playback = &animator.playback;
+                let looped = playback.wrap_mode == WrapMode::Loop
+                    && (playback.previous_time / length).floor()
+                        != (playback.time / length).floor();
+                (playback.clip_time(length) / length, looped)
+            }
+            _ => (0.0, false),
+        };
+
+        let transition = self.transitions.iter().find(|transition| {
+            let from_matches = match &transition.from {
+                Some(from) => *from == current.name,
+                None => transition.to != current.name,
+            };
+
+            from_matches
+                && transition
+                    .exit_time
+                    .is_none_or(|exit_time| looped || normalized_time >= exit_time)
+                && transition
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.is_met(&self.parameters))
+        });
+
+        let Some(transition) = transition else {
+            return;
+        };
+        let Some(next_state) = self.find_state(&transition.to) else {
+            return;
+        };
+
+        if let Some(animation) = animator.find_animation(&self.states[next_state].animation) {
+            animator.crossfade_to(animation, transition.blend_duration);
+        }
+        self.current_state = Some(next_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Animation;
+
+    fn clip(name: &str) -> Animation {
+        Animation {
+            name: name.to_string(),
+            length: 1.0,
+            tracks: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn animator() -> Animator {
+        Animator::new(vec![clip("idle"), clip("walk"), clip("jump"), clip("wave")])
+    }
+
+    fn machine() -> StateMachine {
+        StateMachine::new("idle")
+            .with_state("idle", "idle")
+            .with_state("walk", "walk")
+            .with_state("jump", "jump")
+            .with_state("wave", "wave")
+    }
+
+    fn state(machine: &StateMachine) -> &str {
+        &machine.current_state().unwrap().name
+    }
+
+    #[test]
+    fn transitions_are_taken_when_all_their_conditions_are_met() {
+        let mut animator = animator();
+        let mut machine = machine()
+            .with_transition(
+                Transition::new(Some("idle"), "walk")
+                    .with_condition(Condition::Greater("speed".to_string(), 0.1))
+                    .with_condition(Condition::False("crouching".to_string())),
+            )
+            .with_transition(
+                Transition::new(Some("walk"), "idle")
+                    .with_condition(Condition::Less("speed".to_string(), 0.1)),
+            )
+            .with_transition(
+                Transition::new(Some("walk"), "jump")
+                    .with_condition(Condition::True("jumping".to_string())),
+            );
+
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "idle");
+        assert_eq!(animator.current_animation, 0);
+
+        // parameters that aren't set never meet a condition
+        machine.set_float("speed", 1.0);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "idle");
+
+        machine.set_bool("crouching", false);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "walk");
+        assert_eq!(animator.current_animation, 1);
+
+        // a float doesn't count as true
+        machine.set_float("jumping", 1.0);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "walk");
+
+        machine.set_bool("jumping", true);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "jump");
+        assert_eq!(machine.parameter("jumping"), Some(Parameter::Bool(true)));
+    }
+
+    #[test]
+    fn exit_times_are_checked_against_every_loop() {
+        let mut animator = animator();
+        let mut machine =
+            machine().with_transition(Transition::new(Some("idle"), "wave").with_exit_time(0.9));
+        machine.update(&mut animator);
+
+        for _ in 0..3 {
+            animator.playback.advance(0.25, 1.0);
+            machine.update(&mut animator);
+            assert_eq!(state(&machine), "idle");
+        }
+        // lands on 0 of the next loop, past the exit time of the last one
+        animator.playback.advance(0.25, 1.0);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "wave");
+
+        let mut animator = Animator::new(vec![clip("idle"), clip("wave")]);
+        let mut machine = StateMachine::new("idle")
+            .with_state("idle", "idle")
+            .with_state("wave", "wave")
+            .with_transition(Transition::new(Some("idle"), "wave").with_exit_time(0.9));
+        machine.update(&mut animator);
+        // a long frame jumps from before the exit time of one loop to before the exit time of the next
+        animator.playback.advance(0.5, 1.0);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "idle");
+        animator.playback.advance(0.95, 1.0);
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "wave");
+        assert_eq!(animator.current_animation, 1);
+    }
+
+    #[test]
+    fn transitions_from_any_state_skip_their_own_state() {
+        let mut animator = animator();
+        let mut machine = machine()
+            .with_transition(
+                Transition::new(None, "jump")
+                    .with_condition(Condition::True("jumping".to_string()))
+                    .with_blend_duration(0.2),
+            )
+            .with_transition(
+                Transition::new(Some("jump"), "wave")
+                    .with_condition(Condition::True("waving".to_string())),
+            );
+        machine.update(&mut animator);
+        machine.set_bool("jumping", true);
+        machine.set_bool("waving", true);
+
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "jump");
+        assert_eq!(animator.current_animation, 2);
+
+        // the any state transition comes first but doesn't restart the jump
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "wave");
+
+        machine.update(&mut animator);
+        assert_eq!(state(&machine), "jump");
+    }
+}
@@ -6,6 +6,21 @@ var tex_sampler: sampler;
 @group(1) @binding(0)
 var world_position_tex: texture_storage_2d<rg32float, read_write>;
 
+@group(2) @binding(0)
+var scene_tex: texture_2d<f32>;
+@group(2) @binding(1)
+var scene_sampler: sampler;
+
+struct DatamoshConfig{
+    velocity_scale: f32,
+    decay: f32,
+    refresh_interval: u32,
+    blend: f32,
+};
+
+@group(3) @binding(0)
+var<uniform> config: DatamoshConfig;
+
 struct VertexInput{
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
@@ -65,16 +80,21 @@ fn vs_main(
 fn fs_datamosh(in: VertexOutput) -> @location(0) vec4<f32>{
     let world_position_tex_size = textureDimensions(world_position_tex);
     let t = vec2<u32>(u32(in.uv.x * f32(world_position_tex_size.x)), u32(in.uv.y * f32(world_position_tex_size.y)));
-    var velocity = textureLoad(world_position_tex, t) * 2.0;
+    let stored_velocity = textureLoad(world_position_tex, t);
+    // whatever the scene doesn't overwrite next frame keeps moving pixels
+    textureStore(world_position_tex, t, stored_velocity * config.decay);
+
+    var velocity = stored_velocity * config.velocity_scale;
     velocity.y = -velocity.y;
 
     // return vec4<f32>(velocity.xy, 0.0, 1.0);
 
     let offset_tex = textureSample(tex, tex_sampler, in.uv + velocity.xy);
+    let scene = textureSample(scene_tex, scene_sampler, in.uv);
     if offset_tex.w == 0.0 {
         discard;
     }
-    return offset_tex;
+    return vec4<f32>(mix(offset_tex.rgb, scene.rgb, config.blend), offset_tex.w);
     // return vec4<f32>(offset_tex.rg + velocity.xy * 2.0, offset_tex.b, 1.0);
 }
 
@@ -0,0 +1,203 @@
+use jandering_engine::{
+    bind_group::{
+        BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutDescriptorEntry,
+        BindGroupLayoutEntry,
+    },
+    object::{Instance, Object},
+    render_pass::RenderPass,
+    renderer::{BindGroupHandle, BufferHandle, Janderer, Renderer, ShaderHandle, TargetTexture},
+    shader::ShaderDescriptor,
+    texture::{sampler::SamplerDescriptor, texture_usage, TextureDescriptor, TextureFormat},
+    types::{UVec2, Vec3},
+    utils::texture::{StorageTextureBindGroup, TextureSamplerBindGroup},
+    window::Window,
+};
+
+#[repr(C)]
+#[derive(Copy, Debug, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DatamoshConfig {
+    // how far pixels are moved along the velocity texture
+    pub velocity_scale: f32,
+    // how much of the velocity is left for the next frame where the scene doesn't write a new one,
+    // 0 clears it every frame
+    pub decay: f32,
+    // frames between refreshing the moshed image with the scene, 0 only refreshes on `refresh`.
+    // Only read on the cpu
+    pub refresh_interval: u32,
+    // how much of the scene is mixed into the moshed image every frame
+    pub blend: f32,
+}
+
+impl Default for DatamoshConfig {
+    fn default() -> Self {
+        Self {
+            velocity_scale: 2.0,
+            decay: 0.0,
+            refresh_interval: 0,
+            blend: 0.0,
+        }
+    }
+}
+
+// moves the pixels of the last image along a velocity texture every frame instead of drawing the scene
+// again. The scene colour and velocity are rendered by the caller, the velocity texture is rg32float
+// in screen space and has to be bound read_write
+pub struct Datamosh {
+    pub config: DatamoshConfig,
+    // the moshed image
+    history: TextureSamplerBindGroup,
+    // the next moshed image, copied into `history` after it's shown
+    output: TextureSamplerBindGroup,
+    config_buffer: BufferHandle,
+    config_bind_group: BindGroupHandle,
+    shader: ShaderHandle,
+    blit_shader: ShaderHandle,
+    fullscreen_quad: Object<Instance>,
+    refresh: bool,
+    frames_since_refresh: u32,
+}
+
+impl Datamosh {
+    pub fn new(
+        renderer: &mut Renderer,
+        velocity: &StorageTextureBindGroup,
+        config: DatamoshConfig,
+    ) -> Self {
+        let history = create_target(renderer, "datamosh_history");
+        let output = create_target(renderer, "datamosh_output");
+
+        let config_buffer = renderer.create_uniform_buffer(bytemuck::cast_slice(&[config]));
+        let config_bind_group = renderer.create_bind_group(BindGroupLayout {
+            entries: vec![BindGroupLayoutEntry::Data(config_buffer)],
+        });
+
+        let shader = renderer.create_shader(ShaderDescriptor {
+            name: "popr_shader",
+            source: jandering_engine::shader::ShaderSource::File(
+                jandering_engine::utils::FilePath::FileName("popr_shader.wgsl"),
+            ),
+            bind_group_layout_descriptors: vec![
+                TextureSamplerBindGroup::get_layout_descriptor(),
+                velocity.get_layout_descriptor(),
+                TextureSamplerBindGroup::get_layout_descriptor(),
+                BindGroupLayoutDescriptor {
+                    entries: vec![BindGroupLayoutDescriptorEntry::Data { is_uniform: true }],
+                },
+            ],
+            fs_entry: "fs_datamosh",
+            backface_culling: false,
+            ..Default::default()
+        });
+
+        let blit_shader = renderer.create_shader(ShaderDescriptor {
+            name: "blit_shader",
+            source: jandering_engine::shader::ShaderSource::File(
+                jandering_engine::utils::FilePath::FileName("popr_shader.wgsl"),
+            ),
+            bind_group_layout_descriptors: vec![TextureSamplerBindGroup::get_layout_descriptor()],
+            fs_entry: "fs_blit",
+            backface_culling: false,
+            ..Default::default()
+        });
+
+        let fullscreen_quad = Object::quad(
+            renderer,
+            vec![Instance::default()
+                .translate(Vec3::new(-1.0, -1.0, 0.0))
+                .scale(2.0)],
+        );
+
+        Self {
+            config,
+            history,
+            output,
+            config_buffer,
+            config_bind_group,
+            shader,
+            blit_shader,
+            fullscreen_quad,
+            refresh: true,
+            frames_since_refresh: 0,
+        }
+    }
+
+    // the next `render` starts moshing from the scene again
+    pub fn refresh(&mut self) {
+        self.refresh = true;
+    }
+
+    pub fn resize(&mut self, renderer: &mut Renderer, size: UVec2) {
+        for (target, name) in [
+            (&mut self.history, "datamosh_history"),
+            (&mut self.output, "datamosh_output"),
+        ] {
+            renderer.re_create_texture(
+                TextureDescriptor {
+                    name,
+                    size,
+                    format: TextureFormat::Bgra8U,
+                    usage: texture_usage::GENERIC,
+                    ..Default::default()
+                },
+                target.texture_handle,
+            );
+            target.re_create(renderer, target.texture_handle, target.sampler_handle);
+        }
+        self.refresh = true;
+    }
+
+    pub fn reload_shaders(&self, renderer: &mut Renderer) {
+        renderer.reload_shader(self.shader);
+        renderer.reload_shader(self.blit_shader);
+    }
+
+    // mosh `scene` along `velocity` and draw the result to the screen of `window`
+    pub fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        window: &mut Window,
+        scene: &TextureSamplerBindGroup,
+        velocity: &StorageTextureBindGroup,
+    ) {
+        self.frames_since_refresh += 1;
+        if self.config.refresh_interval > 0
+            && self.frames_since_refresh >= self.config.refresh_interval
+        {
+            self.refresh = true;
+        }
+        if self.refresh {
+            self.refresh = false;
+            self.frames_since_refresh = 0;
+            renderer.blit_textures(scene.texture_handle, self.history.texture_handle);
+        }
+
+        renderer.write_buffer(self.config_buffer, bytemuck::cast_slice(&[self.config]));
+
+        let pass = RenderPass::new(window)
+            .set_shader(self.shader)
+            .with_target_texture_resolve(TargetTexture::Handle(self.output.texture_handle), None)
+            .bind(0, self.history.bind_group)
+            .bind(1, velocity.bind_group)
+            .bind(2, scene.bind_group)
+            .bind(3, self.config_bind_group)
+            .render_one(&self.fullscreen_quad)
+            .set_shader(self.blit_shader)
+            .with_target_texture_resolve(TargetTexture::Screen, None)
+            .bind(0, self.output.bind_group)
+            .render_one(&self.fullscreen_quad);
+        renderer.submit_pass(pass);
+
+        renderer.blit_textures(self.output.texture_handle, self.history.texture_handle);
+    }
+}
+
+fn create_target(renderer: &mut Renderer, name: &'static str) -> TextureSamplerBindGroup {
+    let texture_handle = renderer.create_texture(TextureDescriptor {
+        name,
+        format: TextureFormat::Bgra8U,
+        usage: texture_usage::GENERIC,
+        ..Default::default()
+    });
+    let sampler_handle = renderer.create_sampler(SamplerDescriptor::default());
+    TextureSamplerBindGroup::new(renderer, texture_handle, sampler_handle)
+}
@@ -1,3 +1,4 @@
+use datamosh::{Datamosh, DatamoshConfig};
 use history_instance::HistoryInstance;
 use jandering_engine::{
     bind_group::{
@@ -5,7 +6,7 @@ use jandering_engine::{
         BindGroupLayoutEntry,
     },
     engine::{Engine, EngineConfig},
-    object::{Instance, Object, Vertex},
+    object::{Object, Vertex},
     render_pass::RenderPass,
     renderer::{BindGroupHandle, BufferHandle, Janderer, Renderer},
     shader::ShaderDescriptor,
@@ -18,6 +19,7 @@ use jandering_engine::{
     window::{InputState, WindowConfig, WindowManagerTrait, WindowTrait},
 };
 
+mod datamosh;
 mod history_instance;
 
 struct PrevCameraMatBindGroup {
@@ -79,38 +81,16 @@ fn main() {
         ..Default::default()
     });
 
-    let mut target_textures = [
-        {
-            let texture_handle = renderer.create_texture(TextureDescriptor {
-                name: "target_texture1",
-                format: TextureFormat::Bgra8U,
-                usage: texture_usage::GENERIC,
-                ..Default::default()
-            });
-            let sampler_handle = renderer.create_sampler(SamplerDescriptor::default());
-            TextureSamplerBindGroup::new(renderer, texture_handle, sampler_handle)
-        },
-        {
-            let texture_handle = renderer.create_texture(TextureDescriptor {
-                name: "target_texture2",
-                format: TextureFormat::Bgra8U,
-                usage: texture_usage::GENERIC,
-                ..Default::default()
-            });
-            let sampler_handle = renderer.create_sampler(SamplerDescriptor::default());
-            TextureSamplerBindGroup::new(renderer, texture_handle, sampler_handle)
-        },
-        {
-            let texture_handle = renderer.create_texture(TextureDescriptor {
-                name: "target_texture3",
-                format: TextureFormat::Bgra8U,
-                usage: texture_usage::GENERIC,
-                ..Default::default()
-            });
-            let sampler_handle = renderer.create_sampler(SamplerDescriptor::default());
-            TextureSamplerBindGroup::new(renderer, texture_handle, sampler_handle)
-        },
-    ];
+    let mut scene_texture = {
+        let texture_handle = renderer.create_texture(TextureDescriptor {
+            name: "scene_texture",
+            format: TextureFormat::Bgra8U,
+            usage: texture_usage::GENERIC,
+            ..Default::default()
+        });
+        let sampler_handle = renderer.create_sampler(SamplerDescriptor::default());
+        TextureSamplerBindGroup::new(renderer, texture_handle, sampler_handle)
+    };
 
     let mut storage_texture = {
         let texture_handle = renderer.create_texture(TextureDescriptor {
@@ -153,30 +133,7 @@ fn main() {
         )
     };
 
-    let popr_shader = renderer.create_shader(ShaderDescriptor {
-        name: "popr_shader",
-        source: jandering_engine::shader::ShaderSource::File(
-            jandering_engine::utils::FilePath::FileName("popr_shader.wgsl"),
-        ),
-        bind_group_layout_descriptors: vec![
-            TextureSamplerBindGroup::get_layout_descriptor(),
-            storage_texture.get_layout_descriptor(),
-        ],
-        fs_entry: "fs_datamosh",
-        backface_culling: false,
-        ..Default::default()
-    });
-
-    let blit_shader = renderer.create_shader(ShaderDescriptor {
-        name: "blit_shader",
-        source: jandering_engine::shader::ShaderSource::File(
-            jandering_engine::utils::FilePath::FileName("popr_shader.wgsl"),
-        ),
-        bind_group_layout_descriptors: vec![TextureSamplerBindGroup::get_layout_descriptor()],
-        fs_entry: "fs_blit",
-        backface_culling: false,
-        ..Default::default()
-    });
+    let mut datamosh = Datamosh::new(renderer, &storage_texture, DatamoshConfig::default());
 
     let n = 10;
     let instances = (-n..=n)
@@ -207,13 +164,6 @@ fn main() {
         // vec![Instance::default()],
     );
 
-    let fullscreen_quad = Object::quad(
-        renderer,
-        vec![Instance::default()
-            .translate(Vec3::new(-1.0, -1.0, 0.0))
-            .scale(2.0)],
-    );
-
     let mut time = 0.0;
     let mut last_time = std::time::Instant::now();
 
@@ -228,7 +178,6 @@ fn main() {
         Vec3::new(0.4, 0.7, 0.3),
     ];
 
-    let mut refresh = true;
     let mut no_camera = false;
     let mut random_colors = false;
     let mut alpha0 = true;
@@ -244,7 +193,7 @@ fn main() {
                     if file_name == "shader.wgsl" {
                         renderer.reload_shader(shader);
                     } else if file_name == "popr_shader.wgsl" {
-                        renderer.reload_shader(popr_shader)
+                        datamosh.reload_shaders(renderer)
                     }
                 }
             }
@@ -285,23 +234,22 @@ fn main() {
                         depth_texture,
                     );
 
-                    for target_texture in target_textures.iter_mut() {
-                        renderer.re_create_texture(
-                            TextureDescriptor {
-                                name: "target_texture",
-                                size: window.size().into(),
-                                format: TextureFormat::Bgra8U,
-                                usage: texture_usage::GENERIC,
-                                ..Default::default()
-                            },
-                            target_texture.texture_handle,
-                        );
-                        target_texture.re_create(
-                            renderer,
-                            target_texture.texture_handle,
-                            target_texture.sampler_handle,
-                        );
-                    }
+                    renderer.re_create_texture(
+                        TextureDescriptor {
+                            name: "scene_texture",
+                            size: window.size().into(),
+                            format: TextureFormat::Bgra8U,
+                            usage: texture_usage::GENERIC,
+                            ..Default::default()
+                        },
+                        scene_texture.texture_handle,
+                    );
+                    scene_texture.re_create(
+                        renderer,
+                        scene_texture.texture_handle,
+                        scene_texture.sampler_handle,
+                    );
+                    datamosh.resize(renderer, window.size().into());
 
                     renderer.re_create_texture(
                         TextureDescriptor {
@@ -326,7 +274,7 @@ fn main() {
                 } => match key {
                     jandering_engine::window::Key::Key1 => camera.set_position(Vec3::ZERO),
                     jandering_engine::window::Key::Key2 => {
-                        refresh = true;
+                        datamosh.refresh();
                         current_clear_color = (current_clear_color + 1) % clear_colors.len();
                     }
                     jandering_engine::window::Key::Key3 => no_camera = !no_camera,
@@ -379,13 +327,10 @@ fn main() {
 
             let alpha = if alpha0 { 0.0 } else { 1.0 };
 
-            renderer.clear_texture(storage_texture.texture_handle);
             let main_pass = RenderPass::new(&mut window)
                 .set_shader(main_shader)
                 .with_target_texture_resolve(
-                    jandering_engine::renderer::TargetTexture::Handle(
-                        target_textures[0].texture_handle,
-                    ),
+                    jandering_engine::renderer::TargetTexture::Handle(scene_texture.texture_handle),
                     None,
                 )
                 .with_depth(depth_texture, Some(1.0))
@@ -397,38 +342,7 @@ fn main() {
                 .render_one(&object);
             renderer.submit_pass(main_pass);
 
-            if refresh {
-                refresh = false;
-                renderer.blit_textures(
-                    target_textures[0].texture_handle,
-                    target_textures[1].texture_handle,
-                );
-            }
-
-            let popr_pass = RenderPass::new(&mut window)
-                .set_shader(popr_shader)
-                .with_target_texture_resolve(
-                    jandering_engine::renderer::TargetTexture::Handle(
-                        target_textures[2].texture_handle,
-                    ),
-                    None,
-                )
-                .bind(0, target_textures[1].bind_group)
-                .bind(1, storage_texture.bind_group)
-                .render_one(&fullscreen_quad)
-                .set_shader(blit_shader)
-                .with_target_texture_resolve(
-                    jandering_engine::renderer::TargetTexture::Screen,
-                    None,
-                )
-                .bind(0, target_textures[2].bind_group)
-                .render_one(&fullscreen_quad);
-            renderer.submit_pass(popr_pass);
-
-            renderer.blit_textures(
-                target_textures[2].texture_handle,
-                target_textures[1].texture_handle,
-            );
+            datamosh.render(renderer, &mut window, &scene_texture, &storage_texture);
 
             window.request_redraw();
         }